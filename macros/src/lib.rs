@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+use libaheui::{AheuiEnding, run_within};
+use libaheui::component::AheuiProgram;
+
+// maximum number of steps aheui_output! runs before giving up
+const OUTPUT_STEP_LIMIT: usize = 10_000_000;
+
+/// Parse an Aheui program at compile time.
+///
+/// `aheui! { 밤밣따빠... }` and `aheui!("밤밣따빠...")` expand to a precompiled `AheuiProgram`.
+/// `aheui! { pub fn name { 밤밣따빠... } }` expands to a function taking its input as a `&str`
+/// and returning its output as a `Result<String, AheuiRuntimeError>`.
+///
+/// Programs which can never terminate are rejected:
+///
+/// ```compile_fail
+/// let program = libaheui_macros::aheui! { 받반따망 };
+/// ```
+#[proc_macro]
+pub fn aheui(input: TokenStream) -> TokenStream {
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+
+    // split off a function signature, if there is one
+    let fn_index = tokens.iter().position(|token| matches!(token, TokenTree::Ident(ident) if ident.to_string() == "fn"));
+    let (signature, body) = match fn_index {
+        Some(index) => match (tokens.get(index + 1), tokens.get(index + 2), tokens.len()) {
+            (Some(TokenTree::Ident(name)), Some(TokenTree::Group(group)), len)
+                if group.delimiter() == Delimiter::Brace && len == index + 3 => {
+                (Some((&tokens[..index], name.clone())), group.stream())
+            },
+            _ => return compile_error("expected `fn name { ... }`", tokens[index].span()),
+        },
+        None => (None, tokens.into_iter().collect()),
+    };
+
+    let (source, span) = match program_source(body) {
+        Ok(source) => source,
+        Err((message, span)) => return compile_error(&message, span),
+    };
+
+    let program = AheuiProgram::from_str(&source);
+    if let Err(err) = program.validate() {
+        return compile_error(&format!("invalid Aheui program: {}", err), span);
+    }
+
+    let program_expression = precompiled_program(&program);
+    match signature {
+        Some((visibility, name)) => {
+            let visibility: TokenStream = visibility.iter().cloned().collect();
+            format!(
+                "{} fn {}(input: &str) -> ::std::result::Result<::std::string::String, ::libaheui::component::AheuiRuntimeError> {{ ::libaheui::run_to_string({}, input) }}",
+                visibility, name, program_expression,
+            ).parse().unwrap()
+        },
+        None => program_expression.parse().unwrap(),
+    }
+}
+
+/// Run an Aheui program without input at compile time, expanding to its output as a `&'static str`.
+///
+/// Programs which fail, for example by reading input, are rejected:
+///
+/// ```compile_fail
+/// const OUTPUT: &str = libaheui_macros::aheui_output! { 방망희 };
+/// ```
+#[proc_macro]
+pub fn aheui_output(input: TokenStream) -> TokenStream {
+    let (source, span) = match program_source(input) {
+        Ok(source) => source,
+        Err((message, span)) => return compile_error(&message, span),
+    };
+
+    let program = AheuiProgram::from_str(&source);
+    if let Err(err) = program.validate() {
+        return compile_error(&format!("invalid Aheui program: {}", err), span);
+    }
+
+    let outcome = run_within(program, "", OUTPUT_STEP_LIMIT);
+    let text = match outcome.ending {
+        AheuiEnding::Terminated => outcome.output,
+        AheuiEnding::Failed(err) => return compile_error(&format!("Aheui program failed: {}", err), span),
+        AheuiEnding::OutOfSteps => {
+            return compile_error(&format!("Aheui program did not terminate within {} steps", OUTPUT_STEP_LIMIT), span);
+        },
+    };
+    TokenTree::Literal(Literal::string(&text)).into()
+}
+
+// expand to compile_error!("message") at span
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut literal = Literal::string(message);
+    literal.set_span(span);
+    let mut group = Group::new(Delimiter::Brace, TokenTree::Literal(literal).into());
+    group.set_span(span);
+
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+
+    [
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(bang),
+        TokenTree::Group(group),
+    ].into_iter().collect()
+}
+
+// get the program's source and the span to report errors at,
+// either from a single string literal or from the layout of the tokens
+fn program_source(input: TokenStream) -> Result<(String, Span), (String, Span)> {
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+    let span = match tokens.first() {
+        Some(token) => token.span(),
+        None => return Err(("expected an Aheui program".to_string(), Span::call_site())),
+    };
+
+    if let [TokenTree::Literal(literal)] = tokens.as_slice() {
+        if let Some(source) = string_literal_value(&literal.to_string()) {
+            return Ok((source, span));
+        }
+    }
+
+    // place every token's text at its line and column,
+    // keeping the spaces between tokens on the same line
+    let mut lines: BTreeMap<usize, Vec<(usize, String)>> = BTreeMap::new();
+    collect_tokens(tokens, &mut lines);
+
+    let mut source = String::new();
+    for tokens in lines.values_mut() {
+        tokens.sort_by_key(|(column, _)| *column);
+
+        let mut end = tokens.first().map(|(column, _)| *column).unwrap_or(0);
+        for (column, text) in tokens.iter() {
+            for _ in end..*column {
+                source.push(' ');
+            }
+            source.push_str(text);
+            end = column.max(&end) + text.chars().count();
+        }
+        source.push('\n');
+    }
+
+    Ok((source, span))
+}
+
+fn collect_tokens(tokens: impl IntoIterator<Item = TokenTree>, lines: &mut BTreeMap<usize, Vec<(usize, String)>>) {
+    for token in tokens {
+        match token {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                add_token(lines, group.span_open(), open.to_string());
+                collect_tokens(group.stream(), lines);
+                add_token(lines, group.span_close(), close.to_string());
+            },
+            token => {
+                let text = token.span().source_text().unwrap_or_else(|| token.to_string());
+                add_token(lines, token.span(), text);
+            },
+        };
+    }
+}
+
+fn add_token(lines: &mut BTreeMap<usize, Vec<(usize, String)>>, span: Span, text: String) {
+    if !text.is_empty() {
+        lines.entry(span.line()).or_default().push((span.column(), text));
+    }
+}
+
+// get the value of a string literal's source, like "..." or r#"..."#
+fn string_literal_value(literal: &str) -> Option<String> {
+    if let Some(raw) = literal.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let raw = &raw[hashes..raw.len() - hashes];
+        return raw.strip_prefix('"')?.strip_suffix('"').map(|s| s.to_string());
+    }
+
+    let inner = literal.strip_prefix('"')?.strip_suffix('"')?;
+    let mut value = String::new();
+    let mut chars = inner.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => value.push('\n'),
+            'r' => value.push('\r'),
+            't' => value.push('\t'),
+            '0' => value.push('\0'),
+            '\\' => value.push('\\'),
+            '\'' => value.push('\''),
+            '"' => value.push('"'),
+            'u' => {
+                let code: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+                value.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+            },
+            // line continuation skips the newline and leading whitespace
+            '\n' => {
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            },
+            _ => return None,
+        };
+    }
+
+    Some(value)
+}
+
+// build an expression constructing the program from its decoded instructions
+fn precompiled_program(program: &AheuiProgram) -> String {
+    let mut rows = Vec::new();
+    for row in program.program.iter() {
+        let instructions: Vec<String> = row
+            .iter()
+            .map(|instruction| format!(
+                "I {{ operation: O::{:?}, direction: D::{:?}, argument: A::{:?}, character: {:?} }}",
+                instruction.operation, instruction.direction, instruction.argument, instruction.character,
+            ))
+            .collect();
+        rows.push(format!("::std::vec![{}]", instructions.join(", ")));
+    }
+
+    format!(
+        "{{ use ::libaheui::instruction::{{AheuiInstruction as I, AheuiOperation as O, AheuiDirection as D, AheuiArgument as A}}; \
+        ::libaheui::component::AheuiProgram::from_rows(::std::vec![{}]) }}",
+        rows.join(", "),
+    )
+}
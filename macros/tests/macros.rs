@@ -0,0 +1,58 @@
+use libaheui::component::AheuiProgram;
+use libaheui::run_to_string;
+use libaheui_macros::{aheui, aheui_output};
+
+#[test]
+fn test_precompiled_program() {
+    let program: AheuiProgram = aheui! { 받반따망희 };
+
+    assert_eq!(program.size.x, 5);
+    assert_eq!(program.size.y, 1);
+    assert_eq!(program.program[0][2].character, '따');
+    assert_eq!(run_to_string(program, "").unwrap(), "6\n");
+}
+
+#[test]
+fn test_string_program() {
+    let program = aheui!("받반따뭉\n희어어어");
+
+    assert_eq!(run_to_string(program, "").unwrap(), "6\n");
+}
+
+#[test]
+fn test_token_layout() {
+    // rows are kept apart, as are cells separated by spaces
+    let program = aheui! {
+        받반따뭉
+        희어 어어
+    };
+
+    assert_eq!(program.size.y, 2);
+    assert_eq!(program.program[1][2].character, ' ');
+    assert_eq!(run_to_string(program, "").unwrap(), "6\n");
+}
+
+aheui! {
+    fn add_numbers {
+        방방다망희
+    }
+}
+
+aheui! {
+    pub(crate) fn multiply_numbers { 방방따망희 }
+}
+
+#[test]
+fn test_program_function() {
+    assert_eq!(add_numbers("3\n4\n").unwrap(), "7\n");
+    assert_eq!(multiply_numbers("3\n4\n").unwrap(), "12\n");
+    assert!(add_numbers("3\n").is_err());
+}
+
+#[test]
+fn test_compile_time_output() {
+    const OUTPUT: &str = aheui_output! { 받반따망희 };
+
+    assert_eq!(OUTPUT, "6\n");
+    assert_eq!(aheui_output!("밝밝따망희"), "49\n");
+}
@@ -0,0 +1,392 @@
+use std::fmt;
+use crate::instruction::*;
+use crate::component::*;
+use crate::flow::*;
+
+// bounds on storage depth above this are treated as unbounded
+const DEPTH_LIMIT: usize = 256;
+// number of values tracked at the end of each storage values are popped from
+const VALUE_LIMIT: usize = 8;
+
+// bounds on the depth of a storage, with None as an unbounded maximum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AheuiDepth {
+    pub low: usize,
+    pub high: Option<usize>,
+}
+
+impl AheuiDepth {
+    pub fn exactly(depth: usize) -> Self {
+        Self { low: depth, high: Some(depth) }
+    }
+
+    // smallest bounds containing both
+    fn hull(self, other: AheuiDepth) -> Self {
+        let high = match (self.high, other.high) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            _ => None,
+        };
+        Self { low: self.low.min(other.low), high }
+    }
+
+    fn push(self) -> Self {
+        Self {
+            low: self.low + 1,
+            high: self.high.map(|high| high + 1).filter(|high| *high <= DEPTH_LIMIT),
+        }
+    }
+
+    fn pop(self) -> Self {
+        Self {
+            low: self.low.saturating_sub(1),
+            high: self.high.map(|high| high.saturating_sub(1)),
+        }
+    }
+}
+
+impl fmt::Display for AheuiDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.high {
+            Some(high) if high == self.low => write!(f, "{}", high),
+            Some(high) => write!(f, "{}..{}", self.low, high),
+            None => write!(f, "{}..", self.low),
+        }
+    }
+}
+
+// what is known about a storage: its depth, and the values
+// at the end they are popped from, first popped first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AheuiStorageBounds {
+    pub depth: AheuiDepth,
+    pub values: Vec<Option<isize>>,
+    queue: bool,
+}
+
+impl AheuiStorageBounds {
+    fn empty(queue: bool) -> Self {
+        Self { depth: AheuiDepth::exactly(0), values: Vec::new(), queue }
+    }
+
+    fn hull(&self, other: &AheuiStorageBounds) -> Self {
+        let values = self.values
+            .iter()
+            .zip(other.values.iter())
+            .map(|(a, b)| if a == b { *a } else { None })
+            .collect();
+        Self { depth: self.depth.hull(other.depth), values, queue: self.queue }
+    }
+
+    // push a value to the top of a stack or the back of a queue,
+    // which is only known if every value of the queue is
+    fn push(&mut self, value: Option<isize>) {
+        if !self.queue {
+            self.push_front(value);
+            return;
+        }
+        if self.depth.high == Some(self.values.len()) && self.values.len() < VALUE_LIMIT {
+            self.values.push(value);
+        }
+        self.depth = self.depth.push();
+    }
+
+    // push a value to the end values are popped from
+    fn push_front(&mut self, value: Option<isize>) {
+        self.values.insert(0, value);
+        self.values.truncate(VALUE_LIMIT);
+        self.depth = self.depth.push();
+    }
+
+    fn pop(&mut self) -> Option<isize> {
+        self.depth = self.depth.pop();
+        if self.values.is_empty() {
+            return None;
+        }
+        self.values.remove(0)
+    }
+
+    // the value that would be popped next, if it is known
+    pub fn top(&self) -> Option<isize> {
+        self.values.first().copied().flatten()
+    }
+}
+
+// what is known about the storages when an instruction is reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AheuiAbstractState {
+    // selected storage, None if it depends on the path taken
+    pub storage_index: Option<usize>,
+    pub storages: Vec<AheuiStorageBounds>,
+}
+
+impl AheuiAbstractState {
+    // state at the start of every program
+    pub fn start() -> Self {
+        Self {
+            storage_index: Some(0),
+            storages: (0..28).map(|i| AheuiStorageBounds::empty(i == 21 || i == 27)).collect(),
+        }
+    }
+
+    fn join(&mut self, other: &AheuiAbstractState) {
+        if self.storage_index != other.storage_index {
+            self.storage_index = None;
+        }
+        for (storage, other) in self.storages.iter_mut().zip(other.storages.iter()) {
+            *storage = storage.hull(other);
+        }
+    }
+
+    // bounds on the selected storage, if it is known
+    pub fn selected(&self) -> Option<&AheuiStorageBounds> {
+        self.storage_index.map(|index| &self.storages[index])
+    }
+
+    // any storage may have gained or lost a value
+    fn forget(&mut self) {
+        for storage in self.storages.iter_mut() {
+            storage.depth = storage.depth.hull(storage.depth.push()).hull(storage.depth.pop());
+            storage.values.clear();
+        }
+    }
+}
+
+impl fmt::Display for AheuiAbstractState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (index, storage) = match (self.storage_index, self.selected()) {
+            (Some(index), Some(storage)) => (index, storage),
+            _ => return f.write_str("unknown storage"),
+        };
+        let values: Vec<String> = storage.values
+            .iter()
+            .map(|value| value.map_or("?".to_string(), |value| value.to_string()))
+            .collect();
+        write!(f, "{} depth {} [{}]", storage_name(index), storage.depth, values.join(", "))
+    }
+}
+
+// whether an instruction pops a value before reflecting
+// forks reflect after popping zero, and discarding pops always do
+fn pops_before_reflecting(instruction: &AheuiInstruction) -> bool {
+    instruction.operation == AheuiOperation::Fork
+        || (instruction.operation == AheuiOperation::Pop && instruction.argument == AheuiArgument::Null)
+}
+
+// states after an instruction succeeds and after it reflects, if they are possible
+pub fn transfer(state: &AheuiAbstractState, instruction: &AheuiInstruction) -> (Option<AheuiAbstractState>, Option<AheuiAbstractState>) {
+    let index = match state.storage_index {
+        Some(index) => index,
+        None => {
+            // any storage may be changed, and only the selection of a storage is certain
+            let mut success = state.clone();
+            let mut failure = state.clone();
+            match (instruction.operation, instruction.argument) {
+                (AheuiOperation::StoreSelect, AheuiArgument::Storage(n)) => success.storage_index = Some(n),
+                (AheuiOperation::Null, _) => {},
+                _ => {
+                    success.forget();
+                    failure.forget();
+                },
+            };
+            return (
+                Some(success).filter(|_| instruction.can_succeed()),
+                Some(failure).filter(|_| instruction.can_fail()),
+            );
+        },
+    };
+
+    let required = instruction.operation.required_depth();
+    let storage = &state.storages[index];
+    let enough = storage.depth.high.is_none_or(|high| high >= required);
+    // forks go by the value they pop, when it is known
+    let (nonzero, zero) = match (instruction.operation, storage.top()) {
+        (AheuiOperation::Fork, Some(0)) => (false, true),
+        (AheuiOperation::Fork, Some(_)) => (true, false),
+        _ => (true, true),
+    };
+
+    let mut success = None;
+    if instruction.can_succeed() && enough && nonzero {
+        let mut next = state.clone();
+        let storage = &mut next.storages[index];
+        storage.depth.low = storage.depth.low.max(required);
+        match instruction.operation {
+            AheuiOperation::Push => match instruction.argument {
+                AheuiArgument::Number(n) => storage.push(Some(n as isize)),
+                _ => storage.push(None),
+            },
+            AheuiOperation::Duplicate => {
+                let value = storage.top();
+                storage.push_front(value);
+            },
+            AheuiOperation::Swap => {
+                storage.values.resize(storage.values.len().max(2).min(storage.depth.low), None);
+                storage.values.swap(0, 1);
+            },
+            AheuiOperation::StoreSelect => {
+                if let AheuiArgument::Storage(n) = instruction.argument {
+                    next.storage_index = Some(n);
+                }
+            },
+            AheuiOperation::StoreTransfer => {
+                let value = storage.pop();
+                if let AheuiArgument::Storage(n) = instruction.argument {
+                    next.storages[n].push(value);
+                }
+            },
+            AheuiOperation::Add |
+            AheuiOperation::Multiply |
+            AheuiOperation::Divide |
+            AheuiOperation::Subtract |
+            AheuiOperation::Modulo => {
+                let a = storage.pop();
+                let b = storage.pop();
+                let value = match (a, b) {
+                    (Some(a), Some(b)) => instruction.operation.arithmetic_operation(b, a),
+                    _ => None,
+                };
+                storage.push(value);
+            },
+            AheuiOperation::Compare => {
+                let a = storage.pop();
+                let b = storage.pop();
+                let value = match (a, b) {
+                    (Some(a), Some(b)) => Some((a <= b) as isize),
+                    _ => None,
+                };
+                storage.push(value);
+            },
+            AheuiOperation::Pop | AheuiOperation::Fork => {
+                storage.pop();
+            },
+            _ => {},
+        };
+        success = Some(next);
+    }
+
+    // anything else reflects when it has fewer values than it needs
+    let mut failure = None;
+    if pops_before_reflecting(instruction) {
+        if zero {
+            let mut next = state.clone();
+            next.storages[index].pop();
+            failure = Some(next);
+        }
+    } else if instruction.can_fail() && storage.depth.low < required {
+        let mut next = state.clone();
+        let depth = &mut next.storages[index].depth;
+        depth.high = Some(depth.high.map_or(required - 1, |high| high.min(required - 1)));
+        failure = Some(next);
+    }
+
+    (success, failure)
+}
+
+// bounds on the storages at every state of a program's control flow graph
+#[derive(Debug)]
+pub struct AheuiAnalysis {
+    pub graph: AheuiFlowGraph,
+    // state of each node of the graph, None if it can never be reached
+    pub states: Vec<Option<AheuiAbstractState>>,
+}
+
+impl AheuiAnalysis {
+    pub fn from_program(program: &AheuiProgram) -> Self {
+        let graph = AheuiFlowGraph::from_program(program);
+        let mut states: Vec<Option<AheuiAbstractState>> = vec![None; graph.nodes.len()];
+        if graph.nodes.is_empty() {
+            return Self { graph, states };
+        }
+
+        // join the states reaching every node until none change
+        states[0] = Some(AheuiAbstractState::start());
+        let mut pending = vec![0];
+        while let Some(index) = pending.pop() {
+            let node = &graph.nodes[index];
+            let (success, failure) = transfer(states[index].as_ref().unwrap(), &node.instruction);
+            for (next, state) in [(node.success, success), (node.failure, failure)] {
+                let (next, state) = match (next, state) {
+                    (Some(next), Some(state)) => (next, state),
+                    _ => continue,
+                };
+                let joined = match &states[next] {
+                    Some(previous) => {
+                        let mut joined = previous.clone();
+                        joined.join(&state);
+                        joined
+                    },
+                    None => state,
+                };
+                if states[next].as_ref() != Some(&joined) {
+                    states[next] = Some(joined);
+                    pending.push(next);
+                }
+            }
+        }
+
+        Self { graph, states }
+    }
+
+    // state of a node, if it can be reached
+    pub fn state(&self, position: AheuiCoordinates, direction: AheuiDirection) -> Option<&AheuiAbstractState> {
+        self.states[self.graph.node_index(position, direction)?].as_ref()
+    }
+
+    // nodes a node can go to from the state it is reached with
+    pub fn successors(&self, index: usize) -> Vec<usize> {
+        let state = match &self.states[index] {
+            Some(state) => state,
+            None => return Vec::new(),
+        };
+        let node = &self.graph.nodes[index];
+        let (success, failure) = transfer(state, &node.instruction);
+        [(node.success, success.is_some()), (node.failure, failure.is_some())]
+            .into_iter()
+            .filter_map(|(next, possible)| next.filter(|_| possible))
+            .collect()
+    }
+
+    // whether a node may reflect because its storage lacks values
+    pub fn may_underflow(&self, index: usize) -> bool {
+        let instruction = &self.graph.nodes[index].instruction;
+        let required = instruction.operation.required_depth();
+        match &self.states[index] {
+            Some(state) => required > 0 && state.selected().is_none_or(|storage| storage.depth.low < required),
+            None => false,
+        }
+    }
+
+    // whether a node reflects because its storage lacks values whenever it is reached
+    pub fn always_underflows(&self, index: usize) -> bool {
+        let instruction = &self.graph.nodes[index].instruction;
+        let required = instruction.operation.required_depth();
+        match self.states[index].as_ref().and_then(|state| state.selected()) {
+            Some(storage) => storage.depth.high.is_some_and(|high| high < required),
+            None => false,
+        }
+    }
+
+    // whether no instruction can ever reflect because its storage lacks values
+    pub fn never_underflows(&self) -> bool {
+        (0..self.graph.nodes.len()).all(|index| !self.may_underflow(index))
+    }
+
+    // whether a node divides by a value known to be zero
+    pub fn divides_by_zero(&self, index: usize) -> bool {
+        let operation = self.graph.nodes[index].instruction.operation;
+        let top = self.states[index].as_ref().and_then(|state| state.selected()).and_then(|storage| storage.top());
+        matches!(operation, AheuiOperation::Divide | AheuiOperation::Modulo) && top == Some(0)
+    }
+
+    // the selected storage before and after a node succeeds, like "ㅇ depth 2 [3, 4] -> ㅇ depth 1 [7]"
+    pub fn stack_effect(&self, index: usize) -> Option<String> {
+        let state = self.states[index].as_ref()?;
+        let instruction = &self.graph.nodes[index].instruction;
+        let after = match transfer(state, instruction).0 {
+            Some(after) => after.to_string(),
+            None if instruction.operation == AheuiOperation::Terminate => "halts".to_string(),
+            None => "reflects".to_string(),
+        };
+        Some(format!("{} -> {}", state, after))
+    }
+}
@@ -0,0 +1,503 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use crate::hangul::*;
+use crate::instruction::*;
+use crate::component::*;
+use crate::flow::*;
+use crate::generate::AheuiConstantSearch;
+
+
+// reasons assembly source is rejected, each with its line number
+#[derive(Debug, PartialEq, Eq)]
+pub enum AheuiAsmError {
+    UnknownMnemonic(usize, String),
+    InvalidOperand(usize, String),
+    DuplicateLabel(usize, String),
+    UndefinedLabel(usize, String),
+}
+
+impl fmt::Display for AheuiAsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AheuiAsmError::UnknownMnemonic(line, mnemonic) => write!(f, "line {}: unknown mnemonic {}", line, mnemonic),
+            AheuiAsmError::InvalidOperand(line, operand) => write!(f, "line {}: invalid operand {}", line, operand),
+            AheuiAsmError::DuplicateLabel(line, label) => write!(f, "line {}: duplicate label {}", line, label),
+            AheuiAsmError::UndefinedLabel(line, label) => write!(f, "line {}: undefined label {}", line, label),
+        }
+    }
+}
+
+// assembly mnemonic of an instruction, without its reflection target
+// storages are named by their jamo, with no operand for the empty coda
+pub fn mnemonic(instruction: &AheuiInstruction) -> String {
+    let storage = |argument: AheuiArgument| match argument {
+        AheuiArgument::Storage(n) if n > 0 => format!(" {}", CODA_JAMO[n]),
+        _ => String::new(),
+    };
+    let kind = |argument: AheuiArgument| match argument {
+        AheuiArgument::AsInt => " int".to_string(),
+        AheuiArgument::AsChar => " char".to_string(),
+        AheuiArgument::Number(n) => format!(" {}", n),
+        _ => String::new(),
+    };
+
+    match instruction.operation {
+        AheuiOperation::Null => "nop".to_string(),
+        AheuiOperation::Terminate => "halt".to_string(),
+        AheuiOperation::Add => "add".to_string(),
+        AheuiOperation::Multiply => "mul".to_string(),
+        AheuiOperation::Divide => "div".to_string(),
+        AheuiOperation::Subtract => "sub".to_string(),
+        AheuiOperation::Modulo => "mod".to_string(),
+        AheuiOperation::Pop => format!("pop{}", kind(instruction.argument)),
+        AheuiOperation::Push => format!("push{}", kind(instruction.argument)),
+        AheuiOperation::Duplicate => "dup".to_string(),
+        AheuiOperation::Swap => "swap".to_string(),
+        AheuiOperation::StoreSelect => format!("sel{}", storage(instruction.argument)),
+        AheuiOperation::StoreTransfer => format!("mov{}", storage(instruction.argument)),
+        AheuiOperation::Compare => "cmp".to_string(),
+        AheuiOperation::Fork => "brz".to_string(),
+    }
+}
+
+// line of linear assembly, either an instruction's node or a jump to one
+enum Line {
+    Node(usize),
+    Jump(usize),
+}
+
+// turn a program into linear assembly by following its control flow graph
+// instructions which only move the cursor are left out, and every
+// instruction that can reflect names where it goes when it does
+pub fn disassemble(program: &AheuiProgram) -> String {
+    let graph = AheuiFlowGraph::from_program(program);
+    if graph.nodes.is_empty() {
+        return String::new();
+    }
+
+    // skip over null instructions, stopping if they loop forever
+    let resolve = |mut index: usize| {
+        let mut seen = HashSet::new();
+        while graph.nodes[index].instruction.operation == AheuiOperation::Null && seen.insert(index) {
+            index = graph.nodes[index].success.unwrap();
+        }
+        index
+    };
+
+    // lay out successful paths as straight lines, jumping back
+    // to instructions already placed, and reflections after them
+    let mut lines = Vec::new();
+    let mut placed = HashSet::new();
+    let mut targets = HashSet::new();
+    let mut pending = VecDeque::from([resolve(0)]);
+    while let Some(mut index) = pending.pop_front() {
+        if placed.contains(&index) {
+            continue;
+        }
+        loop {
+            if !placed.insert(index) {
+                targets.insert(index);
+                lines.push(Line::Jump(index));
+                break;
+            }
+            lines.push(Line::Node(index));
+
+            let node = &graph.nodes[index];
+            if let Some(failure) = node.failure {
+                let failure = resolve(failure);
+                targets.insert(failure);
+                pending.push_back(failure);
+            }
+            match node.success {
+                Some(success) => index = resolve(success),
+                None => break,
+            };
+        }
+    }
+
+    // number labels in the order they appear
+    let mut labels = HashMap::new();
+    for line in lines.iter() {
+        if let Line::Node(index) = line {
+            if targets.contains(index) {
+                labels.insert(*index, format!("L{}", labels.len() + 1));
+            }
+        }
+    }
+
+    let mut source = String::new();
+    for line in lines {
+        match line {
+            Line::Node(index) => {
+                let node = &graph.nodes[index];
+                if let Some(label) = labels.get(&index) {
+                    source.push_str(&format!("{}:\n", label));
+                }
+
+                let mut text = mnemonic(&node.instruction);
+                if let Some(failure) = node.failure {
+                    let label = &labels[&resolve(failure)];
+                    match node.instruction.operation {
+                        AheuiOperation::Fork => text.push_str(&format!(" {}", label)),
+                        _ => text.push_str(&format!(" else {}", label)),
+                    };
+                }
+                source.push_str(&format!(
+                    "    {:<20} ; ({}, {}) {}\n",
+                    text, node.position.x, node.position.y, node.instruction.character,
+                ));
+            },
+            Line::Jump(index) => source.push_str(&format!("    jmp {}\n", labels[&index])),
+        };
+    }
+
+    source
+}
+
+// statement of assembly source
+enum Statement {
+    Label(String),
+    // operation and argument, with the label to go to when it reflects
+    Instruction(AheuiOperation, AheuiArgument, Option<String>),
+    Jump(String),
+}
+
+// parse a storage operand, either its jamo or its index
+fn parse_storage(line: usize, operand: Option<&str>) -> Result<AheuiArgument, AheuiAsmError> {
+    let operand = match operand {
+        Some(operand) => operand,
+        None => return Ok(AheuiArgument::Storage(0)),
+    };
+    CODA_JAMO
+        .iter()
+        .skip(1)
+        .position(|jamo| *jamo == operand)
+        .map(|n| n + 1)
+        .or_else(|| operand.parse().ok().filter(|n| *n < CODA_JAMO.len()))
+        .map(AheuiArgument::Storage)
+        .ok_or_else(|| AheuiAsmError::InvalidOperand(line, operand.to_string()))
+}
+
+fn parse_statements(line: usize, text: &str, statements: &mut Vec<(usize, Statement)>) -> Result<(), AheuiAsmError> {
+    // ignore comments
+    let text = text.split(';').next().unwrap();
+    let mut tokens: Vec<&str> = text.split_whitespace().collect();
+
+    // any number of labels may come before an instruction
+    while let Some(label) = tokens.first().and_then(|token| token.strip_suffix(':')) {
+        statements.push((line, Statement::Label(label.to_string())));
+        tokens.remove(0);
+    }
+    let (mnemonic, mut operands) = match tokens.split_first() {
+        Some((mnemonic, operands)) => (*mnemonic, operands.to_vec()),
+        None => return Ok(()),
+    };
+
+    // split off where to go when the instruction reflects
+    let mut failure = None;
+    if let Some(index) = operands.iter().position(|operand| *operand == "else") {
+        match &operands[index + 1..] {
+            [label] => failure = Some(label.to_string()),
+            _ => return Err(AheuiAsmError::InvalidOperand(line, operands[index..].join(" "))),
+        };
+        operands.truncate(index);
+    }
+
+    let invalid = |operands: &[&str]| AheuiAsmError::InvalidOperand(line, operands.join(" "));
+    let (operation, argument) = match (mnemonic, operands.as_slice()) {
+        ("jmp", [label]) if failure.is_none() => {
+            statements.push((line, Statement::Jump(label.to_string())));
+            return Ok(());
+        },
+        ("brz", [label]) if failure.is_none() => {
+            failure = Some(label.to_string());
+            (AheuiOperation::Fork, AheuiArgument::Null)
+        },
+        ("jmp" | "brz", operands) => return Err(invalid(operands)),
+        ("nop", []) => (AheuiOperation::Null, AheuiArgument::Null),
+        ("halt", []) => (AheuiOperation::Terminate, AheuiArgument::Null),
+        ("add", []) => (AheuiOperation::Add, AheuiArgument::Null),
+        ("mul", []) => (AheuiOperation::Multiply, AheuiArgument::Null),
+        ("div", []) => (AheuiOperation::Divide, AheuiArgument::Null),
+        ("sub", []) => (AheuiOperation::Subtract, AheuiArgument::Null),
+        ("mod", []) => (AheuiOperation::Modulo, AheuiArgument::Null),
+        ("dup", []) => (AheuiOperation::Duplicate, AheuiArgument::Null),
+        ("swap", []) => (AheuiOperation::Swap, AheuiArgument::Null),
+        ("cmp", []) => (AheuiOperation::Compare, AheuiArgument::Null),
+        ("pop", []) => (AheuiOperation::Pop, AheuiArgument::Null),
+        ("pop", ["int"]) => (AheuiOperation::Pop, AheuiArgument::AsInt),
+        ("pop", ["char"]) => (AheuiOperation::Pop, AheuiArgument::AsChar),
+        ("push", ["int"]) => (AheuiOperation::Push, AheuiArgument::AsInt),
+        ("push", ["char"]) => (AheuiOperation::Push, AheuiArgument::AsChar),
+        ("push", [number]) => match number.parse() {
+            Ok(n) if n == 0 || (2..=9).contains(&n) => (AheuiOperation::Push, AheuiArgument::Number(n)),
+            _ => return Err(invalid(&[number])),
+        },
+        ("sel", []) | ("mov", []) | ("sel", [_]) | ("mov", [_]) => {
+            let operation = match mnemonic {
+                "sel" => AheuiOperation::StoreSelect,
+                _ => AheuiOperation::StoreTransfer,
+            };
+            (operation, parse_storage(line, operands.first().copied())?)
+        },
+        ("nop" | "halt" | "add" | "mul" | "div" | "sub" | "mod" | "dup" | "swap" | "cmp" | "pop" | "push" | "sel" | "mov", operands) => {
+            return Err(invalid(operands));
+        },
+        (mnemonic, _) => return Err(AheuiAsmError::UnknownMnemonic(line, mnemonic.to_string())),
+    };
+
+    // only instructions which can reflect may say where to go
+    let instruction = AheuiInstruction {
+        operation,
+        direction: AheuiDirection::Null,
+        argument,
+        character: ' ',
+    };
+    if let Some(label) = &failure {
+        if !instruction.can_fail() {
+            return Err(AheuiAsmError::InvalidOperand(line, format!("else {}", label)));
+        }
+    }
+
+    statements.push((line, Statement::Instruction(operation, argument, failure)));
+    Ok(())
+}
+
+// how control leaves a block, with the line of the statement it comes from
+// if there is one
+enum Exit {
+    Halt(Option<usize>),
+    Jump(Option<usize>, usize),
+    // instruction which continues to the first block and reflects to the second
+    Branch(usize, AheuiOperation, AheuiArgument, usize, usize),
+}
+
+// straight line of instructions, each with its line
+type Body = Vec<(usize, AheuiOperation, AheuiArgument)>;
+
+// body of a block ending with an exit
+struct Block {
+    body: Body,
+    exit: Exit,
+}
+
+// exit of a block before its labels are resolved
+enum PendingExit {
+    Halt(Option<usize>),
+    Fallthrough,
+    Jump(usize, String),
+    Branch(AheuiOperation, AheuiArgument, usize, String),
+}
+
+// canonical syllable for an operation, argument and direction
+fn cell(operation: AheuiOperation, argument: AheuiArgument, direction: AheuiDirection) -> char {
+    AheuiInstruction::encode(operation, direction, argument)[0]
+}
+
+// cell which only moves the cursor
+fn turn(direction: AheuiDirection) -> char {
+    cell(AheuiOperation::Null, AheuiArgument::Null, direction)
+}
+
+// lay linear assembly out onto a grid
+//
+// block i takes rows 4i to 4i+3, with its instructions running right along row 4i+1
+// from an entry cell in column 0, and gets its own columns past the longest block
+// for its exit: jumps and the successful path of a branch go down row 4i+2 and
+// reflections up row 4i, then travel down their column, wrapping around the grid,
+// to the target's row 4j+3, which leads left and back up to its entry cell
+pub fn assemble(source: &str) -> Result<String, AheuiAsmError> {
+    assemble_mapped(source).map(|(grid, _)| grid)
+}
+
+// lay linear assembly out onto a grid, along with the line of the statement
+// each cell comes from, for every cell apart from those routing between blocks
+pub fn assemble_mapped(source: &str) -> Result<(String, Vec<(AheuiCoordinates, usize)>), AheuiAsmError> {
+    let mut statements = Vec::new();
+    for (line, text) in source.lines().enumerate() {
+        parse_statements(line + 1, text, &mut statements)?;
+    }
+
+    // split the statements into blocks, ending one at every label and exit
+    let mut labels = HashMap::new();
+    let mut blocks: Vec<(Body, PendingExit)> = Vec::new();
+    let mut body = Vec::new();
+    let mut open = false;
+    for (line, statement) in statements {
+        match statement {
+            Statement::Label(label) => {
+                if !body.is_empty() {
+                    blocks.push((std::mem::take(&mut body), PendingExit::Fallthrough));
+                }
+                if labels.insert(label.clone(), blocks.len()).is_some() {
+                    return Err(AheuiAsmError::DuplicateLabel(line, label));
+                }
+                open = true;
+                continue;
+            },
+            Statement::Instruction(AheuiOperation::Terminate, _, _) => {
+                blocks.push((std::mem::take(&mut body), PendingExit::Halt(Some(line))));
+            },
+            Statement::Instruction(operation, argument, Some(label)) => {
+                blocks.push((std::mem::take(&mut body), PendingExit::Branch(operation, argument, line, label)));
+            },
+            Statement::Instruction(operation, argument, None) => {
+                body.push((line, operation, argument));
+                open = true;
+                continue;
+            },
+            Statement::Jump(label) => {
+                blocks.push((std::mem::take(&mut body), PendingExit::Jump(line, label)));
+            },
+        };
+        open = false;
+    }
+    if open || blocks.is_empty() {
+        blocks.push((body, PendingExit::Fallthrough));
+    }
+
+    // falling off the end of the last block halts
+    let falls_off = matches!(blocks.last(), Some((_, PendingExit::Fallthrough | PendingExit::Branch(..))));
+    if falls_off {
+        blocks.push((Vec::new(), PendingExit::Halt(None)));
+    }
+
+    let target = |line: usize, label: String| {
+        labels.get(&label).copied().ok_or(AheuiAsmError::UndefinedLabel(line, label))
+    };
+    let mut resolved = Vec::new();
+    for (i, (body, exit)) in blocks.into_iter().enumerate() {
+        let exit = match exit {
+            PendingExit::Halt(line) => Exit::Halt(line),
+            PendingExit::Fallthrough => Exit::Jump(None, i + 1),
+            PendingExit::Jump(line, label) => Exit::Jump(Some(line), target(line, label)?),
+            PendingExit::Branch(operation, argument, line, label) => {
+                Exit::Branch(line, operation, argument, i + 1, target(line, label)?)
+            },
+        };
+        resolved.push(Block { body, exit });
+    }
+
+    Ok(layout(&resolved))
+}
+
+// linear assembly generated by a compiler, each statement with the line of
+// source it comes from, for languages translated to aheui through assembly
+pub struct AheuiAsmBuilder {
+    pub lines: Vec<(String, usize)>,
+    labels: usize,
+    constants: AheuiConstantSearch,
+}
+
+impl Default for AheuiAsmBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AheuiAsmBuilder {
+    pub fn new() -> Self {
+        Self {
+            lines: Vec::new(),
+            labels: 0,
+            constants: AheuiConstantSearch::new(),
+        }
+    }
+
+    // add a statement, either an instruction or a label followed by a colon
+    pub fn emit(&mut self, line: usize, text: impl Into<String>) {
+        self.lines.push((text.into(), line));
+    }
+
+    // add the shortest instructions found which push a constant
+    pub fn constant(&mut self, line: usize, n: isize) {
+        for instruction in self.constants.find(n).instructions {
+            self.emit(line, mnemonic(&instruction));
+        }
+    }
+
+    // a label which has not been given out before
+    pub fn label(&mut self) -> String {
+        self.labels += 1;
+        format!("L{}", self.labels)
+    }
+
+    // the assembly one statement per line, with instructions indented under labels
+    pub fn source(&self) -> String {
+        self.lines
+            .iter()
+            .map(|(text, _)| match text.ends_with(':') {
+                true => format!("{}\n", text),
+                false => format!("    {}\n", text),
+            })
+            .collect()
+    }
+
+    // lay the assembly out onto a grid, along with the line of source each
+    // cell comes from, for every cell apart from those routing between blocks
+    pub fn assemble(&self) -> Result<(String, Vec<(AheuiCoordinates, usize)>), AheuiAsmError> {
+        let (grid, cells) = assemble_mapped(&self.source())?;
+        let cells = cells
+            .into_iter()
+            .map(|(position, line)| (position, self.lines[line - 1].1))
+            .collect();
+        Ok((grid, cells))
+    }
+}
+
+fn layout(blocks: &[Block]) -> (String, Vec<(AheuiCoordinates, usize)>) {
+    let exits = blocks.iter().map(|block| block.body.len() + 2).max().unwrap();
+    let mut grid = vec![vec![' '; exits + 3 * blocks.len()]; 4 * blocks.len()];
+    let mut lines = Vec::new();
+
+    for (i, block) in blocks.iter().enumerate() {
+        let y = 4 * i;
+
+        // column 0 keeps rows from being trimmed, and leads into the block
+        grid[y][0] = turn(AheuiDirection::Null);
+        grid[y + 1][0] = turn(AheuiDirection::Right(false));
+        grid[y + 2][0] = turn(AheuiDirection::Null);
+        grid[y + 3][0] = turn(AheuiDirection::Up(false));
+
+        for (x, (line, operation, argument)) in block.body.iter().enumerate() {
+            grid[y + 1][x + 1] = cell(*operation, *argument, AheuiDirection::Right(false));
+            lines.push((AheuiCoordinates { x: x + 1, y: y + 1 }, *line));
+        }
+
+        let x = exits + 3 * i;
+        match block.exit {
+            Exit::Halt(line) => {
+                grid[y + 1][block.body.len() + 1] = cell(AheuiOperation::Terminate, AheuiArgument::Null, AheuiDirection::ReflectXY);
+                if let Some(line) = line {
+                    lines.push((AheuiCoordinates { x: block.body.len() + 1, y: y + 1 }, line));
+                }
+            },
+            Exit::Jump(line, target) => {
+                grid[y + 1][x] = turn(AheuiDirection::Down(false));
+                grid[4 * target + 3][x] = turn(AheuiDirection::Left(false));
+                if let Some(line) = line {
+                    lines.push((AheuiCoordinates { x, y: y + 1 }, line));
+                }
+            },
+            Exit::Branch(line, operation, argument, success, failure) => {
+                // moving down succeeds downwards and reflects upwards
+                grid[y + 1][x] = cell(operation, argument, AheuiDirection::Down(false));
+                lines.push((AheuiCoordinates { x, y: y + 1 }, line));
+                grid[y + 2][x] = turn(AheuiDirection::Right(false));
+                grid[y + 2][x + 1] = turn(AheuiDirection::Down(false));
+                grid[4 * success + 3][x + 1] = turn(AheuiDirection::Left(false));
+                grid[y][x] = turn(AheuiDirection::Right(false));
+                grid[y][x + 2] = turn(AheuiDirection::Down(false));
+                grid[4 * failure + 3][x + 2] = turn(AheuiDirection::Left(false));
+            },
+        };
+    }
+
+    let mut source = String::new();
+    for row in grid {
+        let row: String = row.into_iter().collect();
+        source.push_str(row.trim_end());
+        source.push('\n');
+    }
+    (source, lines)
+}
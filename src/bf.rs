@@ -0,0 +1,174 @@
+use std::fmt;
+use crate::asm::{AheuiAsmBuilder, AheuiAsmError};
+
+// number of cells on the tape, as in the original implementation
+const TAPE_LENGTH: isize = 30000;
+
+// brackets without a partner, with their line and column counting from one
+#[derive(Debug, PartialEq, Eq)]
+pub enum AheuiBfError {
+    UnclosedLoop(usize, usize),
+    UnopenedLoop(usize, usize),
+    InvalidAssembly(AheuiAsmError), // assembly generated for the program is rejected, which is a bug
+}
+
+impl fmt::Display for AheuiBfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AheuiBfError::UnclosedLoop(line, column) => write!(f, "{}:{}: [ is never closed", line, column),
+            AheuiBfError::UnopenedLoop(line, column) => write!(f, "{}:{}: ] closes no loop", line, column),
+            AheuiBfError::InvalidAssembly(error) => write!(f, "generated assembly is invalid: {}", error),
+        }
+    }
+}
+
+// brainfuck instructions, with runs of + and - and of > and < folded together
+enum Command {
+    Add(isize),
+    Move(isize),
+    Output,
+    Input,
+    // [-] and [+], which empty a cell
+    Clear,
+    Loop(Vec<Command>),
+}
+
+fn parse(source: &str) -> Result<Vec<Command>, AheuiBfError> {
+    // commands of each loop still open, and where it was opened
+    let mut open: Vec<(Vec<Command>, usize, usize)> = Vec::new();
+    let mut commands = Vec::new();
+    for (y, line) in source.lines().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            match c {
+                '+' | '-' => {
+                    let n = if c == '+' { 1 } else { -1 };
+                    match commands.last_mut() {
+                        Some(Command::Add(m)) => *m += n,
+                        _ => commands.push(Command::Add(n)),
+                    };
+                },
+                '>' | '<' => {
+                    let n = if c == '>' { 1 } else { -1 };
+                    match commands.last_mut() {
+                        Some(Command::Move(m)) => *m += n,
+                        _ => commands.push(Command::Move(n)),
+                    };
+                },
+                '.' => commands.push(Command::Output),
+                ',' => commands.push(Command::Input),
+                '[' => open.push((std::mem::take(&mut commands), y + 1, x + 1)),
+                ']' => {
+                    let (outer, _, _) = open.pop().ok_or(AheuiBfError::UnopenedLoop(y + 1, x + 1))?;
+                    let body = std::mem::replace(&mut commands, outer);
+                    let clear = matches!(body[..], [Command::Add(n)] if n % 2 != 0);
+                    commands.push(if clear { Command::Clear } else { Command::Loop(body) });
+                },
+                _ => {},
+            };
+        }
+    }
+    match open.pop() {
+        Some((_, line, column)) => Err(AheuiBfError::UnclosedLoop(line, column)),
+        None => Ok(commands),
+    }
+}
+
+// statements are not mapped back to the brainfuck source,
+// so they are all given the same line
+const LINE: usize = 1;
+
+fn commands(assembly: &mut AheuiAsmBuilder, commands: &[Command]) {
+    for command in commands {
+        match command {
+            Command::Add(n) => {
+                // adding the amount modulo 256 keeps the sum positive,
+                // so the remainder wraps the cell around
+                let n = n.rem_euclid(256);
+                if n != 0 {
+                    assembly.constant(LINE, n);
+                    assembly.emit(LINE, "add");
+                    assembly.constant(LINE, 256);
+                    assembly.emit(LINE, "mod");
+                }
+            },
+            Command::Move(n) if *n > 0 => {
+                for _ in 0..*n {
+                    assembly.emit(LINE, "mov ㄱ");
+                }
+            },
+            Command::Move(n) => {
+                assembly.emit(LINE, "sel ㄱ");
+                for _ in 0..-n {
+                    assembly.emit(LINE, "mov");
+                }
+                assembly.emit(LINE, "sel");
+            },
+            Command::Output => {
+                assembly.emit(LINE, "dup");
+                assembly.emit(LINE, "pop char");
+            },
+            Command::Input => {
+                assembly.emit(LINE, "push char");
+                assembly.emit(LINE, "swap");
+                assembly.emit(LINE, "push 0");
+                assembly.emit(LINE, "mul");
+                assembly.emit(LINE, "add");
+            },
+            Command::Clear => {
+                assembly.emit(LINE, "push 0");
+                assembly.emit(LINE, "mul");
+            },
+            Command::Loop(body) => {
+                let (start, end) = (assembly.label(), assembly.label());
+                assembly.emit(LINE, format!("{}:", start));
+                assembly.emit(LINE, "dup");
+                assembly.emit(LINE, format!("brz {}", end));
+                self::commands(assembly, body);
+                assembly.emit(LINE, format!("jmp {}", start));
+                assembly.emit(LINE, format!("{}:", end));
+            },
+        };
+    }
+}
+
+// lower brainfuck to assembly statements
+//
+// the tape is kept as two stacks: the storage without a final consonant holds
+// the current cell on top of those to its right, and ㄱ holds those to its left,
+// the nearest on top. the first storage is filled with the whole tape to begin with
+fn lower(source: &str) -> Result<AheuiAsmBuilder, AheuiBfError> {
+    let commands = parse(source)?;
+    let mut assembly = AheuiAsmBuilder::new();
+
+    // count down in ㄴ, pushing a cell each time
+    assembly.emit(LINE, "push 0");
+    assembly.emit(LINE, "sel ㄴ");
+    assembly.constant(LINE, TAPE_LENGTH - 1);
+    let (fill, filled) = (assembly.label(), assembly.label());
+    assembly.emit(LINE, format!("{}:", fill));
+    assembly.emit(LINE, "dup");
+    assembly.emit(LINE, format!("brz {}", filled));
+    assembly.constant(LINE, 1);
+    assembly.emit(LINE, "sub");
+    assembly.emit(LINE, "sel");
+    assembly.emit(LINE, "push 0");
+    assembly.emit(LINE, "sel ㄴ");
+    assembly.emit(LINE, format!("jmp {}", fill));
+    assembly.emit(LINE, format!("{}:", filled));
+    assembly.emit(LINE, "sel");
+
+    self::commands(&mut assembly, &commands);
+    assembly.emit(LINE, "halt");
+    Ok(assembly)
+}
+
+// translate brainfuck to linear assembly
+pub fn to_assembly(source: &str) -> Result<String, AheuiBfError> {
+    Ok(lower(source)?.source())
+}
+
+// translate brainfuck to an aheui program
+pub fn translate(source: &str) -> Result<String, AheuiBfError> {
+    let (grid, _) = lower(source)?.assemble().map_err(AheuiBfError::InvalidAssembly)?;
+    Ok(grid)
+}
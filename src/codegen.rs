@@ -0,0 +1,10 @@
+pub mod c;
+pub mod wat;
+
+use crate::instruction::*;
+use crate::component::*;
+
+// comment describing a flow node, shared by every backend
+fn describe_node(position: AheuiCoordinates, direction: AheuiDirection, instruction: &AheuiInstruction) -> String {
+    format!("({}, {}) {} moving {:?}", position.x, position.y, instruction.character, direction)
+}
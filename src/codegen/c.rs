@@ -0,0 +1,598 @@
+use std::fmt::Write;
+use crate::instruction::*;
+use crate::component::*;
+use crate::flow::*;
+use crate::encoding::AheuiEncoding;
+use crate::AheuiEof;
+use super::describe_node;
+
+// settings of the interpreter which a compiled program behaves like
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompileOptions {
+    pub strict: bool, // stop on storage underflow instead of reflecting
+    pub encoding: AheuiEncoding, // of characters popped to output
+    pub eof: AheuiEof, // what reading does once input has run out
+}
+
+// runtime shared by every generated program
+// its functions are not static, so that unused ones do not cause warnings
+// storages are ring buffers, so that both stacks and queues
+// can push to and pop from either end
+// arithmetic, output and input errors follow the interpreter:
+// overflow, division by zero, invalid characters and running out of input
+// are fatal, invalid input is reported and asked for again
+// the settings compiled with are defined before it, choosing its branches
+const RUNTIME: &str = r#"#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef intptr_t value_t;
+#define VALUE_MAX INTPTR_MAX
+#define VALUE_MIN INTPTR_MIN
+
+typedef struct {
+    value_t *data;
+    size_t head;
+    size_t len;
+    size_t cap;
+    int queue;
+} storage_t;
+
+static storage_t storages[28];
+static storage_t *current = &storages[0];
+
+void fatal(void) {
+    exit(1);
+}
+
+/* an instruction lacking values reflects, or stops the program in strict mode */
+int underflow(const char *operation, int needed) {
+#if STRICT
+    fflush(stdout);
+    fprintf(stderr, "fatal: %s needs %d values, but storage %s holds %lld\n", operation, needed, storage_names[current - storages], (long long)current->len);
+    fatal();
+#endif
+    (void)operation;
+    (void)needed;
+    return 0;
+}
+
+void grow(storage_t *s) {
+    size_t cap = s->cap ? s->cap * 2 : 16;
+    value_t *data = malloc(cap * sizeof(value_t));
+    size_t i;
+    if (!data) {
+        fflush(stdout);
+        fputs("fatal: out of memory\n", stderr);
+        fatal();
+    }
+    for (i = 0; i < s->len; i++) {
+        data[i] = s->data[(s->head + i) % s->cap];
+    }
+    free(s->data);
+    s->data = data;
+    s->head = 0;
+    s->cap = cap;
+}
+
+/* push a value to the storage, to the top for stacks and the back for queues */
+void push(storage_t *s, value_t v) {
+    if (s->len == s->cap) {
+        grow(s);
+    }
+    s->data[(s->head + s->len) % s->cap] = v;
+    s->len++;
+}
+
+/* push a value to the end values are popped from */
+void push_top(storage_t *s, value_t v) {
+    if (!s->queue) {
+        push(s, v);
+        return;
+    }
+    if (s->len == s->cap) {
+        grow(s);
+    }
+    s->head = (s->head + s->cap - 1) % s->cap;
+    s->data[s->head] = v;
+    s->len++;
+}
+
+value_t pop(storage_t *s) {
+    value_t v;
+    if (s->queue) {
+        v = s->data[s->head];
+        s->head = (s->head + 1) % s->cap;
+    } else {
+        v = s->data[(s->head + s->len - 1) % s->cap];
+    }
+    s->len--;
+    return v;
+}
+
+int mul_overflows(value_t x, value_t y) {
+    if (x == 0 || y == 0) {
+        return 0;
+    }
+    if (x > 0) {
+        return y > 0 ? x > VALUE_MAX / y : y < VALUE_MIN / x;
+    }
+    return y > 0 ? x < VALUE_MIN / y : y < VALUE_MAX / x;
+}
+
+/* pop a and b, then push b op a */
+int op_arithmetic(char op) {
+    value_t a, b, r = 0;
+    int overflow = 0;
+    if (current->len < 2) {
+        switch (op) {
+        case '+': return underflow("Add", 2);
+        case '-': return underflow("Subtract", 2);
+        case '*': return underflow("Multiply", 2);
+        case '/': return underflow("Divide", 2);
+        default: return underflow("Modulo", 2);
+        }
+    }
+    a = pop(current);
+    b = pop(current);
+    switch (op) {
+    case '+':
+        overflow = (a > 0 && b > VALUE_MAX - a) || (a < 0 && b < VALUE_MIN - a);
+        if (!overflow) r = b + a;
+        break;
+    case '-':
+        overflow = (a < 0 && b > VALUE_MAX + a) || (a > 0 && b < VALUE_MIN + a);
+        if (!overflow) r = b - a;
+        break;
+    case '*':
+        overflow = mul_overflows(b, a);
+        if (!overflow) r = b * a;
+        break;
+    case '/':
+        overflow = a == 0 || (b == VALUE_MIN && a == -1);
+        if (!overflow) r = b / a;
+        break;
+    case '%':
+        overflow = a == 0 || (b == VALUE_MIN && a == -1);
+        if (!overflow) r = b % a;
+        break;
+    }
+    if (overflow) {
+        fflush(stdout);
+        fprintf(stderr, "fatal: overflow/underflow occurred with arithmetic operation between %lld and %lld\n", (long long)a, (long long)b);
+        fatal();
+    }
+    push(current, r);
+    return 1;
+}
+
+int op_pop_int(void) {
+    if (current->len < 1) {
+        return underflow("Pop", 1);
+    }
+    printf("%lld", (long long)pop(current));
+    return 1;
+}
+
+int is_scalar(value_t v) {
+    return v >= 0 && v <= 0x10FFFF && !(v >= 0xD800 && v <= 0xDFFF);
+}
+
+/* write the bytes of a character in the output encoding,
+   returning zero if it has none */
+#if defined(ENCODING_BYTE)
+int write_char(value_t v) {
+    putchar((int)(v & 0xFF));
+    return 1;
+}
+#elif defined(ENCODING_UTF16LE) || defined(ENCODING_UTF16BE)
+void put_unit(uint32_t u) {
+#if defined(ENCODING_UTF16LE)
+    putchar((int)(u & 0xFF));
+    putchar((int)(u >> 8));
+#else
+    putchar((int)(u >> 8));
+    putchar((int)(u & 0xFF));
+#endif
+}
+
+int write_char(value_t v) {
+    uint32_t c;
+    if (!is_scalar(v)) {
+        return 0;
+    }
+    c = (uint32_t)v;
+    if (c < 0x10000) {
+        put_unit(c);
+    } else {
+        c -= 0x10000;
+        put_unit(0xD800 | (c >> 10));
+        put_unit(0xDC00 | (c & 0x3FF));
+    }
+    return 1;
+}
+#elif defined(ENCODING_CP949)
+/* ASCII, compatibility jamo, and syllables from a table of their codes */
+int write_char(value_t v) {
+    unsigned code;
+    if (v >= 0 && v < 0x80) {
+        putchar((int)v);
+        return 1;
+    }
+    if (v >= 0x3131 && v <= 0x318E) {
+        putchar(0xA4);
+        putchar((int)(0xA1 + v - 0x3131));
+        return 1;
+    }
+    if (v < 0xAC00 || v > 0xD7A3 || !syllable_codes[v - 0xAC00]) {
+        return 0;
+    }
+    code = syllable_codes[v - 0xAC00];
+    putchar((int)(code >> 8));
+    putchar((int)(code & 0xFF));
+    return 1;
+}
+#else
+int write_char(value_t v) {
+    uint32_t c;
+    if (!is_scalar(v)) {
+        return 0;
+    }
+    c = (uint32_t)v;
+    if (c < 0x80) {
+        putchar((int)c);
+    } else if (c < 0x800) {
+        putchar((int)(0xC0 | (c >> 6)));
+        putchar((int)(0x80 | (c & 0x3F)));
+    } else if (c < 0x10000) {
+        putchar((int)(0xE0 | (c >> 12)));
+        putchar((int)(0x80 | ((c >> 6) & 0x3F)));
+        putchar((int)(0x80 | (c & 0x3F)));
+    } else {
+        putchar((int)(0xF0 | (c >> 18)));
+        putchar((int)(0x80 | ((c >> 12) & 0x3F)));
+        putchar((int)(0x80 | ((c >> 6) & 0x3F)));
+        putchar((int)(0x80 | (c & 0x3F)));
+    }
+    return 1;
+}
+#endif
+
+int op_pop_char(void) {
+    value_t v;
+    if (current->len < 1) {
+        return underflow("Pop", 1);
+    }
+    v = pop(current);
+    if (!write_char(v)) {
+        fflush(stdout);
+        fprintf(stderr, "fatal: attempted to pop value %lld to output, which can not be encoded in %s\n", (long long)v, ENCODING_NAME);
+        fatal();
+    }
+    return 1;
+}
+
+/* popping without an output argument discards the value and reflects */
+int op_pop_discard(void) {
+    if (current->len < 1) {
+        return underflow("Pop", 1);
+    }
+    pop(current);
+    return 0;
+}
+
+/* read a line including its newline, returning NULL at end of input */
+static char *read_line(void) {
+    size_t len = 0, cap = 64;
+    char *buffer = malloc(cap);
+    int c;
+    if (!buffer) {
+        fatal();
+    }
+    while ((c = getchar()) != EOF) {
+        if (len + 2 > cap) {
+            char *grown = realloc(buffer, cap * 2);
+            if (!grown) {
+                fatal();
+            }
+            buffer = grown;
+            cap *= 2;
+        }
+        buffer[len++] = (char)c;
+        if (c == '\n') {
+            break;
+        }
+    }
+    if (len == 0 && c == EOF) {
+        free(buffer);
+        return NULL;
+    }
+    buffer[len] = '\0';
+    return buffer;
+}
+
+/* value read once input has run out, stopping unless one was given */
+value_t end_of_input(void) {
+#if defined(EOF_VALUE)
+    return EOF_VALUE;
+#else
+    fflush(stdout);
+    fputs("fatal: reached the end of input\n", stderr);
+    fatal();
+    return 0;
+#endif
+}
+
+int is_space(char c) {
+    return c == ' ' || c == '\t' || c == '\n' || c == '\r' || c == '\v' || c == '\f';
+}
+
+/* trim whitespace in place, returning the start of the trimmed string */
+static char *trim(char *s) {
+    size_t len;
+    while (is_space(*s)) {
+        s++;
+    }
+    len = strlen(s);
+    while (len > 0 && is_space(s[len - 1])) {
+        s[--len] = '\0';
+    }
+    return s;
+}
+
+int parse_number(const char *s, value_t *result) {
+    int negative = 0;
+    value_t n = 0;
+    if (*s == '+' || *s == '-') {
+        negative = *s == '-';
+        s++;
+    }
+    if (!*s) {
+        return 0;
+    }
+    for (; *s; s++) {
+        value_t digit;
+        if (*s < '0' || *s > '9') {
+            return 0;
+        }
+        digit = *s - '0';
+        if (negative ? n < (VALUE_MIN + digit) / 10 : n > (VALUE_MAX - digit) / 10) {
+            return 0;
+        }
+        n = negative ? n * 10 - digit : n * 10 + digit;
+    }
+    *result = n;
+    return 1;
+}
+
+void prompt(const char *kind) {
+    printf("\ninput %s: ", kind);
+    fflush(stdout);
+}
+
+int op_push_int(void) {
+    for (;;) {
+        char *buffer, *trimmed;
+        value_t n;
+        prompt("number");
+        buffer = read_line();
+        if (!buffer) {
+            push(current, end_of_input());
+            return 1;
+        }
+        trimmed = trim(buffer);
+        if (parse_number(trimmed, &n)) {
+            free(buffer);
+            push(current, n);
+            return 1;
+        }
+        fprintf(stderr, "non-number input: %s\n", buffer);
+        free(buffer);
+    }
+}
+
+int op_push_char(void) {
+    for (;;) {
+        char *buffer, *s;
+        unsigned char c;
+        value_t n;
+        prompt("character");
+        buffer = read_line();
+        if (!buffer) {
+            push(current, end_of_input());
+            return 1;
+        }
+        s = trim(buffer);
+        c = (unsigned char)s[0];
+        if (c) {
+            if (c < 0x80) {
+                n = c;
+            } else if (c >= 0xF0) {
+                n = ((value_t)(c & 0x07) << 18) | ((value_t)(s[1] & 0x3F) << 12) | ((value_t)(s[2] & 0x3F) << 6) | (s[3] & 0x3F);
+            } else if (c >= 0xE0) {
+                n = ((value_t)(c & 0x0F) << 12) | ((value_t)(s[1] & 0x3F) << 6) | (s[2] & 0x3F);
+            } else {
+                n = ((value_t)(c & 0x1F) << 6) | (s[1] & 0x3F);
+            }
+            free(buffer);
+            push(current, n);
+            return 1;
+        }
+        fputs("no input provided\n", stderr);
+        free(buffer);
+    }
+}
+
+int op_duplicate(void) {
+    value_t v;
+    if (current->len < 1) {
+        return underflow("Duplicate", 1);
+    }
+    v = pop(current);
+    push_top(current, v);
+    push_top(current, v);
+    return 1;
+}
+
+int op_swap(void) {
+    value_t a, b;
+    if (current->len < 2) {
+        return underflow("Swap", 2);
+    }
+    a = pop(current);
+    b = pop(current);
+    push_top(current, a);
+    push_top(current, b);
+    return 1;
+}
+
+int op_transfer(int dest) {
+    if (current->len < 1) {
+        return underflow("StoreTransfer", 1);
+    }
+    push(&storages[dest], pop(current));
+    return 1;
+}
+
+int op_compare(void) {
+    value_t a, b;
+    if (current->len < 2) {
+        return underflow("Compare", 2);
+    }
+    a = pop(current);
+    b = pop(current);
+    push(current, a <= b);
+    return 1;
+}
+
+int op_fork(void) {
+    if (current->len < 1) {
+        return underflow("Fork", 1);
+    }
+    return pop(current) != 0;
+}
+
+int finish(void) {
+    putchar('\n');
+    fflush(stdout);
+    return 0;
+}
+
+"#;
+
+// generate a C expression performing an instruction's operation,
+// evaluating to nonzero if the instruction succeeds
+// operations which never fail are only used as statements
+fn operation_expression(instruction: &AheuiInstruction) -> String {
+    match (instruction.operation, instruction.argument) {
+        (AheuiOperation::Add, _) => "op_arithmetic('+')".to_string(),
+        (AheuiOperation::Subtract, _) => "op_arithmetic('-')".to_string(),
+        (AheuiOperation::Multiply, _) => "op_arithmetic('*')".to_string(),
+        (AheuiOperation::Divide, _) => "op_arithmetic('/')".to_string(),
+        (AheuiOperation::Modulo, _) => "op_arithmetic('%')".to_string(),
+        (AheuiOperation::Pop, AheuiArgument::AsInt) => "op_pop_int()".to_string(),
+        (AheuiOperation::Pop, AheuiArgument::AsChar) => "op_pop_char()".to_string(),
+        (AheuiOperation::Pop, _) => "op_pop_discard()".to_string(),
+        (AheuiOperation::Push, AheuiArgument::Number(n)) => format!("push(current, {})", n),
+        (AheuiOperation::Push, AheuiArgument::AsInt) => "op_push_int()".to_string(),
+        (AheuiOperation::Push, AheuiArgument::AsChar) => "op_push_char()".to_string(),
+        (AheuiOperation::Duplicate, _) => "op_duplicate()".to_string(),
+        (AheuiOperation::Swap, _) => "op_swap()".to_string(),
+        (AheuiOperation::StoreSelect, AheuiArgument::Storage(n)) => format!("current = &storages[{}]", n),
+        (AheuiOperation::StoreTransfer, AheuiArgument::Storage(n)) => format!("op_transfer({})", n),
+        (AheuiOperation::Compare, _) => "op_compare()".to_string(),
+        (AheuiOperation::Fork, _) => "op_fork()".to_string(),
+        _ => String::new(),
+    }
+}
+
+// definitions choosing the branches of the runtime for the options given,
+// along with the tables those branches use
+fn settings(options: &CompileOptions) -> String {
+    let mut source = String::new();
+    writeln!(source, "#define STRICT {}", options.strict as u8).unwrap();
+    if options.strict {
+        let names: Vec<String> = (0..28).map(|i| format!("\"{}\"", storage_name(i))).collect();
+        writeln!(source, "const char *storage_names[28] = {{{}}};", names.join(", ")).unwrap();
+    }
+
+    let encoding = match options.encoding {
+        AheuiEncoding::Utf8 => "UTF8",
+        AheuiEncoding::Byte => "BYTE",
+        AheuiEncoding::Utf16Le => "UTF16LE",
+        AheuiEncoding::Utf16Be => "UTF16BE",
+        AheuiEncoding::Cp949 | AheuiEncoding::EucKr => "CP949",
+    };
+    writeln!(source, "#define ENCODING_{}", encoding).unwrap();
+    writeln!(source, "#define ENCODING_NAME \"{}\"", options.encoding).unwrap();
+    if encoding == "CP949" {
+        // codes of the syllables from 가, 0 for those without one,
+        // which EUC-KR has many more of
+        let codes: Vec<String> = (0xAC00..=0xD7A3)
+            .map(|code| match options.encoding.encode(code) {
+                Some(bytes) => ((bytes[0] as u16) << 8 | bytes[1] as u16).to_string(),
+                None => "0".to_string(),
+            })
+            .collect();
+        source.push_str("const unsigned short syllable_codes[11172] = {\n");
+        for line in codes.chunks(16) {
+            writeln!(source, "    {},", line.join(", ")).unwrap();
+        }
+        source.push_str("};\n");
+    }
+
+    if let AheuiEof::Value(value) = options.eof {
+        writeln!(source, "#define EOF_VALUE ((value_t){})", value).unwrap();
+    }
+    source
+}
+
+// translate a program into a self-contained C source file
+// every reachable (position, direction) state becomes a label,
+// jumping to the state reached on success or reflection
+pub fn compile(program: &AheuiProgram, options: &CompileOptions) -> String {
+    let graph = AheuiFlowGraph::from_program(program);
+    let mut source = settings(options);
+    source.push_str(RUNTIME);
+
+    source.push_str("int main(void) {\n");
+    source.push_str("    storages[21].queue = 1;\n");
+    source.push_str("    storages[27].queue = 1;\n");
+    if !graph.nodes.is_empty() {
+        source.push_str("    goto n0;\n");
+    }
+
+    for (i, node) in graph.nodes.iter().enumerate() {
+        let instruction = &node.instruction;
+        writeln!(source, "n{}: /* {} */", i, describe_node(node.position, node.direction, instruction)).unwrap();
+
+        if instruction.operation == AheuiOperation::Terminate {
+            source.push_str("    return finish();\n");
+            continue;
+        }
+
+        let expression = operation_expression(instruction);
+        match (node.success, node.failure) {
+            (Some(success), Some(failure)) => {
+                writeln!(source, "    if ({}) goto n{};", expression, success).unwrap();
+                writeln!(source, "    goto n{};", failure).unwrap();
+            },
+            (Some(success), None) => {
+                if !expression.is_empty() {
+                    writeln!(source, "    {};", expression).unwrap();
+                }
+                writeln!(source, "    goto n{};", success).unwrap();
+            },
+            (None, Some(failure)) => {
+                writeln!(source, "    {};", expression).unwrap();
+                writeln!(source, "    goto n{};", failure).unwrap();
+            },
+            (None, None) => {},
+        };
+    }
+
+    // programs without any instructions end immediately
+    source.push_str("    return 0;\n}\n");
+    source
+}
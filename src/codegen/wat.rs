@@ -0,0 +1,340 @@
+use std::fmt::Write;
+use crate::instruction::*;
+use crate::component::*;
+use crate::flow::*;
+use super::describe_node;
+
+// module header and runtime shared by every generated program
+// input and output are imported from the host, which also reports
+// fatal errors (0: arithmetic overflow/underflow, 1: invalid character)
+// storages are ring buffers of i64 values in linear memory,
+// described by 16 byte headers (base, head, len, cap) at address 0
+// and grown by bump allocation from address 1024
+const RUNTIME: &str = r#"(module
+  (import "aheui" "input_number" (func $input_number (result i64)))
+  (import "aheui" "input_char" (func $input_char (result i64)))
+  (import "aheui" "output_number" (func $output_number (param i64)))
+  (import "aheui" "output_char" (func $output_char (param i32)))
+  (import "aheui" "error" (func $error (param i32 i64 i64)))
+  (memory (export "memory") 1)
+  (global $current (mut i32) (i32.const 0))
+  (global $heap (mut i32) (i32.const 1024))
+
+  (func $is_queue (param $s i32) (result i32)
+    (i32.or (i32.eq (local.get $s) (i32.const 21)) (i32.eq (local.get $s) (i32.const 27))))
+
+  (func $len (param $s i32) (result i32)
+    (i32.load offset=8 (i32.shl (local.get $s) (i32.const 4))))
+
+  ;; address of the value i places after the head of a storage
+  (func $slot (param $s i32) (param $i i32) (result i32)
+    (local $h i32)
+    (local.set $h (i32.shl (local.get $s) (i32.const 4)))
+    (i32.add
+      (i32.load (local.get $h))
+      (i32.shl
+        (i32.rem_u
+          (i32.add (i32.load offset=4 (local.get $h)) (local.get $i))
+          (i32.load offset=12 (local.get $h)))
+        (i32.const 3))))
+
+  ;; move a storage into a new buffer of twice its capacity
+  (func $grow (param $s i32)
+    (local $h i32) (local $cap i32) (local $base i32) (local $bytes i32) (local $i i32)
+    (local.set $h (i32.shl (local.get $s) (i32.const 4)))
+    (local.set $cap (select
+      (i32.shl (i32.load offset=12 (local.get $h)) (i32.const 1))
+      (i32.const 16)
+      (i32.load offset=12 (local.get $h))))
+    (local.set $base (global.get $heap))
+    (local.set $bytes (i32.shl (local.get $cap) (i32.const 3)))
+    (if (i32.gt_u
+          (i32.add (local.get $base) (local.get $bytes))
+          (i32.shl (memory.size) (i32.const 16)))
+      (then
+        (if (i32.eq
+              (memory.grow (i32.add
+                (i32.shr_u
+                  (i32.sub
+                    (i32.add (local.get $base) (local.get $bytes))
+                    (i32.shl (memory.size) (i32.const 16)))
+                  (i32.const 16))
+                (i32.const 1)))
+              (i32.const -1))
+          (then (unreachable)))))
+    (block $done
+      (loop $copy
+        (br_if $done (i32.ge_u (local.get $i) (i32.load offset=8 (local.get $h))))
+        (i64.store
+          (i32.add (local.get $base) (i32.shl (local.get $i) (i32.const 3)))
+          (i64.load (call $slot (local.get $s) (local.get $i))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $copy)))
+    (i32.store (local.get $h) (local.get $base))
+    (i32.store offset=4 (local.get $h) (i32.const 0))
+    (i32.store offset=12 (local.get $h) (local.get $cap))
+    (global.set $heap (i32.add (local.get $base) (local.get $bytes))))
+
+  ;; push a value to the storage, to the top for stacks and the back for queues
+  (func $push (param $s i32) (param $v i64)
+    (local $h i32)
+    (local.set $h (i32.shl (local.get $s) (i32.const 4)))
+    (if (i32.eq (i32.load offset=8 (local.get $h)) (i32.load offset=12 (local.get $h)))
+      (then (call $grow (local.get $s))))
+    (i64.store (call $slot (local.get $s) (i32.load offset=8 (local.get $h))) (local.get $v))
+    (i32.store offset=8 (local.get $h) (i32.add (i32.load offset=8 (local.get $h)) (i32.const 1))))
+
+  ;; push a value to the end values are popped from
+  (func $push_top (param $s i32) (param $v i64)
+    (local $h i32)
+    (if (i32.eqz (call $is_queue (local.get $s)))
+      (then
+        (call $push (local.get $s) (local.get $v))
+        (return)))
+    (local.set $h (i32.shl (local.get $s) (i32.const 4)))
+    (if (i32.eq (i32.load offset=8 (local.get $h)) (i32.load offset=12 (local.get $h)))
+      (then (call $grow (local.get $s))))
+    (i32.store offset=4 (local.get $h)
+      (i32.rem_u
+        (i32.sub
+          (i32.add (i32.load offset=4 (local.get $h)) (i32.load offset=12 (local.get $h)))
+          (i32.const 1))
+        (i32.load offset=12 (local.get $h))))
+    (i64.store (call $slot (local.get $s) (i32.const 0)) (local.get $v))
+    (i32.store offset=8 (local.get $h) (i32.add (i32.load offset=8 (local.get $h)) (i32.const 1))))
+
+  (func $pop (param $s i32) (result i64)
+    (local $h i32) (local $v i64)
+    (local.set $h (i32.shl (local.get $s) (i32.const 4)))
+    (if (call $is_queue (local.get $s))
+      (then
+        (local.set $v (i64.load (call $slot (local.get $s) (i32.const 0))))
+        (i32.store offset=4 (local.get $h)
+          (i32.rem_u
+            (i32.add (i32.load offset=4 (local.get $h)) (i32.const 1))
+            (i32.load offset=12 (local.get $h)))))
+      (else
+        (local.set $v (i64.load (call $slot (local.get $s)
+          (i32.sub (i32.load offset=8 (local.get $h)) (i32.const 1)))))))
+    (i32.store offset=8 (local.get $h) (i32.sub (i32.load offset=8 (local.get $h)) (i32.const 1)))
+    (local.get $v))
+
+  ;; pop a and b, then push b op a
+  ;; returns 0 if reflected, 1 if successful and 2 on a fatal error
+  (func $op_arithmetic (param $op i32) (result i32)
+    (local $a i64) (local $b i64) (local $r i64) (local $overflow i32)
+    (if (i32.lt_u (call $len (global.get $current)) (i32.const 2))
+      (then (return (i32.const 0))))
+    (local.set $a (call $pop (global.get $current)))
+    (local.set $b (call $pop (global.get $current)))
+    (block $checked
+      ;; add
+      (if (i32.eq (local.get $op) (i32.const 0))
+        (then
+          (local.set $r (i64.add (local.get $b) (local.get $a)))
+          (local.set $overflow (i64.lt_s
+            (i64.and
+              (i64.xor (local.get $b) (local.get $r))
+              (i64.xor (local.get $a) (local.get $r)))
+            (i64.const 0)))
+          (br $checked)))
+      ;; subtract
+      (if (i32.eq (local.get $op) (i32.const 1))
+        (then
+          (local.set $r (i64.sub (local.get $b) (local.get $a)))
+          (local.set $overflow (i64.lt_s
+            (i64.and
+              (i64.xor (local.get $b) (local.get $a))
+              (i64.xor (local.get $b) (local.get $r)))
+            (i64.const 0)))
+          (br $checked)))
+      ;; multiply
+      (if (i32.eq (local.get $op) (i32.const 2))
+        (then
+          (local.set $r (i64.mul (local.get $b) (local.get $a)))
+          (if (i64.eq (local.get $a) (i64.const -1))
+            (then (local.set $overflow (i64.eq (local.get $b) (i64.const 0x8000000000000000))))
+            (else
+              (if (i64.ne (local.get $a) (i64.const 0))
+                (then (local.set $overflow
+                  (i64.ne (i64.div_s (local.get $r) (local.get $a)) (local.get $b)))))))
+          (br $checked)))
+      ;; divide and modulo
+      (local.set $overflow (i32.or
+        (i64.eqz (local.get $a))
+        (i32.and
+          (i64.eq (local.get $a) (i64.const -1))
+          (i64.eq (local.get $b) (i64.const 0x8000000000000000)))))
+      (br_if $checked (local.get $overflow))
+      (if (i32.eq (local.get $op) (i32.const 3))
+        (then (local.set $r (i64.div_s (local.get $b) (local.get $a))))
+        (else (local.set $r (i64.rem_s (local.get $b) (local.get $a))))))
+    (if (local.get $overflow)
+      (then
+        (call $error (i32.const 0) (local.get $a) (local.get $b))
+        (return (i32.const 2))))
+    (call $push (global.get $current) (local.get $r))
+    (i32.const 1))
+
+  (func $op_pop_int (result i32)
+    (if (i32.lt_u (call $len (global.get $current)) (i32.const 1))
+      (then (return (i32.const 0))))
+    (call $output_number (call $pop (global.get $current)))
+    (i32.const 1))
+
+  (func $op_pop_char (result i32)
+    (local $v i64) (local $c i32)
+    (if (i32.lt_u (call $len (global.get $current)) (i32.const 1))
+      (then (return (i32.const 0))))
+    (local.set $v (call $pop (global.get $current)))
+    (local.set $c (i32.wrap_i64 (local.get $v)))
+    (if (i32.or
+          (i64.gt_u (local.get $v) (i64.const 0x10FFFF))
+          (i32.eq (i32.and (local.get $c) (i32.const 0xFFFFF800)) (i32.const 0xD800)))
+      (then
+        (call $error (i32.const 1) (local.get $v) (i64.const 0))
+        (return (i32.const 2))))
+    (call $output_char (local.get $c))
+    (i32.const 1))
+
+  ;; popping without an output argument discards the value and reflects
+  (func $op_pop_discard (result i32)
+    (if (call $len (global.get $current))
+      (then (drop (call $pop (global.get $current)))))
+    (i32.const 0))
+
+  (func $op_duplicate (result i32)
+    (local $v i64)
+    (if (i32.lt_u (call $len (global.get $current)) (i32.const 1))
+      (then (return (i32.const 0))))
+    (local.set $v (call $pop (global.get $current)))
+    (call $push_top (global.get $current) (local.get $v))
+    (call $push_top (global.get $current) (local.get $v))
+    (i32.const 1))
+
+  (func $op_swap (result i32)
+    (local $a i64) (local $b i64)
+    (if (i32.lt_u (call $len (global.get $current)) (i32.const 2))
+      (then (return (i32.const 0))))
+    (local.set $a (call $pop (global.get $current)))
+    (local.set $b (call $pop (global.get $current)))
+    (call $push_top (global.get $current) (local.get $a))
+    (call $push_top (global.get $current) (local.get $b))
+    (i32.const 1))
+
+  (func $op_transfer (param $dest i32) (result i32)
+    (if (i32.lt_u (call $len (global.get $current)) (i32.const 1))
+      (then (return (i32.const 0))))
+    (call $push (local.get $dest) (call $pop (global.get $current)))
+    (i32.const 1))
+
+  (func $op_compare (result i32)
+    (local $a i64) (local $b i64)
+    (if (i32.lt_u (call $len (global.get $current)) (i32.const 2))
+      (then (return (i32.const 0))))
+    (local.set $a (call $pop (global.get $current)))
+    (local.set $b (call $pop (global.get $current)))
+    (call $push (global.get $current) (i64.extend_i32_u (i64.le_s (local.get $a) (local.get $b))))
+    (i32.const 1))
+
+  (func $op_fork (result i32)
+    (if (i32.lt_u (call $len (global.get $current)) (i32.const 1))
+      (then (return (i32.const 0))))
+    (i64.ne (call $pop (global.get $current)) (i64.const 0)))
+
+  ;; end the program, using the popped value as its exit code
+  (func $op_terminate (result i32)
+    (call $output_char (i32.const 10))
+    (if (i32.eqz (call $len (global.get $current)))
+      (then (return (i32.const 0))))
+    (i32.wrap_i64 (call $pop (global.get $current))))
+
+"#;
+
+// generate WAT instructions performing an instruction's operation,
+// leaving its i32 status on the stack if it can fail
+fn operation_instructions(instruction: &AheuiInstruction) -> String {
+    match (instruction.operation, instruction.argument) {
+        (AheuiOperation::Add, _) => "i32.const 0 call $op_arithmetic".to_string(),
+        (AheuiOperation::Subtract, _) => "i32.const 1 call $op_arithmetic".to_string(),
+        (AheuiOperation::Multiply, _) => "i32.const 2 call $op_arithmetic".to_string(),
+        (AheuiOperation::Divide, _) => "i32.const 3 call $op_arithmetic".to_string(),
+        (AheuiOperation::Modulo, _) => "i32.const 4 call $op_arithmetic".to_string(),
+        (AheuiOperation::Pop, AheuiArgument::AsInt) => "call $op_pop_int".to_string(),
+        (AheuiOperation::Pop, AheuiArgument::AsChar) => "call $op_pop_char".to_string(),
+        (AheuiOperation::Pop, _) => "call $op_pop_discard".to_string(),
+        (AheuiOperation::Push, AheuiArgument::Number(n)) => format!("global.get $current i64.const {} call $push", n),
+        (AheuiOperation::Push, AheuiArgument::AsInt) => "global.get $current call $input_number call $push".to_string(),
+        (AheuiOperation::Push, AheuiArgument::AsChar) => "global.get $current call $input_char call $push".to_string(),
+        (AheuiOperation::Duplicate, _) => "call $op_duplicate".to_string(),
+        (AheuiOperation::Swap, _) => "call $op_swap".to_string(),
+        (AheuiOperation::StoreSelect, AheuiArgument::Storage(n)) => format!("i32.const {} global.set $current", n),
+        (AheuiOperation::StoreTransfer, AheuiArgument::Storage(n)) => format!("i32.const {} call $op_transfer", n),
+        (AheuiOperation::Compare, _) => "call $op_compare".to_string(),
+        (AheuiOperation::Fork, _) => "call $op_fork".to_string(),
+        _ => String::new(),
+    }
+}
+
+// translate a program into a WebAssembly text module
+// exporting its memory and a run function returning the exit code
+// every reachable (position, direction) state is a block of a
+// dispatch loop, selected by the $state local through br_table
+pub fn compile(program: &AheuiProgram) -> String {
+    let graph = AheuiFlowGraph::from_program(program);
+    let mut source = String::from(RUNTIME);
+
+    source.push_str("  (func $run (export \"run\") (result i32)\n");
+    source.push_str("    (local $state i32) (local $status i32)\n");
+
+    if graph.nodes.is_empty() {
+        source.push_str("    (i32.const 0))\n)\n");
+        return source;
+    }
+
+    source.push_str("    loop $dispatch\n");
+    for i in (0..graph.nodes.len()).rev() {
+        writeln!(source, "    block $n{}", i).unwrap();
+    }
+    source.push_str("    local.get $state\n    br_table");
+    for i in 0..graph.nodes.len() {
+        write!(source, " $n{}", i).unwrap();
+    }
+    source.push('\n');
+
+    for node in graph.nodes.iter() {
+        let instruction = &node.instruction;
+        writeln!(source, "    end ;; {}", describe_node(node.position, node.direction, instruction)).unwrap();
+
+        if instruction.operation == AheuiOperation::Terminate {
+            source.push_str("    call $op_terminate\n    return\n");
+            continue;
+        }
+
+        let instructions = operation_instructions(instruction);
+        match (node.success, node.failure) {
+            (Some(success), Some(failure)) => {
+                // stop on fatal errors, otherwise pick the next state by status
+                writeln!(source, "    {}", instructions).unwrap();
+                source.push_str("    local.tee $status\n");
+                source.push_str("    i32.const 2\n    i32.eq\n    if\n    i32.const 1\n    return\n    end\n");
+                writeln!(source, "    i32.const {}\n    i32.const {}\n    local.get $status\n    select", success, failure).unwrap();
+            },
+            (Some(success), None) => {
+                if !instructions.is_empty() {
+                    writeln!(source, "    {}", instructions).unwrap();
+                }
+                writeln!(source, "    i32.const {}", success).unwrap();
+            },
+            (None, Some(failure)) => {
+                writeln!(source, "    {}\n    drop", instructions).unwrap();
+                writeln!(source, "    i32.const {}", failure).unwrap();
+            },
+            (None, None) => {},
+        };
+        source.push_str("    local.set $state\n    br $dispatch\n");
+    }
+
+    source.push_str("    end\n    unreachable)\n)\n");
+    source
+}
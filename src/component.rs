@@ -1,5 +1,7 @@
 use std::collections::VecDeque;
 use super::instruction::*;
+use super::hangul::{CODA_JAMO, compose};
+use super::encoding::{AheuiEncoding, AheuiDecodeError, decode_source};
 use std::{io, fmt, error};
 
 // enum for error handling
@@ -7,66 +9,183 @@ use std::{io, fmt, error};
 pub enum AheuiError {
     StorageSizeError, // error when insufficient num of elements in storage for operation
     TerminatedError, // error when executing after termination
-    InstructionNotFoundError, // error when 
+    InstructionNotFoundError, // error when the position holds no instruction
     EmptyInputError,
     ArithmeticError(isize, isize), // error when arithmetic operation leads to (over/under)flow
     InputError(io::Error),
     OutputError(io::Error),
-    InvalidCharError(u32),
+    InvalidCharError(isize, AheuiEncoding), // value popped to output which has no encoding
     InvalidNumberError(String),
+    EndOfInput, // error when input is read after it has run out
+    InfiniteLoopError(usize, Vec<AheuiCoordinates>), // state repeats with a period, through the cells visited
+    StorageUnderflow(AheuiOperation, usize, usize), // operation, storage index and depth in strict mode
 }
 
 impl fmt::Display for AheuiError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let error = match self {
             AheuiError::ArithmeticError(a,b) => format!("fatal: overflow/underflow occurred with arithmetic operation between {} and {}", a, b),
-            AheuiError::InputError(err) => err.to_string(),
-            AheuiError::OutputError(err) => err.to_string(),
-            AheuiError::InvalidCharError(num) => format!("fatal: attempted to pop invalid UTF-8 value {} to output", num),
-            AheuiError::InvalidNumberError(num) => format!("non-number input: {}", num),
+            AheuiError::StorageSizeError => "not enough values in storage".to_string(),
+            AheuiError::TerminatedError => "program has already terminated".to_string(),
+            AheuiError::InstructionNotFoundError => "fatal: no instruction at the current position".to_string(),
+            AheuiError::InputError(_) => "failed to read input".to_string(),
+            AheuiError::OutputError(_) => "failed to write output".to_string(),
+            AheuiError::InvalidCharError(num, encoding) => format!("fatal: attempted to pop value {} to output, which can not be encoded in {}", num, encoding),
+            AheuiError::InvalidNumberError(num) => format!("non-number input: {}", num.trim_end()),
             AheuiError::EmptyInputError => "no input provided".to_string(),
-            _ => "".to_string(),
+            AheuiError::EndOfInput => "fatal: reached the end of input".to_string(),
+            AheuiError::InfiniteLoopError(period, cells) => {
+                let cells: Vec<String> = cells.iter().map(|cell| format!("({}, {})", cell.x, cell.y)).collect();
+                format!("fatal: program repeats its state every {} steps and will never terminate, looping through {}", period, cells.join(" "))
+            },
+            AheuiError::StorageUnderflow(operation, index, depth) => format!(
+                "fatal: {:?} needs {} values, but storage {} holds {}",
+                operation, operation.required_depth(), storage_name(*index), depth,
+            ),
         };
         f.write_str(&error)
     }
 }
 
-impl error::Error for AheuiError {}
+impl error::Error for AheuiError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            AheuiError::InputError(err) | AheuiError::OutputError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// error raised while running a program, with where and when it happened
+#[derive(Debug)]
+pub struct AheuiRuntimeError {
+    pub error: AheuiError,
+    pub position: AheuiCoordinates,
+    pub character: Option<char>, // None if the position holds no instruction
+    pub direction: AheuiDirection,
+    pub step: usize, // number of the failing step, counting from 1
+    pub storage_index: usize,
+}
+
+impl fmt::Display for AheuiRuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at ({}, {})", self.error, self.position.x, self.position.y)?;
+        if let Some(character) = self.character {
+            write!(f, " {}", character)?;
+        }
+        write!(
+            f, " moving {:?} on step {}, with storage {} selected",
+            self.direction, self.step, storage_name(self.storage_index),
+        )
+    }
+}
+
+impl AheuiRuntimeError {
+    // rows of the program around the error, with carets under the failing cell
+    pub fn excerpt(&self, program: &AheuiProgram) -> String {
+        let first = self.position.y.saturating_sub(1);
+        let last = self.position.y + 1;
+        let gutter = (last + 1).to_string().len();
+
+        let mut lines = vec![format!("{:gutter$} |", "")];
+        for (y, row) in program.program.iter().enumerate().take(last + 1).skip(first) {
+            let text: String = row
+                .iter()
+                .map(|instruction| if instruction.character.is_control() { ' ' } else { instruction.character })
+                .collect();
+            lines.push(format!("{:>gutter$} | {}", y + 1, text.trim_end()));
+
+            if y == self.position.y {
+                let offset: usize = row.iter().take(self.position.x).map(|instruction| display_width(instruction.character)).sum();
+                let width = row.get(self.position.x).map_or(1, |instruction| display_width(instruction.character));
+                lines.push(format!("{:gutter$} | {}{}", "", " ".repeat(offset), "^".repeat(width)));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+// columns a character takes up in a terminal, with hangul and other east asian
+// wide characters taking two
+fn display_width(c: char) -> usize {
+    match c {
+        '\u{1100}'..='\u{115F}' | '\u{2E80}'..='\u{A4CF}' | '\u{AC00}'..='\u{D7A3}' |
+        '\u{F900}'..='\u{FAFF}' | '\u{FF00}'..='\u{FF60}' => 2,
+        _ => 1,
+    }
+}
+
+impl error::Error for AheuiRuntimeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+// name of a storage by its final consonant, for messages
+pub fn storage_name(index: usize) -> &'static str {
+    match index {
+        0 => "∅",
+        n => CODA_JAMO[n],
+    }
+}
+
+// backing data for a storage unit
+// stacks keep their top at the end of a Vec so that
+// the values can be shared with native code,
+// queues keep their front at the front of a VecDeque
+#[derive(Debug)]
+enum StorageData {
+    Stack(Vec<isize>),
+    Queue(VecDeque<isize>),
+}
 
 // struct for individual stack/queue
 #[derive(Debug)]
 pub struct AheuiStorage {
-    storage: VecDeque<isize>,
-    queue: bool,
+    storage: StorageData,
 }
 
 impl AheuiStorage {
     // create a new storage unit
     pub fn new(queue: bool) -> Self {
+        let storage = if queue {
+            StorageData::Queue(VecDeque::new())
+        } else {
+            StorageData::Stack(Vec::new())
+        };
+
         Self {
-            storage: VecDeque::new(),
-            queue,
+            storage,
         }
     }
 
     // push a value to the storage
-    // push_back for stack,
-    // push_front for queue
+    // to the top for stack,
+    // to the back for queue
     pub fn push(&mut self, data: isize) {
-        if self.queue {
-            self.storage.push_back(data);
-        } else {
-            self.storage.push_front(data);
-        }
+        match &mut self.storage {
+            StorageData::Stack(stack) => stack.push(data),
+            StorageData::Queue(queue) => queue.push_back(data),
+        };
     }
 
     // pop a value from storage
     pub fn pop(&mut self) -> Option<isize> {
-        self.storage.pop_front()
+        match &mut self.storage {
+            StorageData::Stack(stack) => stack.pop(),
+            StorageData::Queue(queue) => queue.pop_front(),
+        }
+    }
+
+    // push a value to the end values are popped from
+    fn push_front(&mut self, data: isize) {
+        match &mut self.storage {
+            StorageData::Stack(stack) => stack.push(data),
+            StorageData::Queue(queue) => queue.push_front(data),
+        };
     }
 
     // swap the top two values of storage
-    // TODO: make use of AheuiError dropped in lib
     pub fn swap(&mut self) -> Result<(), AheuiError> {
         if self.len() < 2 {
             return Err(AheuiError::StorageSizeError);
@@ -74,32 +193,69 @@ impl AheuiStorage {
 
         let val1 = self.pop().unwrap();
         let val2 = self.pop().unwrap();
-        self.storage.push_front(val1);
-        self.storage.push_front(val2);
+        self.push_front(val1);
+        self.push_front(val2);
 
         Ok(())
     }
 
     // duplicate the first element in storage
-    // TODO: make use of AheuiError dropped in lib
     pub fn duplicate(&mut self) -> Result<(), AheuiError> {
         let num = self.peek().ok_or(AheuiError::StorageSizeError)?;
-        self.storage.push_front(*num);
+        self.push_front(*num);
 
         Ok(())
     }
 
     pub fn len(&self) -> usize {
-        self.storage.len()
+        match &self.storage {
+            StorageData::Stack(stack) => stack.len(),
+            StorageData::Queue(queue) => queue.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_queue(&self) -> bool {
+        matches!(self.storage, StorageData::Queue(_))
     }
-    
+
     pub fn peek(&self) -> Option<&isize> {
-        self.storage.get(0)
+        match &self.storage {
+            StorageData::Stack(stack) => stack.last(),
+            StorageData::Queue(queue) => queue.front(),
+        }
+    }
+
+    // get the values of storage in the order they would be popped
+    pub fn values(&self) -> Vec<isize> {
+        self.iter().copied().collect()
+    }
+
+    // iterate over the values of storage in the order they would be popped,
+    // without copying them
+    pub fn iter(&self) -> impl Iterator<Item = &isize> {
+        let (stack, queue) = match &self.storage {
+            StorageData::Stack(stack) => (Some(stack.iter().rev()), None),
+            StorageData::Queue(queue) => (None, Some(queue.iter())),
+        };
+        stack.into_iter().flatten().chain(queue.into_iter().flatten())
+    }
+
+    // get the backing Vec of a stack, with its top at the end
+    // returns None for queues
+    pub fn stack_mut(&mut self) -> Option<&mut Vec<isize>> {
+        match &mut self.storage {
+            StorageData::Stack(stack) => Some(stack),
+            StorageData::Queue(_) => None,
+        }
     }
 }
 
 // storage struct for aheui program coordinates
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct AheuiCoordinates {
     pub x: usize,
     pub y: usize,
@@ -115,40 +271,44 @@ impl AheuiCoordinates {
 }
 
 // struct for holding 2D table of AheuiInstructions
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AheuiProgram {
     pub program: Vec<Vec<AheuiInstruction>>,
     pub size: AheuiCoordinates
 }
 
 impl AheuiProgram {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
         let mut program: Vec<Vec<AheuiInstruction>> = Vec::new();
 
-        // track rows and columns of programs
-        let mut rows = 0;
-        let mut max_col_length = 0;
-
         // convert each line's characters into AheuiInstructions
-        // and keep track of which line has the most characters
         for line in s.lines() {
             program.push(Vec::new());
             let current_row = program.last_mut().unwrap();
 
-            // create an AheuiInstruction for each character in line
-            for c in line.trim().chars() {
+            // create an AheuiInstruction for each character in line,
+            // after composing decomposed syllables
+            for c in compose(line.trim()).chars() {
                 let aheui_cmd = AheuiInstruction::from_char(c);
                 current_row.push(aheui_cmd);
             }
+        }
 
-            // update max_col_length accordingly
-            let num_chars = line.trim().chars().count();
-            if num_chars > max_col_length {
-                max_col_length = num_chars;
-            }
+        Self::from_rows(program)
+    }
 
-            rows += 1;
-        }
+    // decode a program from the bytes of a source file,
+    // detecting their encoding unless one is given
+    pub fn from_bytes(bytes: &[u8], encoding: Option<AheuiEncoding>) -> Result<Self, AheuiDecodeError> {
+        Ok(Self::from_str(&decode_source(bytes, encoding)?))
+    }
+
+    // create a program from rows of already decoded AheuiInstructions
+    pub fn from_rows(rows: Vec<Vec<AheuiInstruction>>) -> Self {
+        let mut program = rows;
+        let max_col_length = program.iter().map(|row| row.len()).max().unwrap_or(0);
+        let rows = program.len();
 
         // iterate through each row of AheuiInstructions and
         // add empty AheuiInstructions until each row has max_col_length items
@@ -167,6 +327,31 @@ impl AheuiProgram {
         }
     }
 
+    // write the program back out as source, one line per row
+    // blank cells before an instruction become null syllables
+    // so that the row is not trimmed when read again, as do those
+    // ending the first row when no row would keep the program as wide
+    pub fn to_source(&self) -> String {
+        let null = AheuiInstruction::encode(AheuiOperation::Null, AheuiDirection::Null, AheuiArgument::Null)[0];
+        let lines: Vec<String> = self.program
+            .iter()
+            .map(|row| row.iter().map(|instruction| instruction.character).collect::<String>().trim_end().to_string())
+            .collect();
+        let narrowed = lines.iter().all(|line| line.chars().count() < self.size.x);
+
+        let mut source = String::new();
+        for (y, line) in lines.iter().enumerate() {
+            let indent = line.chars().take_while(|c| c.is_whitespace()).count();
+            source.extend(std::iter::repeat_n(null, indent));
+            source.push_str(line.trim_start());
+            if y == 0 && narrowed {
+                source.extend(std::iter::repeat_n(null, self.size.x - line.chars().count()));
+            }
+            source.push('\n');
+        }
+        source
+    }
+
     pub fn get_instruction(&self, coords: &AheuiCoordinates) -> Option<&AheuiInstruction> {
         // attempt to get row
         let row = self.program.get(coords.y)?;
@@ -174,4 +359,46 @@ impl AheuiProgram {
         // attempt to return instruction in row
         row.get(coords.x)
     }
+
+    // get the position and direction after leaving the cell at position,
+    // moving in direction after applying the cell's instruction direction
+    pub fn next_position(&self, position: AheuiCoordinates, direction: AheuiDirection, new_direction: AheuiDirection) -> (AheuiCoordinates, AheuiDirection) {
+        let mut final_direction = direction;
+
+        // either reflect the program's current direction
+        // or update it to the current instruction's direction
+        // or do nothing (in case of null instruction)
+        match new_direction {
+            AheuiDirection::Null => {},
+            AheuiDirection::ReflectX => final_direction.reflect_x(),
+            AheuiDirection::ReflectY => final_direction.reflect_y(),
+            AheuiDirection::ReflectXY => final_direction.reflect_xy(),
+            _ => final_direction = new_direction,
+        };
+
+        // update position based on final_direction,
+        // wrapping around to the other side of the program
+        let mut position = position;
+        match final_direction {
+            AheuiDirection::Left(b) => {
+                let movement = (1 + b as usize) % self.size.x;
+                position.x = (position.x + self.size.x - movement) % self.size.x;
+            },
+            AheuiDirection::Right(b) => {
+                let movement = 1 + b as usize;
+                position.x = (position.x + movement) % self.size.x;
+            },
+            AheuiDirection::Up(b) => {
+                let movement = (1 + b as usize) % self.size.y;
+                position.y = (position.y + self.size.y - movement) % self.size.y;
+            },
+            AheuiDirection::Down(b) => {
+                let movement = 1 + b as usize;
+                position.y = (position.y + movement) % self.size.y;
+            },
+            _ => {},
+        };
+
+        (position, final_direction)
+    }
 }
@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use std::fmt;
+use crate::instruction::{AheuiArgument, AheuiDirection, AheuiInstruction, AheuiOperation};
+use crate::component::{AheuiCoordinates, AheuiProgram};
+
+// free rows and columns kept past the fragments for routing around them
+const MARGIN: usize = 2;
+
+// error for fragments which can not be put together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AheuiComposeError {
+    Overlap(usize, usize), // indices of two fragments taking up the same cells
+    InvalidDirection(usize), // fragment entered or left other than up, down, left or right
+    NoExit(usize), // fragment before another which never leaves
+    NoRoute(usize), // fragment which can not be reached from the one before
+}
+
+impl fmt::Display for AheuiComposeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AheuiComposeError::Overlap(a, b) => write!(f, "fragments {} and {} overlap", a, b),
+            AheuiComposeError::InvalidDirection(i) => write!(f, "fragment {} is entered or left in no direction", i),
+            AheuiComposeError::NoExit(i) => write!(f, "fragment {} has no exit", i),
+            AheuiComposeError::NoRoute(i) => write!(f, "no route leads to fragment {}", i),
+        }
+    }
+}
+
+// a program placed on the canvas with its top left at offset,
+// entered at a cell moving in a direction, like a whole program is at its
+// top left moving down, and left by moving on in a direction from a cell
+// positions are relative to the fragment
+pub struct AheuiFragment {
+    pub program: AheuiProgram,
+    pub offset: AheuiCoordinates,
+    pub entry: (AheuiCoordinates, AheuiDirection),
+    pub exit: Option<(AheuiCoordinates, AheuiDirection)>,
+}
+
+impl AheuiFragment {
+    // a fragment entered like a program and which never leaves
+    pub fn new(program: AheuiProgram, offset: AheuiCoordinates) -> Self {
+        Self {
+            program,
+            offset,
+            entry: (AheuiCoordinates::zero(), AheuiDirection::Down(false)),
+            exit: None,
+        }
+    }
+
+    fn contains(&self, x: usize, y: usize) -> bool {
+        (self.offset.x..self.offset.x + self.program.size.x).contains(&x) &&
+        (self.offset.y..self.offset.y + self.program.size.y).contains(&y)
+    }
+}
+
+// step and distance moved by a direction
+fn vector(direction: AheuiDirection) -> Option<(isize, isize, usize)> {
+    match direction {
+        AheuiDirection::Up(b) => Some((0, -1, 1 + b as usize)),
+        AheuiDirection::Down(b) => Some((0, 1, 1 + b as usize)),
+        AheuiDirection::Left(b) => Some((-1, 0, 1 + b as usize)),
+        AheuiDirection::Right(b) => Some((1, 0, 1 + b as usize)),
+        _ => None,
+    }
+}
+
+// null instruction which turns towards a step, moving two cells at a time if fast
+fn turn(dx: isize, dy: isize, fast: bool) -> AheuiInstruction {
+    let direction = match (dx, dy) {
+        (0, -1) => AheuiDirection::Up(fast),
+        (0, 1) => AheuiDirection::Down(fast),
+        (-1, 0) => AheuiDirection::Left(fast),
+        _ => AheuiDirection::Right(fast),
+    };
+    AheuiInstruction::from_char(AheuiInstruction::encode(AheuiOperation::Null, direction, AheuiArgument::Null)[0])
+}
+
+// cells of the canvas, which are free until taken by a fragment or a route
+struct Canvas {
+    cells: Vec<Vec<Option<AheuiInstruction>>>,
+    size: AheuiCoordinates,
+}
+
+impl Canvas {
+    fn get(&self, x: isize, y: isize) -> Option<&Option<AheuiInstruction>> {
+        self.cells.get(usize::try_from(y).ok()?)?.get(usize::try_from(x).ok()?)
+    }
+
+    fn is_free(&self, x: isize, y: isize) -> bool {
+        matches!(self.get(x, y), Some(None))
+    }
+
+    // lead from a free cell, which is moved onto in direction (dx, dy) from
+    // distance cells away, into a fragment's entry, turning with null
+    // instructions on free cells
+    fn route(&mut self, start: (isize, isize), (dx, dy, distance): (isize, isize, usize), fragment: &AheuiFragment) -> Option<()> {
+        let (entry, direction) = fragment.entry;
+        let (ex, ey, speed) = vector(direction)?;
+        let target = ((fragment.offset.x + entry.x) as isize, (fragment.offset.y + entry.y) as isize);
+        // arriving from any side and at any speed will do when the entry turns
+        // by itself. otherwise a fast entry is jumped to from two cells away,
+        // over a cell which may be taken
+        let turns = fragment.program.get_instruction(&entry).and_then(|instruction| vector(instruction.direction)).is_some();
+        let fast = !turns && speed == 2;
+        let arrives = |(x, y): (isize, isize), (dx, dy): (isize, isize)| match turns {
+            true => (x + dx, y + dy) == target,
+            false => (x + dx * speed as isize, y + dy * speed as isize) == target && (dx, dy) == (ex, ey),
+        };
+
+        if start == target {
+            return (turns || (dx, dy, distance) == (ex, ey, speed)).then_some(());
+        }
+        if !self.is_free(start.0, start.1) {
+            return None;
+        }
+
+        // breadth first search over free cells for the shortest route
+        let mut previous = vec![vec![None; self.size.x]; self.size.y];
+        previous[start.1 as usize][start.0 as usize] = Some(start);
+        let mut queue = VecDeque::from([start]);
+        while let Some((x, y)) = queue.pop_front() {
+            let step = [(1, 0), (0, 1), (-1, 0), (0, -1)].into_iter().find(|step| arrives((x, y), *step));
+            if let Some((sx, sy)) = step {
+                // walk back from the last cell, turning each towards the next
+                self.cells[y as usize][x as usize] = Some(turn(sx, sy, fast));
+                let mut cell = (x, y);
+                while cell != start {
+                    let before = previous[cell.1 as usize][cell.0 as usize].unwrap();
+                    self.cells[before.1 as usize][before.0 as usize] = Some(turn(cell.0 - before.0, cell.1 - before.1, false));
+                    cell = before;
+                }
+                return Some(());
+            }
+            for (sx, sy) in [(1, 0), (0, 1), (-1, 0), (0, -1)] {
+                let (nx, ny) = (x + sx, y + sy);
+                if self.is_free(nx, ny) && previous[ny as usize][nx as usize].is_none() {
+                    previous[ny as usize][nx as usize] = Some((x, y));
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        None
+    }
+}
+
+// place fragments on one grid, running them in order
+//
+// execution is routed from the top left to the entry of the first fragment, and
+// from the exit of each fragment to the entry of the next, through null instructions
+// with direction vowels on cells no fragment takes up. fragments should not rely on
+// wrapping around their own edges, as they are no longer at the edges of the grid
+pub fn compose(fragments: &[AheuiFragment]) -> Result<AheuiProgram, AheuiComposeError> {
+    let overlaps = |a: &AheuiFragment, b: &AheuiFragment| {
+        a.offset.x < b.offset.x + b.program.size.x && b.offset.x < a.offset.x + a.program.size.x &&
+        a.offset.y < b.offset.y + b.program.size.y && b.offset.y < a.offset.y + a.program.size.y
+    };
+    for (i, a) in fragments.iter().enumerate() {
+        for (j, b) in fragments.iter().enumerate().skip(i + 1) {
+            if overlaps(a, b) {
+                return Err(AheuiComposeError::Overlap(i, j));
+            }
+        }
+    }
+
+    let size = AheuiCoordinates {
+        x: fragments.iter().map(|fragment| fragment.offset.x + fragment.program.size.x).max().unwrap_or(0) + MARGIN,
+        y: fragments.iter().map(|fragment| fragment.offset.y + fragment.program.size.y).max().unwrap_or(0) + MARGIN,
+    };
+    let mut canvas = Canvas {
+        cells: vec![vec![None; size.x]; size.y],
+        size,
+    };
+    for fragment in fragments.iter() {
+        for (y, row) in fragment.program.program.iter().enumerate() {
+            for (x, instruction) in row.iter().enumerate() {
+                canvas.cells[fragment.offset.y + y][fragment.offset.x + x] = Some(*instruction);
+            }
+        }
+    }
+
+    // execution starts on the top left cell moving down
+    let mut start = ((0, 0), (0, 1, 1));
+    for (i, fragment) in fragments.iter().enumerate() {
+        if vector(fragment.entry.1).is_none() {
+            return Err(AheuiComposeError::InvalidDirection(i));
+        }
+        canvas.route(start.0, start.1, fragment).ok_or(AheuiComposeError::NoRoute(i))?;
+        if i + 1 == fragments.len() {
+            break;
+        }
+
+        let (exit, direction) = fragment.exit.ok_or(AheuiComposeError::NoExit(i))?;
+        let (dx, dy, distance) = vector(direction).ok_or(AheuiComposeError::InvalidDirection(i))?;
+        let x = (fragment.offset.x + exit.x) as isize + dx * distance as isize;
+        let y = (fragment.offset.y + exit.y) as isize + dy * distance as isize;
+        if x >= 0 && y >= 0 && fragment.contains(x as usize, y as usize) {
+            return Err(AheuiComposeError::NoRoute(i + 1));
+        }
+        start = ((x, y), (dx, dy, distance));
+    }
+
+    // leave out the margin where no route went through it
+    let mut rows: Vec<Vec<AheuiInstruction>> = canvas.cells
+        .into_iter()
+        .map(|row| {
+            let length = row.iter().rposition(|cell| cell.is_some()).map_or(0, |x| x + 1);
+            row[..length].iter().map(|cell| cell.unwrap_or(AheuiInstruction::null())).collect()
+        })
+        .collect();
+    while rows.last().is_some_and(|row| row.is_empty()) {
+        rows.pop();
+    }
+    Ok(AheuiProgram::from_rows(rows))
+}
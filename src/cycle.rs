@@ -0,0 +1,130 @@
+use std::io;
+use crate::instruction::*;
+use crate::component::*;
+use crate::AheuiState;
+
+// everything that decides how a program continues, apart from its input
+struct Snapshot {
+    position: AheuiCoordinates,
+    direction: AheuiDirection,
+    storage_index: usize,
+    lengths: Vec<usize>,
+    values: Vec<Vec<isize>>,
+}
+
+impl Snapshot {
+    fn new(state: &AheuiState) -> Self {
+        Self {
+            position: state.position,
+            direction: state.direction,
+            storage_index: state.storage_index,
+            lengths: state.storages.iter().map(|storage| storage.len()).collect(),
+            values: state.storages.iter().map(|storage| storage.values()).collect(),
+        }
+    }
+
+    // compare the cheap parts of the state before the storage contents
+    fn matches(&self, state: &AheuiState) -> bool {
+        self.position == state.position
+            && self.direction == state.direction
+            && self.storage_index == state.storage_index
+            && self.lengths.iter().zip(state.storages.iter()).all(|(length, storage)| *length == storage.len())
+            && self.values.iter().zip(state.storages.iter()).all(|(values, storage)| values.iter().eq(storage.iter()))
+    }
+
+    // a state continuing from the snapshot with the settings of state,
+    // which reads nothing and writes nowhere
+    fn restore(&self, state: &AheuiState) -> AheuiState {
+        let mut restored = AheuiState::from_program(state.program.clone(), Box::new(io::empty()), Box::new(io::sink()));
+        restored.position = self.position;
+        restored.direction = self.direction;
+        restored.storage_index = self.storage_index;
+        restored.strict = state.strict;
+        restored.encoding = state.encoding;
+        // values are kept in the order they would be popped
+        for (storage, values) in restored.storages.iter_mut().zip(self.values.iter()) {
+            if storage.is_queue() {
+                values.iter().for_each(|value| storage.push(*value));
+            } else {
+                values.iter().rev().for_each(|value| storage.push(*value));
+            }
+        }
+        restored
+    }
+}
+
+// detects a program returning to exactly the same state, which means it will never terminate
+// a snapshot is taken at steps doubling in distance apart, and every state is compared with it,
+// so a loop is found within a few times its period once the program enters it
+// the cells of the loop are found by running one period again from the snapshot,
+// so nothing is kept about the steps in between
+pub struct AheuiLoopDetector {
+    saved: Option<Snapshot>,
+    power: usize,
+    length: usize,
+}
+
+impl Default for AheuiLoopDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AheuiLoopDetector {
+    pub fn new() -> Self {
+        Self {
+            saved: None,
+            power: 1,
+            length: 0,
+        }
+    }
+
+    // forget the states seen so far, such as after reading input
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    // check the state before a step, returning the period of the loop
+    // and the cells it visits if the state has been seen before
+    pub fn check(&mut self, state: &AheuiState) -> Option<(usize, Vec<AheuiCoordinates>)> {
+        // the same state may read different input
+        let reads_input = state.program.get_instruction(&state.position).is_some_and(|instruction| {
+            instruction.operation == AheuiOperation::Push
+                && matches!(instruction.argument, AheuiArgument::AsInt | AheuiArgument::AsChar)
+        });
+        if reads_input {
+            self.reset();
+            return None;
+        }
+
+        match &self.saved {
+            Some(saved) if saved.matches(state) => {
+                let mut replay = saved.restore(state);
+                let mut cells = Vec::new();
+                for _ in 0..self.length {
+                    if !cells.contains(&replay.position) {
+                        cells.push(replay.position);
+                    }
+                    if replay.step().is_err() {
+                        break;
+                    }
+                }
+                return Some((self.length, cells));
+            },
+            Some(_) if self.length < self.power => {},
+            Some(_) => {
+                self.power *= 2;
+                self.save(state);
+            },
+            None => self.save(state),
+        };
+
+        self.length += 1;
+        None
+    }
+
+    fn save(&mut self, state: &AheuiState) {
+        self.saved = Some(Snapshot::new(state));
+        self.length = 0;
+    }
+}
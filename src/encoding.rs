@@ -0,0 +1,321 @@
+use std::{fmt, error};
+
+// ways of turning values popped with ㅁ and ㅎ into output bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AheuiEncoding {
+    #[default]
+    Utf8,
+    Byte, // the low 8 bits of the value as a single byte
+    Utf16Le,
+    Utf16Be,
+    Cp949, // unified hangul code, covering every hangul syllable
+    EucKr, // the KS X 1001 subset of CP949, with 2350 hangul syllables
+}
+
+impl AheuiEncoding {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(AheuiEncoding::Utf8),
+            "byte" => Some(AheuiEncoding::Byte),
+            "utf-16le" | "utf16le" => Some(AheuiEncoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(AheuiEncoding::Utf16Be),
+            "cp949" | "uhc" => Some(AheuiEncoding::Cp949),
+            "euc-kr" | "euckr" => Some(AheuiEncoding::EucKr),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AheuiEncoding::Utf8 => "UTF-8",
+            AheuiEncoding::Byte => "byte",
+            AheuiEncoding::Utf16Le => "UTF-16LE",
+            AheuiEncoding::Utf16Be => "UTF-16BE",
+            AheuiEncoding::Cp949 => "CP949",
+            AheuiEncoding::EucKr => "EUC-KR",
+        }
+    }
+
+    // character a value stands for, or None if it has no encoding
+    // in byte mode every value stands for the latin-1 character of its low 8 bits
+    pub fn character(&self, value: isize) -> Option<char> {
+        if *self == AheuiEncoding::Byte {
+            return Some(char::from((value & 0xFF) as u8));
+        }
+        let character = u32::try_from(value).ok().and_then(char::from_u32)?;
+        match self {
+            AheuiEncoding::Cp949 | AheuiEncoding::EucKr => cp949_encode(character, *self == AheuiEncoding::Cp949).map(|_| character),
+            _ => Some(character),
+        }
+    }
+
+    // bytes to write for a value, or None if it has no encoding
+    pub fn encode(&self, value: isize) -> Option<Vec<u8>> {
+        let character = self.character(value)?;
+        let bytes = match self {
+            AheuiEncoding::Utf8 => character.to_string().into_bytes(),
+            AheuiEncoding::Byte => vec![(value & 0xFF) as u8],
+            AheuiEncoding::Utf16Le => character.encode_utf16(&mut [0; 2]).iter().flat_map(|unit| unit.to_le_bytes()).collect(),
+            AheuiEncoding::Utf16Be => character.encode_utf16(&mut [0; 2]).iter().flat_map(|unit| unit.to_be_bytes()).collect(),
+            AheuiEncoding::Cp949 => cp949_encode(character, true)?,
+            AheuiEncoding::EucKr => cp949_encode(character, false)?,
+        };
+        Some(bytes)
+    }
+
+    // guess the encoding of a source file from its byte order mark,
+    // or failing that from the encodings its bytes make sense in
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            return AheuiEncoding::Utf8;
+        } else if bytes.starts_with(&[0xFF, 0xFE]) {
+            return AheuiEncoding::Utf16Le;
+        } else if bytes.starts_with(&[0xFE, 0xFF]) {
+            return AheuiEncoding::Utf16Be;
+        }
+
+        // only UTF-16 puts NUL bytes next to ASCII characters such as newlines,
+        // after them when little endian
+        if bytes.len().is_multiple_of(2) && bytes.contains(&0) {
+            let odd = bytes.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+            let even = bytes.iter().step_by(2).filter(|b| **b == 0).count();
+            return if odd >= even { AheuiEncoding::Utf16Le } else { AheuiEncoding::Utf16Be };
+        }
+
+        [AheuiEncoding::Utf8, AheuiEncoding::Cp949, AheuiEncoding::Utf16Le]
+            .into_iter()
+            .find(|encoding| encoding.decode(bytes).is_ok())
+            .unwrap_or(AheuiEncoding::Utf8)
+    }
+
+    // decode source text, leaving out any byte order mark
+    // CP949 and EUC-KR characters other than ASCII, hangul syllables and
+    // compatibility jamo are replaced with U+FFFD
+    pub fn decode(&self, bytes: &[u8]) -> Result<String, AheuiDecodeError> {
+        let error = |offset| AheuiDecodeError { encoding: *self, offset };
+        match self {
+            AheuiEncoding::Utf8 => {
+                let text = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+                let skipped = bytes.len() - text.len();
+                std::str::from_utf8(text)
+                    .map(|s| s.to_string())
+                    .map_err(|e| error(skipped + e.valid_up_to()))
+            },
+            AheuiEncoding::Byte => Ok(bytes.iter().map(|b| char::from(*b)).collect()),
+            AheuiEncoding::Utf16Le | AheuiEncoding::Utf16Be => {
+                if !bytes.len().is_multiple_of(2) {
+                    return Err(error(bytes.len() - 1));
+                }
+                let units = bytes.chunks(2).map(|pair| match self {
+                    AheuiEncoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                });
+
+                let mut decoded = String::new();
+                let mut offset = 0;
+                for character in char::decode_utf16(units) {
+                    let character = character.map_err(|_| error(offset))?;
+                    if !(offset == 0 && character == '\u{FEFF}') {
+                        decoded.push(character);
+                    }
+                    offset += 2 * character.len_utf16();
+                }
+                Ok(decoded)
+            },
+            AheuiEncoding::Cp949 | AheuiEncoding::EucKr => {
+                let extended = *self == AheuiEncoding::Cp949;
+                let mut decoded = String::new();
+                let mut offset = 0;
+                while offset < bytes.len() {
+                    let lead = bytes[offset];
+                    if lead < 0x80 {
+                        decoded.push(char::from(lead));
+                        offset += 1;
+                        continue;
+                    }
+                    let trail = *bytes.get(offset + 1).ok_or(error(offset))?;
+                    let character = cp949_decode(lead, trail, extended).ok_or(error(offset))?;
+                    decoded.push(character);
+                    offset += 2;
+                }
+                Ok(decoded)
+            },
+        }
+    }
+}
+
+// decode a source file, detecting its encoding unless one is given
+pub fn decode_source(bytes: &[u8], encoding: Option<AheuiEncoding>) -> Result<String, AheuiDecodeError> {
+    encoding.unwrap_or_else(|| AheuiEncoding::detect(bytes)).decode(bytes)
+}
+
+// error for source bytes which are not valid in an encoding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AheuiDecodeError {
+    pub encoding: AheuiEncoding,
+    pub offset: usize, // byte offset the invalid sequence starts at
+}
+
+impl fmt::Display for AheuiDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid {} at byte {}", self.encoding, self.offset)
+    }
+}
+
+impl error::Error for AheuiDecodeError {}
+
+impl fmt::Display for AheuiEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// hangul syllables from 가 included in KS X 1001, one bit each
+// these are encoded from B0A1 in order, and the rest from 8141 in order
+const KS_X_1001_SYLLABLES: [u64; 175] = [
+    0x1303B0113EFF0793, 0x0593000011102801, 0x3B019703B0111E7B, 0x306B959300A01112,
+    0x113032011102B051, 0xB879300A011102B0, 0x0080001030011306, 0x93000011100B0113,
+    0x0593000000102B03, 0x3B011323B051746B, 0x7000000000001030, 0x111029001303B011,
+    0xB015300000012180, 0x020000303001030E, 0x1300000010230111, 0x0113030010106B81,
+    0x0000010030111013, 0x3000000022B85530, 0x113AFB079702B011, 0x00000021011303B0,
+    0x03B011383B0D1B00, 0x1300000111330113, 0x00000100111C2B05, 0x2A011300B0111000,
+    0x1010000102B01930, 0x1030030111000000, 0x0011146B07130230, 0x8FB8F9742B051300,
+    0x00000000103B0113, 0x01134AB0D9700000, 0x000011030011103B, 0x100001112AB15930,
+    0x00100B0111010000, 0x0000102B01130000, 0x02A0111020000101, 0x0102B05930210111,
+    0x011307B019300000, 0x00000003B011383B, 0x383B0D1300000000, 0x000010000103B011,
+    0x0010102001130000, 0x0000011000000100, 0x0002181130000000, 0x0111000000100000,
+    0x0B01930000000023, 0x302B011100301110, 0x01303B0113C7B011, 0xB011300000000280,
+    0x03B011302B011383, 0x1102B011300A0011, 0x0111010000002000, 0x2B011302A011102B,
+    0x3000000101000010, 0x11302B0113029011, 0xB0113000000066B0, 0x07B0113A6B07D302,
+    0x1300000000200103, 0x011303B011386B05, 0x2B051B00000010B8, 0x1000000003000110,
+    0x79700A011102A011, 0x0000100A0111A2B0, 0x0090111000011100, 0x9300000000090111,
+    0x011322B0F9F2BB05, 0x000000002001323B, 0x303B019306B05930, 0x117000001123A011,
+    0x00001010001102B0, 0x0000011003011301, 0x01010010162B0793, 0x0111020011300000,
+    0x00000000B0113029, 0x383B05130EB05130, 0x000001000303B011, 0x0000103901930000,
+    0x000000003B000302, 0x0000000000230113, 0x0001000000100000, 0x0000000290113020,
+    0x1000000000000000, 0x0000030111020000, 0xB079B02B01130000, 0x02B011303B011323,
+    0x1343B0D9F0210111, 0x011103B011303B01, 0x20011322B0517020, 0x300B011101901110,
+    0x0016AB019302B011, 0xB011302101130100, 0x02B0313029010302, 0x1B42B81930000000,
+    0x0000033011383301, 0x3305130000000020, 0x0000000000001110, 0x0130230593000001,
+    0x3011101000010100, 0x0230113000000100, 0x1100000010100001, 0x8513020000000000,
+    0x2B01130010111003, 0x303B011363B87730, 0x7B30020111A2B091, 0xF0D1702B011357F0,
+    0x0AB971301B0111E3, 0x13029001303B0113, 0x071302B011302B01, 0x230113033011302B,
+    0x30AB011302B01130, 0x7130090111FEB411, 0xB011307B05D347B8, 0x0000111021015303,
+    0x1102B011306B0513, 0x0513000000103301, 0x30000102A01038EB, 0x3020001302B01110,
+    0x001010000102B071, 0x1011100B01130000, 0x000000002B011300, 0x1303B095366B0593,
+    0x0000020001103B01, 0x20000103B0113000, 0x3000000001000010, 0x00101001030AB011,
+    0x0000000301110100, 0x0300001023011302, 0x0100000010000000, 0x0000029000100000,
+    0x7B01538630113000, 0x0021015103B01130, 0x11303B0113000000, 0x00011010001102B0,
+    0x020011102B011302, 0x0102B01110000000, 0x000102B011300100, 0x2B01110000011010,
+    0x002B011302101110, 0x11302B0393000000, 0x0000303B011302B0, 0x03B0193000000002,
+    0x0103B011102B0113, 0x011302B011300000, 0x0001010200001021, 0x102B011300000010,
+    0x1130200001020011, 0x30113001011102B0, 0x02B0113000000002, 0x0103B011303B0313,
+    0x0513000000002000, 0x10001102B011303B, 0x142B011300000110, 0x0110000001000001,
+    0xB011300000010280, 0x0000001010000102, 0x9302101110230113, 0x0113003011100B05,
+    0x3B011323B051702B, 0x3000000000000030, 0x11102B011303B011, 0xB011300A01010330,
+    0x0000000020000102, 0x9300A01110000011, 0x0000020000102B05, 0x2901110090111000,
+    0x3000000000B01110, 0x11302B211302B011, 0x00000020000103B0, 0x02B011302B051300,
+    0x13002011103B0113, 0x0013028011322B21, 0x0A011102A0113028, 0x3021011102921130,
+    0x11302B0113020011, 0x3011122B03D30290, 0x000000002B011302,
+];
+
+// trail bytes of the unified hangul code extension, only the first 84
+// being used once lead bytes reach A1
+const UHC_TRAIL_BYTES: [(u8, u8); 3] = [(0x41, 0x5A), (0x61, 0x7A), (0x81, 0xFE)];
+
+// whether the syllable at index from 가 is in KS X 1001,
+// with the number of syllables before it that are
+fn ks_x_1001_rank(index: usize) -> (bool, usize) {
+    let (word, bit) = (index / 64, index % 64);
+    let before = KS_X_1001_SYLLABLES[..word].iter().map(|bits| bits.count_ones() as usize).sum::<usize>()
+        + (KS_X_1001_SYLLABLES[word] & ((1 << bit) - 1)).count_ones() as usize;
+    (KS_X_1001_SYLLABLES[word] & (1 << bit) != 0, before)
+}
+
+// index from 가 of the syllable with a rank among those in KS X 1001,
+// or among those which are not
+fn ks_x_1001_select(rank: usize, included: bool) -> Option<usize> {
+    let mut rank = rank;
+    for (word, bits) in KS_X_1001_SYLLABLES.iter().enumerate() {
+        let bits = if included { *bits } else { !*bits };
+        let count = bits.count_ones() as usize;
+        if rank < count {
+            let bit = (0..64).filter(|bit| bits & (1 << bit) != 0).nth(rank)?;
+            let index = word * 64 + bit;
+            return if index < 11172 { Some(index) } else { None };
+        }
+        rank -= count;
+    }
+    None
+}
+
+// CP949 bytes of a character, limited to ASCII, hangul syllables and
+// hangul compatibility jamo
+// without the extension only the syllables of KS X 1001 can be encoded
+fn cp949_encode(character: char, extended: bool) -> Option<Vec<u8>> {
+    let code = character as u32;
+    match code {
+        0..=0x7F => Some(vec![code as u8]),
+        0x3131..=0x318E => Some(vec![0xA4, (0xA1 + code - 0x3131) as u8]),
+        0xAC00..=0xD7A3 => {
+            let index = (code - 0xAC00) as usize;
+            let (included, before) = ks_x_1001_rank(index);
+
+            if included {
+                Some(vec![(0xB0 + before / 94) as u8, (0xA1 + before % 94) as u8])
+            } else if extended {
+                // 178 trail bytes per lead byte up to A0, then 84
+                let mut rank = index - before;
+                let lead = if rank < 32 * 178 {
+                    let lead = 0x81 + rank / 178;
+                    rank %= 178;
+                    lead
+                } else {
+                    rank -= 32 * 178;
+                    let lead = 0xA1 + rank / 84;
+                    rank %= 84;
+                    lead
+                };
+                let trail = UHC_TRAIL_BYTES
+                    .iter()
+                    .flat_map(|(first, last)| *first..=*last)
+                    .nth(rank)?;
+                Some(vec![lead as u8, trail])
+            } else {
+                None
+            }
+        },
+        _ => None,
+    }
+}
+
+// character of a CP949 double byte sequence, U+FFFD for KS X 1001 characters
+// other than hangul, or None if the sequence is invalid
+// without the extension only sequences of KS X 1001 are valid
+fn cp949_decode(lead: u8, trail: u8, extended: bool) -> Option<char> {
+    if (0xA1..=0xFE).contains(&lead) && (0xA1..=0xFE).contains(&trail) {
+        let index = (trail - 0xA1) as usize;
+        let character = match lead {
+            0xA4 => char::from_u32(0x3131 + index as u32),
+            0xB0..=0xC8 => ks_x_1001_select((lead - 0xB0) as usize * 94 + index, true)
+                .and_then(|index| char::from_u32(0xAC00 + index as u32)),
+            _ => None,
+        };
+        return Some(character.unwrap_or('\u{FFFD}'));
+    }
+
+    // the rest of the syllables, in the extension
+    if !extended {
+        return None;
+    }
+    let trail_index = UHC_TRAIL_BYTES
+        .iter()
+        .flat_map(|(first, last)| *first..=*last)
+        .position(|byte| byte == trail)?;
+    let rank = match lead {
+        0x81..=0xA0 => (lead - 0x81) as usize * 178 + trail_index,
+        0xA1..=0xC6 if trail_index < 84 => 32 * 178 + (lead - 0xA1) as usize * 84 + trail_index,
+        _ => return None,
+    };
+    ks_x_1001_select(rank, false).and_then(|index| char::from_u32(0xAC00 + index as u32))
+}
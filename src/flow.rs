@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::instruction::*;
+use crate::component::*;
+
+// a node of the control flow graph: executing the instruction at position
+// after arriving there while moving in direction
+#[derive(Debug, Clone, Copy)]
+pub struct AheuiFlowNode {
+    pub position: AheuiCoordinates,
+    pub direction: AheuiDirection,
+    pub instruction: AheuiInstruction,
+    // node reached when the instruction succeeds, None for termination
+    pub success: Option<usize>,
+    // node reached when the instruction reflects, None if it never does
+    pub failure: Option<usize>,
+}
+
+// control flow graph of every state reachable from the start of a program
+#[derive(Debug)]
+pub struct AheuiFlowGraph {
+    pub nodes: Vec<AheuiFlowNode>,
+    index: HashMap<(AheuiCoordinates, AheuiDirection), usize>,
+}
+
+impl AheuiFlowGraph {
+    // initial state of every program
+    pub fn start() -> (AheuiCoordinates, AheuiDirection) {
+        (AheuiCoordinates::zero(), AheuiDirection::Down(false))
+    }
+
+    // build the graph by following every path from the start of program
+    pub fn from_program(program: &AheuiProgram) -> Self {
+        let mut graph = Self {
+            nodes: Vec::new(),
+            index: HashMap::new(),
+        };
+
+        let start = Self::start();
+        if program.get_instruction(&start.0).is_none() {
+            return graph;
+        }
+
+        graph.add_node(program, start);
+        let mut current = 0;
+        while current < graph.nodes.len() {
+            let node = graph.nodes[current];
+            let instruction = node.instruction;
+
+            // successful instructions follow their own direction
+            if instruction.can_succeed() {
+                let next = program.next_position(node.position, node.direction, instruction.direction);
+                graph.nodes[current].success = Some(graph.add_node(program, next));
+            }
+
+            // failed instructions reflect both the current and their own direction
+            if instruction.can_fail() {
+                let mut direction = node.direction;
+                direction.reflect_xy();
+                let mut inst_direction = instruction.direction;
+                inst_direction.reflect_xy();
+                let next = program.next_position(node.position, direction, inst_direction);
+                graph.nodes[current].failure = Some(graph.add_node(program, next));
+            }
+
+            current += 1;
+        }
+
+        graph
+    }
+
+    // get the index of a state's node, adding it if it is new
+    fn add_node(&mut self, program: &AheuiProgram, state: (AheuiCoordinates, AheuiDirection)) -> usize {
+        if let Some(index) = self.index.get(&state) {
+            return *index;
+        }
+
+        let instruction = *program
+            .get_instruction(&state.0)
+            .expect("reachable position outside of program");
+        self.nodes.push(AheuiFlowNode {
+            position: state.0,
+            direction: state.1,
+            instruction,
+            success: None,
+            failure: None,
+        });
+        self.index.insert(state, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    // get the index of the node for a state, if it is reachable
+    pub fn node_index(&self, position: AheuiCoordinates, direction: AheuiDirection) -> Option<usize> {
+        self.index.get(&(position, direction)).copied()
+    }
+}
+
+// reasons a program is rejected by validation
+#[derive(Debug, PartialEq, Eq)]
+pub enum AheuiValidationError {
+    EmptyProgram, // program contains no instructions
+    NoTermination, // no ㅎ instruction can be reached from the start
+}
+
+impl fmt::Display for AheuiValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let error = match self {
+            AheuiValidationError::EmptyProgram => "program contains no instructions",
+            AheuiValidationError::NoTermination => "program can never reach a termination (ㅎ) instruction",
+        };
+        f.write_str(error)
+    }
+}
+
+impl AheuiProgram {
+    // check that a program has instructions and can terminate
+    pub fn validate(&self) -> Result<(), AheuiValidationError> {
+        let graph = AheuiFlowGraph::from_program(self);
+        if graph.nodes.is_empty() {
+            return Err(AheuiValidationError::EmptyProgram);
+        }
+
+        let terminates = graph.nodes
+            .iter()
+            .any(|node| node.instruction.operation == AheuiOperation::Terminate);
+        if !terminates {
+            return Err(AheuiValidationError::NoTermination);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+use crate::hangul::HangulSyllable;
+use crate::instruction::AheuiInstruction;
+use crate::component::AheuiProgram;
+
+// blank cells are written as ideographic spaces,
+// which take up as many columns as a syllable does
+const BLANK: char = '\u{3000}';
+
+// the canonical character of a cell
+// syllables become the canonical syllable of the instruction they decode to,
+// so that null operations use ㅇ, null directions use ㅐ and final consonants
+// are left out or made the simplest one with the same meaning
+// other characters are kept, apart from whitespace
+pub fn canonical(instruction: &AheuiInstruction) -> char {
+    match instruction.character {
+        c if HangulSyllable::from_char(c).is_some() => {
+            AheuiInstruction::encode(instruction.operation, instruction.direction, instruction.argument)[0]
+        },
+        c if c.is_whitespace() => BLANK,
+        c => c,
+    }
+}
+
+// rewrite a program in canonical form, one line per row, leaving out
+// blank cells at the end of each row unless align pads every row to the width
+// of the program. the program runs exactly as it did before
+pub fn format(source: &str, align: bool) -> String {
+    let program = AheuiProgram::from_str(source);
+    let mut formatted = String::new();
+    for row in program.program.iter() {
+        let line: String = row.iter().map(canonical).collect();
+        if align {
+            formatted.push_str(&line);
+        } else {
+            formatted.push_str(line.trim_end());
+        }
+        formatted.push('\n');
+    }
+    formatted
+}
+
+// lines, counting from one, which formatting changes
+pub fn check(source: &str, align: bool) -> Vec<usize> {
+    let formatted = format(source, align);
+    let mut lines: Vec<usize> = source.split('\n')
+        .zip(formatted.split('\n'))
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(y, _)| y + 1)
+        .collect();
+    // missing or extra lines at the end
+    let (a, b) = (source.split('\n').count(), formatted.split('\n').count());
+    if a != b {
+        lines.push(a.min(b));
+    }
+    lines.dedup();
+    lines
+}
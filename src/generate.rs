@@ -0,0 +1,387 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+use crate::instruction::*;
+
+// instruction of a generated program before it is given a direction
+type Step = (AheuiOperation, AheuiArgument);
+
+const DUPLICATE: Step = (AheuiOperation::Duplicate, AheuiArgument::Null);
+const SWAP: Step = (AheuiOperation::Swap, AheuiArgument::Null);
+const PRINT: Step = (AheuiOperation::Pop, AheuiArgument::AsChar);
+// moving a value to an unused storage drops it, since popping it without an
+// argument reflects
+const DROP: Step = (AheuiOperation::StoreTransfer, AheuiArgument::Storage(1));
+
+// every literal which can be pushed
+const LITERALS: [usize; 9] = [0, 2, 3, 4, 5, 6, 7, 8, 9];
+
+const ARITHMETIC: [AheuiOperation; 5] = [
+    AheuiOperation::Add,
+    AheuiOperation::Multiply,
+    AheuiOperation::Divide,
+    AheuiOperation::Subtract,
+    AheuiOperation::Modulo,
+];
+
+// longest programs tried one by one, past which constants are built
+// by combining shorter ones
+const EXHAUSTIVE_LENGTH: usize = 8;
+
+// largest remainder added to or subtracted from a product
+const MAX_REMAINDER: isize = 9;
+
+// largest factor tried when dividing a constant into a product,
+// apart from its square root. past 24 bits it is the largest literal,
+// as there are too many ways to divide them
+const MAX_FACTOR: isize = 1024;
+
+fn push(n: usize) -> Step {
+    (AheuiOperation::Push, AheuiArgument::Number(n))
+}
+
+fn arithmetic(operation: AheuiOperation) -> Step {
+    (operation, AheuiArgument::Null)
+}
+
+// storages one step away from another, along with the step
+fn successors(storage: &[isize]) -> Vec<(Step, Vec<isize>)> {
+    let mut successors = Vec::new();
+    for n in LITERALS {
+        successors.push((push(n), [storage, &[n as isize]].concat()));
+    }
+    if let Some(top) = storage.last() {
+        successors.push((DUPLICATE, [storage, &[*top]].concat()));
+    }
+    if let [rest @ .., a, b] = storage {
+        successors.push((SWAP, [rest, &[*b, *a]].concat()));
+        for operation in ARITHMETIC {
+            // division by zero and overflow are fatal, so never tried
+            if let Some(value) = operation.arithmetic_operation(*a, *b) {
+                successors.push((arithmetic(operation), [rest, &[value]].concat()));
+            }
+        }
+    }
+    successors
+}
+
+// a constant along with the shortest instructions found which push it,
+// and their syllables moving right
+#[derive(Debug, Clone)]
+pub struct AheuiConstant {
+    pub value: isize,
+    pub instructions: Vec<AheuiInstruction>,
+    pub syllables: String,
+}
+
+impl AheuiConstant {
+    fn from_steps(value: isize, steps: &[Step]) -> Self {
+        let syllables: String = steps
+            .iter()
+            .map(|(operation, argument)| AheuiInstruction::encode(*operation, AheuiDirection::Right(false), *argument)[0])
+            .collect();
+        Self {
+            value,
+            instructions: syllables.chars().map(AheuiInstruction::from_char).collect(),
+            syllables,
+        }
+    }
+}
+
+// search for the shortest instructions pushing constants onto an empty storage,
+// using push, duplicate, swap and arithmetic
+//
+// every program up to EXHAUSTIVE_LENGTH instructions is tried, so constants those
+// can push get the shortest possible program. longer ones are built from a product
+// of two constants, the second of which may be made from a copy of the first,
+// plus or minus a remainder, searching every such split with memoization
+pub struct AheuiConstantSearch {
+    known: &'static HashMap<isize, Vec<Step>>,
+    // the shortest program found so far for other constants,
+    // and the longest limit no program was found within
+    found: HashMap<isize, Vec<Step>>,
+    failed: HashMap<isize, usize>,
+}
+
+impl Default for AheuiConstantSearch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// shortest programs for every constant which can be pushed by programs up to
+// EXHAUSTIVE_LENGTH instructions, found once by trying them all
+fn exhaustive() -> &'static HashMap<isize, Vec<Step>> {
+    static KNOWN: OnceLock<HashMap<isize, Vec<Step>>> = OnceLock::new();
+    KNOWN.get_or_init(|| {
+        // breadth first over the storages programs leave behind,
+        // keeping the state each was first reached from
+        let mut states: Vec<(Vec<isize>, usize, Step)> = vec![(Vec::new(), 0, DUPLICATE)];
+        let mut seen = HashSet::from([Vec::new()]);
+        let mut found = HashMap::new();
+        let mut frontier = 0..1;
+        for length in 1..=EXHAUSTIVE_LENGTH {
+            let start = states.len();
+            for parent in frontier {
+                for (step, storage) in successors(&states[parent].0) {
+                    // each value beyond one needs an instruction to combine it away
+                    if storage.len() + length > EXHAUSTIVE_LENGTH + 1 || !seen.insert(storage.clone()) {
+                        continue;
+                    }
+                    if let [value] = storage[..] {
+                        found.entry(value).or_insert(states.len());
+                    }
+                    states.push((storage, parent, step));
+                }
+            }
+            frontier = start..states.len();
+        }
+
+        found
+            .into_iter()
+            .map(|(value, mut index)| {
+                let mut steps = Vec::new();
+                while index != 0 {
+                    steps.push(states[index].2);
+                    index = states[index].1;
+                }
+                steps.reverse();
+                (value, steps)
+            })
+            .collect()
+    })
+}
+
+impl AheuiConstantSearch {
+    pub fn new() -> Self {
+        Self {
+            known: exhaustive(),
+            found: HashMap::new(),
+            failed: HashMap::new(),
+        }
+    }
+
+    pub fn find(&mut self, n: isize) -> AheuiConstant {
+        AheuiConstant::from_steps(n, &self.steps(n))
+    }
+
+    // deepen the limit on the length one at a time, so the first program
+    // found is the shortest of those the splits can build
+    fn steps(&mut self, n: isize) -> Vec<Step> {
+        (EXHAUSTIVE_LENGTH..).find_map(|limit| self.within(n, limit)).unwrap()
+    }
+
+    // instructions pushing n of at most the given length, if there are any
+    fn within(&mut self, n: isize, limit: usize) -> Option<Vec<Step>> {
+        if let Some(steps) = self.known.get(&n) {
+            return (steps.len() <= limit).then(|| steps.clone());
+        }
+        // anything left out of the exhaustive search takes longer than it
+        if limit <= EXHAUSTIVE_LENGTH || self.failed.get(&n).is_some_and(|failed| *failed >= limit) {
+            return None;
+        }
+        if let Some(steps) = self.found.get(&n).filter(|steps| steps.len() <= limit) {
+            return Some(steps.clone());
+        }
+
+        let mut best: Option<Vec<Step>> = None;
+        let consider = |best: &mut Option<Vec<Step>>, steps: Vec<Step>| {
+            if steps.len() <= limit && best.as_ref().is_none_or(|best| steps.len() < best.len()) {
+                *best = Some(steps);
+            }
+        };
+
+        if n < 0 {
+            // subtracted from a literal, or failing that
+            // from the nearest constant which can be
+            for a in LITERALS {
+                if let Some(b) = (a as isize).checked_sub(n) {
+                    if let Some(steps) = self.within(b, limit - 2) {
+                        consider(&mut best, [vec![push(a)], steps, vec![arithmetic(AheuiOperation::Subtract)]].concat());
+                    }
+                }
+            }
+            if n.checked_neg().is_none() {
+                if let Some(steps) = self.within(n + 2, limit - 2) {
+                    consider(&mut best, [steps, vec![push(2), arithmetic(AheuiOperation::Subtract)]].concat());
+                }
+            }
+        } else {
+            // n = a * b + r, or n = a * b - r
+            for r in -MAX_REMAINDER..=MAX_REMAINDER {
+                let product = match n.checked_sub(r) {
+                    Some(product) if product > 1 => product,
+                    _ => continue,
+                };
+                let remainder = match r {
+                    0 => Vec::new(),
+                    _ => [self.known[&r.abs()].clone(), vec![arithmetic(if r > 0 { AheuiOperation::Add } else { AheuiOperation::Subtract })]].concat(),
+                };
+                // leaving room for the multiplication and at least one instruction for b
+                let budget = match limit.checked_sub(remainder.len() + 1) {
+                    Some(budget) if budget >= 2 => budget,
+                    _ => continue,
+                };
+                let root = product.isqrt();
+                let max_factor = if product >= 1 << 24 { 9 } else { MAX_FACTOR };
+                let factors = (2..=max_factor.min(root)).chain((root > max_factor).then_some(root));
+                for a in factors.filter(|a| product % a == 0) {
+                    let b = product / a;
+                    let first = match self.within(a, budget - 1) {
+                        Some(first) => first,
+                        None => continue,
+                    };
+                    // b as an independent constant, or made from a copy of a
+                    let mut second = self.within(b, budget - first.len());
+                    let shared = match b - a {
+                        _ if b == a * a => Some(vec![DUPLICATE, DUPLICATE, arithmetic(AheuiOperation::Multiply)]),
+                        0 => Some(vec![DUPLICATE]),
+                        d if d.abs() <= MAX_REMAINDER => Some([vec![DUPLICATE], offset(d, self)].concat()),
+                        _ => None,
+                    };
+                    if let Some(shared) = shared {
+                        if second.as_ref().is_none_or(|second| shared.len() < second.len()) {
+                            second = Some(shared);
+                        }
+                    }
+                    let factors = second.map(|second| [first, second].concat());
+                    if let Some(factors) = factors {
+                        consider(&mut best, [factors, vec![arithmetic(AheuiOperation::Multiply)], remainder.clone()].concat());
+                    }
+                }
+            }
+        }
+
+        match best {
+            Some(best) => {
+                self.found.insert(n, best.clone());
+                Some(best)
+            },
+            None => {
+                self.failed.insert(n, limit);
+                None
+            },
+        }
+    }
+}
+
+// short instructions pushing a constant, which are the shortest possible for
+// constants pushed by at most EXHAUSTIVE_LENGTH instructions. past that they are
+// the shortest product and remainder split found, which is not always minimal
+pub fn find_constant(n: isize) -> AheuiConstant {
+    AheuiConstantSearch::new().find(n)
+}
+
+// steps turning the value on top of the storage into one differing by delta
+fn offset(delta: isize, search: &mut AheuiConstantSearch) -> Vec<Step> {
+    let mut steps = Vec::new();
+    if delta != 0 {
+        steps.extend(search.steps(delta.abs()));
+        steps.push(arithmetic(if delta > 0 { AheuiOperation::Add } else { AheuiOperation::Subtract }));
+    }
+    steps
+}
+
+// cheapest way found to print the text so far and end up with an anchor
+struct Choice {
+    cost: usize,
+    previous: Option<isize>,
+    steps: Vec<Step>,
+}
+
+// steps printing text, each character either built from scratch or as an offset
+// from an anchor kept at the bottom of the storage, which is the value of some
+// earlier character: the cheapest choice of anchors is found for the whole text
+fn print_steps(text: &str) -> Vec<Step> {
+    let mut search = AheuiConstantSearch::new();
+
+    // for each character, the cheapest way to end up with each anchor
+    let mut layers: Vec<HashMap<Option<isize>, Choice>> = Vec::new();
+    let mut costs = HashMap::from([(None, 0)]);
+    for c in text.chars() {
+        let c = c as isize;
+        let mut layer = HashMap::new();
+        let mut relax = |anchor: Option<isize>, cost: usize, previous: Option<isize>, steps: Vec<Step>| {
+            let cost = cost + steps.len();
+            if layer.get(&anchor).is_none_or(|known: &Choice| known.cost > cost) {
+                layer.insert(anchor, Choice { cost, previous, steps });
+            }
+        };
+
+        for (anchor, cost) in costs.iter() {
+            let fresh = search.steps(c);
+            relax(*anchor, *cost, *anchor, [fresh.clone(), vec![PRINT]].concat());
+            match anchor {
+                None => relax(Some(c), *cost, None, [fresh, vec![DUPLICATE, PRINT]].concat()),
+                Some(a) => {
+                    // leave the anchor's copy below the new character, then swap it up to drop it
+                    let relative = [vec![DUPLICATE], offset(c - a, &mut search)].concat();
+                    relax(*anchor, *cost, *anchor, [relative.clone(), vec![PRINT]].concat());
+                    relax(Some(c), *cost, *anchor, [fresh, vec![DUPLICATE, PRINT, SWAP, DROP]].concat());
+                    relax(Some(c), *cost, *anchor, [relative, vec![DUPLICATE, PRINT, SWAP, DROP]].concat());
+                },
+            };
+        }
+
+        costs = layer.iter().map(|(anchor, choice)| (*anchor, choice.cost)).collect();
+        layers.push(layer);
+    }
+
+    // follow the cheapest final anchor back to the start
+    let mut anchor = costs
+        .iter()
+        .min_by_key(|(anchor, cost)| (**cost, **anchor))
+        .map(|(anchor, _)| *anchor)
+        .unwrap();
+    let mut chunks = Vec::new();
+    for layer in layers.iter().rev() {
+        let choice = &layer[&anchor];
+        chunks.push(choice.steps.clone());
+        anchor = choice.previous;
+    }
+    chunks.into_iter().rev().flatten().collect()
+}
+
+// lay steps out on a square grid, snaking right along even rows
+// and left along odd ones, each row turning down into the next at its end
+fn layout(mut steps: Vec<Step>) -> String {
+    let width = (1..).find(|width| width * width >= steps.len()).unwrap();
+
+    // a short last row running left is filled up with cells that move on,
+    // so it ends in the first column rather than leaving unreachable cells
+    let rows = steps.len().div_ceil(width);
+    if rows % 2 == 0 && !steps.len().is_multiple_of(width) {
+        let terminate = steps.pop().unwrap();
+        steps.resize(rows * width - 1, (AheuiOperation::Null, AheuiArgument::Null));
+        steps.push(terminate);
+    }
+
+    let mut source = String::new();
+    for (row, chunk) in steps.chunks(width).enumerate() {
+        let forward = if row % 2 == 0 { AheuiDirection::Right(false) } else { AheuiDirection::Left(false) };
+        let mut cells: Vec<char> = chunk
+            .iter()
+            .enumerate()
+            .map(|(column, (operation, argument))| {
+                let direction = match operation {
+                    AheuiOperation::Terminate => AheuiDirection::Null,
+                    _ if column == width - 1 => AheuiDirection::Down(false),
+                    _ => forward,
+                };
+                AheuiInstruction::encode(*operation, direction, *argument)[0]
+            })
+            .collect();
+        if row % 2 == 1 {
+            cells.reverse();
+        }
+        source.extend(cells);
+        source.push('\n');
+    }
+    source
+}
+
+// a program printing exactly the given text and then terminating
+pub fn print_program(text: &str) -> String {
+    let mut steps = print_steps(text);
+    steps.push((AheuiOperation::Terminate, AheuiArgument::Null));
+    layout(steps)
+}
@@ -0,0 +1,254 @@
+use std::fmt;
+use crate::{run_within, AheuiEnding, AheuiOutcome};
+use crate::instruction::{AheuiArgument, AheuiDirection, AheuiInstruction, AheuiOperation};
+use crate::component::AheuiProgram;
+use crate::generate::AheuiConstantSearch;
+
+// steps the original program may take on each input
+const STEP_LIMIT: usize = 10_000_000;
+
+// largest value worked out by a run which is replaced, as searching
+// for the shortest way to push larger ones can take a while
+const MAX_CONSTANT: isize = 1 << 20;
+
+type Grid = Vec<Vec<AheuiInstruction>>;
+
+// cells in the order they run in moving one way
+type Line = (AheuiDirection, Vec<(usize, usize)>);
+
+// error for a program which can not be golfed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AheuiGolfError {
+    NoTermination(usize), // index of an input the program does not stop on
+}
+
+impl fmt::Display for AheuiGolfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AheuiGolfError::NoTermination(i) => write!(f, "input {} does not stop within {} steps", i, STEP_LIMIT),
+        }
+    }
+}
+
+// the program as it would be written out and read back in
+fn program(grid: &Grid) -> AheuiProgram {
+    AheuiProgram::from_str(&AheuiProgram::from_rows(grid.clone()).to_source())
+}
+
+// what the original program did on each input
+struct Tests<'a> {
+    inputs: Vec<&'a str>,
+    expected: Vec<AheuiOutcome>,
+}
+
+impl Tests<'_> {
+    // whether a grid does the same on every input, given twice as many steps
+    fn pass(&self, grid: &Grid) -> bool {
+        self.inputs.iter().zip(self.expected.iter()).all(|(input, expected)| {
+            let limit = 2 * expected.steps + 1000;
+            run_within(program(grid), input, limit).same_behavior(expected)
+        })
+    }
+}
+
+// value pushed by instructions run on a stack of their own,
+// if they leave exactly one there
+fn evaluate(instructions: &[AheuiInstruction]) -> Option<isize> {
+    let mut stack: Vec<isize> = Vec::new();
+    for instruction in instructions {
+        match (instruction.operation, instruction.argument) {
+            (AheuiOperation::Push, AheuiArgument::Number(n)) => stack.push(n as isize),
+            (AheuiOperation::Duplicate, _) => stack.push(*stack.last()?),
+            (AheuiOperation::Swap, _) => {
+                let (a, b) = (stack.pop()?, stack.pop()?);
+                stack.push(a);
+                stack.push(b);
+            },
+            (operation, _) => {
+                let (a, b) = (stack.pop()?, stack.pop()?);
+                stack.push(operation.arithmetic_operation(b, a)?);
+            },
+        };
+    }
+    match stack[..] {
+        [value] => Some(value),
+        _ => None,
+    }
+}
+
+// whether an instruction can be part of a run working out a constant
+fn builds_constant(instruction: &AheuiInstruction) -> bool {
+    matches!((instruction.operation, instruction.argument),
+        (AheuiOperation::Push, AheuiArgument::Number(_)) |
+        (AheuiOperation::Duplicate, _) |
+        (AheuiOperation::Swap, _) |
+        (AheuiOperation::Add, _) |
+        (AheuiOperation::Multiply, _) |
+        (AheuiOperation::Divide, _) |
+        (AheuiOperation::Subtract, _) |
+        (AheuiOperation::Modulo, _))
+}
+
+// cells along each row and column in the order they run moving one way,
+// split into runs of instructions which work out constants moving that way
+fn runs(grid: &Grid) -> Vec<Line> {
+    let (width, height) = (grid[0].len(), grid.len());
+    let mut lines: Vec<Line> = Vec::new();
+    for y in 0..height {
+        let cells: Vec<(usize, usize)> = (0..width).map(|x| (x, y)).collect();
+        lines.push((AheuiDirection::Right(false), cells.clone()));
+        lines.push((AheuiDirection::Left(false), cells.into_iter().rev().collect()));
+    }
+    for x in 0..width {
+        let cells: Vec<(usize, usize)> = (0..height).map(|y| (x, y)).collect();
+        lines.push((AheuiDirection::Down(false), cells.clone()));
+        lines.push((AheuiDirection::Up(false), cells.into_iter().rev().collect()));
+    }
+
+    let mut runs = Vec::new();
+    for (direction, cells) in lines {
+        let mut run = Vec::new();
+        for (x, y) in cells {
+            let instruction = &grid[y][x];
+            if instruction.direction == direction && builds_constant(instruction) {
+                run.push((x, y));
+            } else if !run.is_empty() {
+                runs.push((direction, std::mem::take(&mut run)));
+            }
+        }
+        if !run.is_empty() {
+            runs.push((direction, run));
+        }
+    }
+    runs
+}
+
+// replace the first run of instructions working out a constant which can be
+// pushed in fewer, leaving null instructions keeping the direction after it
+fn shorten_constant(grid: &mut Grid, tests: &Tests, search: &mut AheuiConstantSearch) -> bool {
+    for (direction, run) in runs(grid) {
+        let instructions: Vec<AheuiInstruction> = run.iter().map(|(x, y)| grid[*y][*x]).collect();
+        for start in 0..run.len() {
+            for end in (start + 2..=run.len()).rev() {
+                let value = match evaluate(&instructions[start..end]) {
+                    Some(value) if value.abs() <= MAX_CONSTANT => value,
+                    _ => continue,
+                };
+                let constant = search.find(value);
+                if constant.instructions.len() >= end - start {
+                    continue;
+                }
+
+                let mut candidate = grid.clone();
+                let replacement = constant.instructions
+                    .iter()
+                    .map(|instruction| (instruction.operation, instruction.argument))
+                    .chain(std::iter::repeat((AheuiOperation::Null, AheuiArgument::Null)));
+                for ((x, y), (operation, argument)) in run[start..end].iter().zip(replacement) {
+                    candidate[*y][*x] = AheuiInstruction::from_char(AheuiInstruction::encode(operation, direction, argument)[0]);
+                }
+                if tests.pass(&candidate) {
+                    *grid = candidate;
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+// whether a cell does nothing but let execution through
+fn passes_through(instruction: &AheuiInstruction) -> bool {
+    instruction.operation == AheuiOperation::Null
+}
+
+// remove the first cell doing nothing from a row or column, moving the cells
+// after it back by one and leaving a blank cell at the end, so that straight
+// paths get shorter where no whole row or column can go. only cells before
+// one which does something are removed, so that every change moves such a
+// cell back and compacting stops
+fn compact(grid: &mut Grid, tests: &Tests) -> bool {
+    let (width, height) = (grid[0].len(), grid.len());
+    let mut lines: Vec<Vec<(usize, usize)>> = (0..height).map(|y| (0..width).map(|x| (x, y)).collect()).collect();
+    lines.extend((0..width).map(|x| (0..height).map(|y| (x, y)).collect()));
+
+    for cells in lines {
+        for (i, (x, y)) in cells.iter().enumerate() {
+            let rest = &cells[i + 1..];
+            if !passes_through(&grid[*y][*x]) || rest.iter().all(|(x, y)| passes_through(&grid[*y][*x])) {
+                continue;
+            }
+
+            let mut candidate = grid.clone();
+            let shifted = rest.iter().map(|(x, y)| grid[*y][*x]).chain(std::iter::once(AheuiInstruction::null()));
+            for ((x, y), instruction) in cells[i..].iter().zip(shifted) {
+                candidate[*y][*x] = instruction;
+            }
+            if tests.pass(&candidate) {
+                *grid = candidate;
+                return true;
+            }
+        }
+    }
+    false
+}
+
+// remove the first row which is not needed, keeping at least one
+fn remove_row(grid: &mut Grid, tests: &Tests) -> bool {
+    if grid.len() == 1 {
+        return false;
+    }
+    for y in (0..grid.len()).rev() {
+        let mut candidate = grid.clone();
+        candidate.remove(y);
+        if tests.pass(&candidate) {
+            *grid = candidate;
+            return true;
+        }
+    }
+    false
+}
+
+// remove the first column which is not needed, keeping at least one
+fn remove_column(grid: &mut Grid, tests: &Tests) -> bool {
+    if grid[0].len() == 1 {
+        return false;
+    }
+    for x in (0..grid[0].len()).rev() {
+        let mut candidate = grid.clone();
+        for row in candidate.iter_mut() {
+            row.remove(x);
+        }
+        if tests.pass(&candidate) {
+            *grid = candidate;
+            return true;
+        }
+    }
+    false
+}
+
+// shrink a program for as long as it writes the same and ends the same way
+// on each input, replacing runs of instructions working out a constant with
+// the shortest found, then removing rows and columns and the cells
+// doing nothing along straight paths
+//
+// programs are only run on the inputs given, or on no input if there are none,
+// so the result may do something else on others
+pub fn golf(program: &AheuiProgram, inputs: &[&str]) -> Result<AheuiProgram, AheuiGolfError> {
+    let inputs = if inputs.is_empty() { vec![""] } else { inputs.to_vec() };
+    let mut grid: Grid = program.program.clone();
+    if grid.is_empty() || grid[0].is_empty() {
+        return Ok(AheuiProgram::from_rows(grid));
+    }
+
+    let expected: Vec<AheuiOutcome> = inputs.iter().map(|input| run_within(self::program(&grid), input, STEP_LIMIT)).collect();
+    if let Some(i) = expected.iter().position(|outcome| matches!(outcome.ending, AheuiEnding::OutOfSteps)) {
+        return Err(AheuiGolfError::NoTermination(i));
+    }
+    let tests = Tests { inputs, expected };
+
+    let mut search = AheuiConstantSearch::new();
+    while shorten_constant(&mut grid, &tests, &mut search) {}
+    while remove_row(&mut grid, &tests) || remove_column(&mut grid, &tests) || compact(&mut grid, &tests) {}
+    Ok(self::program(&grid))
+}
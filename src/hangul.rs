@@ -5,9 +5,18 @@ const HANGUL_START: u32 = 0xAC00; // start of hangul code block
 const HANGUL_END: u32 = 0xD7A3; // end of hangul code block
 const HANGUL_ONSET_MULT: u32 = 0x24C; // offset multiplier for initial consonant
 const HANGUL_VOWEL_MULT: u32 = 0x1C; // offset multiplier for vowel
+const CHOSEONG_START: u32 = 0x1100; // start of the 19 conjoining initial consonants
+const JUNGSEONG_START: u32 = 0x1161; // start of the 21 conjoining vowels
+const JONGSEONG_START: u32 = 0x11A7; // one before the 27 conjoining final consonants
+
+// compatibility jamo for each final consonant, in HangulCoda order
+pub const CODA_JAMO: [&str; 28] = [
+    "", "ㄱ", "ㄲ", "ㄳ", "ㄴ", "ㄵ", "ㄶ", "ㄷ", "ㄹ", "ㄺ", "ㄻ", "ㄼ", "ㄽ", "ㄾ",
+    "ㄿ", "ㅀ", "ㅁ", "ㅂ", "ㅄ", "ㅅ", "ㅆ", "ㅇ", "ㅈ", "ㅊ", "ㅋ", "ㅌ", "ㅍ", "ㅎ",
+];
 
 // initial hangul consonant enum
-#[derive(Debug, FromPrimitive, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, FromPrimitive, PartialEq, Eq)]
 pub enum HangulOnset {
     Giyeok, //ㄱ
     SsangGiyeok,    //ㄲ
@@ -32,7 +41,7 @@ pub enum HangulOnset {
 
 
 // hangul vowel enum
-#[derive(Debug, FromPrimitive, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, FromPrimitive, PartialEq, Eq)]
 pub enum HangulVowel {
     A,  //ㅏ
     Ae, //ㅐ
@@ -58,7 +67,7 @@ pub enum HangulVowel {
 }
 
 // final consonant enum
-#[derive(Debug, FromPrimitive, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, FromPrimitive, PartialEq, Eq)]
 pub enum HangulCoda {
     Null,   // empty
     Giyeok, //ㄱ
@@ -92,7 +101,7 @@ pub enum HangulCoda {
 
 // a representation of a hangul syllable
 // containing an onset, vowel, and coda
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct HangulSyllable {
     pub onset: HangulOnset,
     pub vowel: HangulVowel,
@@ -100,13 +109,37 @@ pub struct HangulSyllable {
 }
 
 impl HangulSyllable { 
+    pub fn new(onset: HangulOnset, vowel: HangulVowel, coda: HangulCoda) -> Self {
+        Self {
+            onset,
+            vowel,
+            coda,
+        }
+    }
+
+    // create a HangulSyllable from the numbers of its components,
+    // if invalid, return None
+    pub fn from_parts(onset: u32, vowel: u32, coda: u32) -> Option<Self> {
+        Some(Self {
+            onset: FromPrimitive::from_u32(onset)?,
+            vowel: FromPrimitive::from_u32(vowel)?,
+            coda: FromPrimitive::from_u32(coda)?,
+        })
+    }
+
+    // compose the precomposed hangul character of the syllable
+    pub fn to_char(&self) -> char {
+        let offset = self.onset as u32 * HANGUL_ONSET_MULT + self.vowel as u32 * HANGUL_VOWEL_MULT + self.coda as u32;
+        char::from_u32(HANGUL_START + offset).unwrap()
+    }
+
     // create a HangulSyllable from a hangul character
     // if invalid, return None
     pub fn from_char(hangul_char: char) -> Option<Self> {
         let uni = u32::from(hangul_char);
 
         // return None if character is not hangul
-        if uni < HANGUL_START || uni > HANGUL_END {
+        if !(HANGUL_START..=HANGUL_END).contains(&uni) {
             return None;
         }
 
@@ -120,14 +153,51 @@ impl HangulSyllable {
         let coda_num = onset_rem % HANGUL_VOWEL_MULT;
 
         // convert numbers into hangul component enums
-        let onset: HangulOnset = FromPrimitive::from_u32(onset_num)?;
-        let vowel: HangulVowel = FromPrimitive::from_u32(vowel_num)?;
-        let coda: HangulCoda = FromPrimitive::from_u32(coda_num)?;
+        Self::from_parts(onset_num, vowel_num, coda_num)
+    }
+}
 
-        Some(Self {
-            onset,
-            vowel,
-            coda,
-        })
+// compose sequences of conjoining jamo, as left by NFD normalization,
+// into precomposed syllables
+// every composed syllable takes up one column, as does every jamo
+// which is not part of a syllable
+pub fn compose(text: &str) -> String {
+    let mut composed = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let code = u32::from(c);
+        let vowel = chars.peek().map(|next| u32::from(*next).wrapping_sub(JUNGSEONG_START));
+
+        // an initial consonant followed by a vowel starts a syllable
+        let syllable = match (code.wrapping_sub(CHOSEONG_START), vowel) {
+            (onset @ 0..=18, Some(vowel @ 0..=20)) => {
+                chars.next();
+                HANGUL_START + onset * HANGUL_ONSET_MULT + vowel * HANGUL_VOWEL_MULT
+            },
+            // as does a precomposed syllable without a final consonant
+            _ if (HANGUL_START..=HANGUL_END).contains(&code) && (code - HANGUL_START).is_multiple_of(HANGUL_VOWEL_MULT) => code,
+            _ => {
+                composed.push(c);
+                continue;
+            },
+        };
+
+        // which can then be followed by a final consonant
+        let coda = chars.peek().map(|next| u32::from(*next).wrapping_sub(JONGSEONG_START));
+        let syllable = match coda {
+            Some(coda @ 1..=27) => {
+                chars.next();
+                syllable + coda
+            },
+            _ => syllable,
+        };
+        composed.push(char::from_u32(syllable).unwrap());
     }
+    composed
+}
+
+// whether a character is a jamo rather than a syllable, either a compatibility
+// jamo such as ㄱ and ㅏ or a conjoining jamo
+pub fn is_jamo(c: char) -> bool {
+    matches!(u32::from(c), 0x1100..=0x11FF | 0x3131..=0x318E | 0xA960..=0xA97F | 0xD7B0..=0xD7FF)
 }
@@ -2,7 +2,7 @@ use std::fmt;
 use super::hangul::*;
 
 // aheui action enum
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AheuiOperation {
     Null, //ㅇ
     Terminate, //ㅎ
@@ -33,10 +33,28 @@ impl AheuiOperation {
             _ => None,
         }
     }
+
+    // number of values the operation needs in its storage to succeed
+    pub fn required_depth(&self) -> usize {
+        match *self {
+            AheuiOperation::Add |
+            AheuiOperation::Multiply |
+            AheuiOperation::Divide |
+            AheuiOperation::Subtract |
+            AheuiOperation::Modulo |
+            AheuiOperation::Swap |
+            AheuiOperation::Compare => 2,
+            AheuiOperation::Pop |
+            AheuiOperation::Duplicate |
+            AheuiOperation::StoreTransfer |
+            AheuiOperation::Fork => 1,
+            _ => 0,
+        }
+    }
 }
 
 // aheui direction enum
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AheuiDirection {
     Null,
     Up(bool),
@@ -80,7 +98,7 @@ impl AheuiDirection {
 }
 
 // aheui argument enum
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AheuiArgument {
     Null,
     AsInt,
@@ -92,6 +110,7 @@ pub enum AheuiArgument {
 // representation of an aheui instruction
 // each instruction contains an operation (onset),
 // a direction (vowel), and an argument (coda)
+#[derive(Clone, Copy)]
 pub struct AheuiInstruction {
     pub operation: AheuiOperation,
     pub direction: AheuiDirection,
@@ -99,13 +118,58 @@ pub struct AheuiInstruction {
     pub character: char,
 }
 
+// final consonants preferred when encoding instructions,
+// the simplest one for each number pushed
+const CANONICAL_CODAS: [HangulCoda; 9] = [
+    HangulCoda::Null,
+    HangulCoda::Giyeok,
+    HangulCoda::Digeut,
+    HangulCoda::Mieum,
+    HangulCoda::Rieul,
+    HangulCoda::BieupSiot,
+    HangulCoda::RieulGiyeok,
+    HangulCoda::RieulHieut,
+    HangulCoda::RieulBieup,
+];
+
 impl AheuiInstruction {
+    // every syllable which decodes to an instruction, the canonical one first
+    // which uses ㅇ for null operations, ㅐ for null directions, no final consonant
+    // where the argument allows it and the simplest one for numbers pushed
+    // empty if the argument does not go with the operation
+    pub fn encode(operation: AheuiOperation, direction: AheuiDirection, argument: AheuiArgument) -> Vec<char> {
+        let decode = |onset, vowel, coda| {
+            AheuiInstruction::from_char(HangulSyllable::from_parts(onset, vowel, coda).unwrap().to_char())
+        };
+
+        // each component decides one part of the instruction on its own,
+        // apart from the argument depending on the operation
+        let mut onsets: Vec<u32> = (0..19)
+            .filter(|onset| decode(*onset, 0, 0).operation == operation)
+            .collect();
+        onsets.sort_by_key(|onset| *onset != HangulOnset::Ieung as u32);
+        let vowels: Vec<u32> = (0..21)
+            .filter(|vowel| decode(HangulOnset::Ieung as u32, *vowel, 0).direction == direction)
+            .collect();
+        let mut codas: Vec<u32> = (0..28)
+            .filter(|coda| onsets.first().is_some_and(|onset| decode(*onset, 0, *coda).argument == argument))
+            .collect();
+        codas.sort_by_key(|coda| !CANONICAL_CODAS.iter().any(|canonical| *canonical as u32 == *coda));
+
+        onsets
+            .iter()
+            .flat_map(|onset| vowels.iter().map(move |vowel| (*onset, *vowel)))
+            .flat_map(|(onset, vowel)| codas.iter().map(move |coda| HangulSyllable::from_parts(onset, vowel, *coda).unwrap().to_char()))
+            .collect()
+    }
+
     pub fn from_char(cmd: char) -> Self {
         // create hangul syllable from character
-        // and return a null instruction if invalid or non-hangul
+        // and return a null instruction if invalid or non-hangul,
+        // keeping the character so it can be reported
         let syllable = match HangulSyllable::from_char(cmd) {
             Some(syllable) => syllable,
-            None => return AheuiInstruction::null(),
+            None => return Self { character: cmd, ..AheuiInstruction::null() },
         };
         
         // convert init const to AheuiOperation
@@ -193,6 +257,26 @@ impl AheuiInstruction {
         }
     }
 
+    // whether the instruction can continue in its own direction
+    // termination has no next instruction and popping without
+    // an output argument always reflects
+    pub fn can_succeed(&self) -> bool {
+        match self.operation {
+            AheuiOperation::Terminate => false,
+            AheuiOperation::Pop => self.argument != AheuiArgument::Null,
+            _ => true,
+        }
+    }
+
+    // whether the instruction can reflect instead of following its direction
+    pub fn can_fail(&self) -> bool {
+        !matches!(self.operation,
+            AheuiOperation::Null |
+            AheuiOperation::Terminate |
+            AheuiOperation::Push |
+            AheuiOperation::StoreSelect)
+    }
+
     // return a null instruction
     pub fn null() -> Self {
         Self {
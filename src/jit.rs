@@ -0,0 +1,536 @@
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::ptr;
+use crate::AheuiState;
+use crate::instruction::*;
+use crate::component::*;
+
+// number of times a (position, direction) state has to be reached
+// by the interpreter before its block is compiled
+const HOT_THRESHOLD: usize = 16;
+
+// maximum number of instructions compiled into one block
+const MAX_BLOCK_LENGTH: usize = 256;
+
+// free slots every stack should have before entering native code
+const MIN_SPARE_CAPACITY: usize = 64;
+
+// number of times native code may loop back before
+// returning control to the interpreter
+const LOOP_FUEL: usize = 1024;
+
+// mmap/mprotect constants for linux x86-64
+const PROT_READ: i32 = 0x1;
+const PROT_WRITE: i32 = 0x2;
+const PROT_EXEC: i32 = 0x4;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+// view of a single stack shared with native code
+// queues are given a null pointer with no length or capacity,
+// so that every native operation on them falls back to the interpreter
+#[repr(C)]
+struct NativeStack {
+    ptr: *mut isize,
+    len: usize,
+    cap: usize,
+}
+
+// state shared with native code, passed as its only argument
+#[repr(C)]
+struct NativeContext {
+    stacks: [NativeStack; 28],
+    storage_index: usize,
+    fuel: usize,
+}
+
+const STACK_SIZE: i32 = std::mem::size_of::<NativeStack>() as i32;
+const LEN_OFFSET: i8 = 8;
+const CAP_OFFSET: i8 = 16;
+const INDEX_OFFSET: i32 = 28 * STACK_SIZE;
+const FUEL_OFFSET: i32 = INDEX_OFFSET + 8;
+
+// page of executable memory holding a compiled block
+struct ExecutableBuffer {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+impl ExecutableBuffer {
+    // copy code into freshly mapped memory, then make it read-only and executable
+    fn new(code: &[u8]) -> Option<Self> {
+        let len = code.len().max(1);
+        let ptr = unsafe {
+            mmap(ptr::null_mut(), len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0)
+        };
+        if ptr.is_null() || ptr as isize == -1 {
+            return None;
+        }
+
+        let buffer = Self { ptr, len };
+        unsafe {
+            ptr::copy_nonoverlapping(code.as_ptr(), ptr as *mut u8, code.len());
+            if mprotect(ptr, len, PROT_READ | PROT_EXEC) != 0 {
+                return None;
+            }
+        }
+
+        Some(buffer)
+    }
+
+    // run the block, returning the index of the state to resume from
+    fn call(&self, context: &mut NativeContext) -> usize {
+        let function: extern "C" fn(*mut NativeContext) -> usize = unsafe { std::mem::transmute(self.ptr) };
+        function(context)
+    }
+}
+
+impl Drop for ExecutableBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.ptr, self.len);
+        }
+    }
+}
+
+// a compiled straight-line path through the program
+// native code returns the index of the state in states
+// from which the interpreter has to continue
+struct CompiledBlock {
+    code: ExecutableBuffer,
+    states: Vec<(AheuiCoordinates, AheuiDirection)>,
+    // number of instructions each loop back runs, 0 if the path does not loop
+    loop_length: usize,
+}
+
+// minimal x86-64 encoder for the instructions emitted by the compiler
+// registers are fixed: r12 holds the context, rbx the current stack,
+// rcx its length and r8 its data pointer
+struct Assembler {
+    code: Vec<u8>,
+    // positions of rel32 jumps to exit stubs, with the state index to exit with
+    exit_jumps: Vec<(usize, usize)>,
+    // positions of rel32 jumps to instruction labels
+    label_jumps: Vec<(usize, usize)>,
+    // positions of rel32 jumps to the epilogue
+    epilogue_jumps: Vec<usize>,
+    labels: Vec<usize>,
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Self {
+            code: Vec::new(),
+            exit_jumps: Vec::new(),
+            label_jumps: Vec::new(),
+            epilogue_jumps: Vec::new(),
+            labels: Vec::new(),
+        }
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn emit_i32(&mut self, value: i32) {
+        self.code.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // emit a conditional jump (0x0F 0x8_) to the exit stub of a state
+    fn jump_exit(&mut self, condition: u8, state: usize) {
+        self.emit(&[0x0F, condition]);
+        self.exit_jumps.push((self.code.len(), state));
+        self.emit_i32(0);
+    }
+
+    // load the current stack into rbx and its length into rcx
+    fn load_current_stack(&mut self) {
+        // mov rax, [r12 + INDEX_OFFSET]
+        self.emit(&[0x49, 0x8B, 0x84, 0x24]);
+        self.emit_i32(INDEX_OFFSET);
+        // lea rax, [rax + rax*2]
+        self.emit(&[0x48, 0x8D, 0x04, 0x40]);
+        // lea rbx, [r12 + rax*8]
+        self.emit(&[0x49, 0x8D, 0x1C, 0xC4]);
+        // mov rcx, [rbx + 8]
+        self.emit(&[0x48, 0x8B, 0x4B, LEN_OFFSET as u8]);
+    }
+
+    // exit unless the current stack has at least n values,
+    // then load its data pointer into r8
+    fn require_len(&mut self, n: u8, state: usize) {
+        // cmp rcx, n
+        self.emit(&[0x48, 0x83, 0xF9, n]);
+        // jb exit
+        self.jump_exit(0x82, state);
+        // mov r8, [rbx]
+        self.emit(&[0x4C, 0x8B, 0x03]);
+    }
+
+    // exit unless the current stack has room for another value
+    fn require_capacity(&mut self, state: usize) {
+        // cmp rcx, [rbx + 16]
+        self.emit(&[0x48, 0x3B, 0x4B, CAP_OFFSET as u8]);
+        // jae exit
+        self.jump_exit(0x83, state);
+        // mov r8, [rbx]
+        self.emit(&[0x4C, 0x8B, 0x03]);
+    }
+
+    // store rcx back as the current stack's length
+    fn store_len(&mut self) {
+        // mov [rbx + 8], rcx
+        self.emit(&[0x48, 0x89, 0x4B, LEN_OFFSET as u8]);
+    }
+
+    // load the top value into rax and the second value into rsi
+    fn load_top_two(&mut self) {
+        // mov rax, [r8 + rcx*8 - 8]
+        self.emit(&[0x49, 0x8B, 0x44, 0xC8, 0xF8]);
+        // mov rsi, [r8 + rcx*8 - 16]
+        self.emit(&[0x49, 0x8B, 0x74, 0xC8, 0xF0]);
+    }
+
+    fn compile_instruction(&mut self, instruction: &AheuiInstruction, state: usize) -> bool {
+        match (instruction.operation, instruction.argument) {
+            (AheuiOperation::Null, _) => {},
+            (AheuiOperation::Push, AheuiArgument::Number(n)) => {
+                self.load_current_stack();
+                self.require_capacity(state);
+                // mov qword [r8 + rcx*8], n
+                self.emit(&[0x49, 0xC7, 0x04, 0xC8]);
+                self.emit_i32(n as i32);
+                // inc rcx
+                self.emit(&[0x48, 0xFF, 0xC1]);
+                self.store_len();
+            },
+            (AheuiOperation::Add, _) |
+            (AheuiOperation::Subtract, _) |
+            (AheuiOperation::Multiply, _) => {
+                self.load_current_stack();
+                self.require_len(2, state);
+                self.load_top_two();
+                match instruction.operation {
+                    // add rsi, rax
+                    AheuiOperation::Add => self.emit(&[0x48, 0x01, 0xC6]),
+                    // sub rsi, rax
+                    AheuiOperation::Subtract => self.emit(&[0x48, 0x29, 0xC6]),
+                    // imul rsi, rax
+                    _ => self.emit(&[0x48, 0x0F, 0xAF, 0xF0]),
+                };
+                // jo exit, leaving the interpreter to report the overflow
+                self.jump_exit(0x80, state);
+                // mov [r8 + rcx*8 - 16], rsi
+                self.emit(&[0x49, 0x89, 0x74, 0xC8, 0xF0]);
+                // dec rcx
+                self.emit(&[0x48, 0xFF, 0xC9]);
+                self.store_len();
+            },
+            (AheuiOperation::Divide, _) |
+            (AheuiOperation::Modulo, _) => {
+                self.load_current_stack();
+                self.require_len(2, state);
+                // mov r9, [r8 + rcx*8 - 8]
+                self.emit(&[0x4D, 0x8B, 0x4C, 0xC8, 0xF8]);
+                // mov rax, [r8 + rcx*8 - 16]
+                self.emit(&[0x49, 0x8B, 0x44, 0xC8, 0xF0]);
+                // test r9, r9; jz exit
+                self.emit(&[0x4D, 0x85, 0xC9]);
+                self.jump_exit(0x84, state);
+                // cmp r9, -1; je exit
+                self.emit(&[0x49, 0x83, 0xF9, 0xFF]);
+                self.jump_exit(0x84, state);
+                // cqo; idiv r9
+                self.emit(&[0x48, 0x99, 0x49, 0xF7, 0xF9]);
+                if instruction.operation == AheuiOperation::Divide {
+                    // mov [r8 + rcx*8 - 16], rax
+                    self.emit(&[0x49, 0x89, 0x44, 0xC8, 0xF0]);
+                } else {
+                    // mov [r8 + rcx*8 - 16], rdx
+                    self.emit(&[0x49, 0x89, 0x54, 0xC8, 0xF0]);
+                }
+                // dec rcx
+                self.emit(&[0x48, 0xFF, 0xC9]);
+                self.store_len();
+            },
+            (AheuiOperation::Duplicate, _) => {
+                self.load_current_stack();
+                self.require_len(1, state);
+                self.require_capacity(state);
+                // mov rax, [r8 + rcx*8 - 8]
+                self.emit(&[0x49, 0x8B, 0x44, 0xC8, 0xF8]);
+                // mov [r8 + rcx*8], rax
+                self.emit(&[0x49, 0x89, 0x04, 0xC8]);
+                // inc rcx
+                self.emit(&[0x48, 0xFF, 0xC1]);
+                self.store_len();
+            },
+            (AheuiOperation::Swap, _) => {
+                self.load_current_stack();
+                self.require_len(2, state);
+                self.load_top_two();
+                // mov [r8 + rcx*8 - 8], rsi
+                self.emit(&[0x49, 0x89, 0x74, 0xC8, 0xF8]);
+                // mov [r8 + rcx*8 - 16], rax
+                self.emit(&[0x49, 0x89, 0x44, 0xC8, 0xF0]);
+            },
+            (AheuiOperation::Compare, _) => {
+                self.load_current_stack();
+                self.require_len(2, state);
+                self.load_top_two();
+                // xor edx, edx; cmp rax, rsi; setle dl
+                self.emit(&[0x31, 0xD2, 0x48, 0x39, 0xF0, 0x0F, 0x9E, 0xC2]);
+                // mov [r8 + rcx*8 - 16], rdx
+                self.emit(&[0x49, 0x89, 0x54, 0xC8, 0xF0]);
+                // dec rcx
+                self.emit(&[0x48, 0xFF, 0xC9]);
+                self.store_len();
+            },
+            (AheuiOperation::StoreSelect, AheuiArgument::Storage(n)) => {
+                // mov qword [r12 + INDEX_OFFSET], n
+                self.emit(&[0x49, 0xC7, 0x84, 0x24]);
+                self.emit_i32(INDEX_OFFSET);
+                self.emit_i32(n as i32);
+            },
+            (AheuiOperation::StoreTransfer, AheuiArgument::Storage(n)) => {
+                self.load_current_stack();
+                self.require_len(1, state);
+                // lea rdx, [r12 + n * STACK_SIZE]
+                self.emit(&[0x49, 0x8D, 0x94, 0x24]);
+                self.emit_i32(n as i32 * STACK_SIZE);
+                // cmp rbx, rdx; je next (transfer onto itself)
+                self.emit(&[0x48, 0x39, 0xD3, 0x0F, 0x84]);
+                let skip = self.code.len();
+                self.emit_i32(0);
+                // mov rsi, [rdx + 8]; cmp rsi, [rdx + 16]; jae exit
+                self.emit(&[0x48, 0x8B, 0x72, LEN_OFFSET as u8]);
+                self.emit(&[0x48, 0x3B, 0x72, CAP_OFFSET as u8]);
+                self.jump_exit(0x83, state);
+                // mov rax, [r8 + rcx*8 - 8]; dec rcx
+                self.emit(&[0x49, 0x8B, 0x44, 0xC8, 0xF8, 0x48, 0xFF, 0xC9]);
+                self.store_len();
+                // mov r9, [rdx]; mov [r9 + rsi*8], rax
+                self.emit(&[0x4C, 0x8B, 0x0A, 0x49, 0x89, 0x04, 0xF1]);
+                // inc rsi; mov [rdx + 8], rsi
+                self.emit(&[0x48, 0xFF, 0xC6, 0x48, 0x89, 0x72, LEN_OFFSET as u8]);
+                let offset = (self.code.len() - skip - 4) as i32;
+                self.code[skip..skip + 4].copy_from_slice(&offset.to_le_bytes());
+            },
+            // input, output, forks and termination are left to the interpreter
+            _ => return false,
+        };
+
+        true
+    }
+
+    // emit an exit stub and a jump to the epilogue for every state exited from
+    fn finish(mut self) -> Vec<u8> {
+        let mut stubs: HashMap<usize, usize> = HashMap::new();
+        let exits = std::mem::take(&mut self.exit_jumps);
+        for (position, state) in exits {
+            let stub = match stubs.get(&state) {
+                Some(stub) => *stub,
+                None => {
+                    let stub = self.code.len();
+                    // mov eax, state; jmp epilogue
+                    self.emit(&[0xB8]);
+                    self.emit_i32(state as i32);
+                    self.emit(&[0xE9]);
+                    self.epilogue_jumps.push(self.code.len());
+                    self.emit_i32(0);
+                    stubs.insert(state, stub);
+                    stub
+                },
+            };
+            let offset = (stub - position - 4) as i32;
+            self.code[position..position + 4].copy_from_slice(&offset.to_le_bytes());
+        }
+
+        for (position, label) in std::mem::take(&mut self.label_jumps) {
+            let offset = self.labels[label] as i32 - (position + 4) as i32;
+            self.code[position..position + 4].copy_from_slice(&offset.to_le_bytes());
+        }
+
+        // pop r12; pop rbx; ret
+        let epilogue = self.code.len();
+        self.emit(&[0x41, 0x5C, 0x5B, 0xC3]);
+        for position in std::mem::take(&mut self.epilogue_jumps) {
+            let offset = (epilogue - position - 4) as i32;
+            self.code[position..position + 4].copy_from_slice(&offset.to_le_bytes());
+        }
+
+        self.code
+    }
+}
+
+// compile the straight-line path starting at position and direction
+// returns None if not even the first instruction can be compiled
+fn compile_block(program: &AheuiProgram, position: AheuiCoordinates, direction: AheuiDirection) -> Option<CompiledBlock> {
+    let mut assembler = Assembler::new();
+    let mut states: Vec<(AheuiCoordinates, AheuiDirection)> = Vec::new();
+    let mut seen: HashMap<(AheuiCoordinates, AheuiDirection), usize> = HashMap::new();
+    let mut loop_length = 0;
+
+    // push rbx; push r12; mov r12, rdi
+    assembler.emit(&[0x53, 0x41, 0x54, 0x49, 0x89, 0xFC]);
+
+    let mut current = (position, direction);
+    loop {
+        // jump back if the path loops onto itself,
+        // exiting once the loop runs out of fuel
+        if let Some(label) = seen.get(&current) {
+            loop_length = states.len() - label;
+            // dec qword [r12 + FUEL_OFFSET]; jz exit
+            assembler.emit(&[0x49, 0xFF, 0x8C, 0x24]);
+            assembler.emit_i32(FUEL_OFFSET);
+            assembler.jump_exit(0x84, *label);
+            // jmp label
+            assembler.emit(&[0xE9]);
+            assembler.label_jumps.push((assembler.code.len(), *label));
+            assembler.emit_i32(0);
+            break;
+        }
+
+        let state = states.len();
+        let instruction = program.get_instruction(&current.0)?;
+        states.push(current);
+        seen.insert(current, state);
+        assembler.labels.push(assembler.code.len());
+
+        // stop at instructions native code does not handle
+        // or once the block gets too long
+        if state >= MAX_BLOCK_LENGTH || !assembler.compile_instruction(instruction, state) {
+            // mov eax, state; jmp epilogue
+            assembler.emit(&[0xB8]);
+            assembler.emit_i32(state as i32);
+            assembler.emit(&[0xE9]);
+            assembler.epilogue_jumps.push(assembler.code.len());
+            assembler.emit_i32(0);
+            break;
+        }
+
+        current = program.next_position(current.0, current.1, instruction.direction);
+    }
+
+    // a block which exits right away is not worth calling
+    if states.len() < 2 {
+        return None;
+    }
+
+    let code = ExecutableBuffer::new(&assembler.finish())?;
+    Some(CompiledBlock {
+        code,
+        states,
+        loop_length,
+    })
+}
+
+// just-in-time compiler running hot paths of an AheuiState natively
+pub struct AheuiJit {
+    blocks: HashMap<(AheuiCoordinates, AheuiDirection), Option<CompiledBlock>>,
+    hits: HashMap<(AheuiCoordinates, AheuiDirection), usize>,
+}
+
+impl Default for AheuiJit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AheuiJit {
+    pub fn new() -> Self {
+        Self {
+            blocks: HashMap::new(),
+            hits: HashMap::new(),
+        }
+    }
+
+    // number of blocks compiled so far
+    pub fn compiled_blocks(&self) -> usize {
+        self.blocks.values().filter(|block| block.is_some()).count()
+    }
+
+    // run a single block or interpreter step
+    pub fn step(&mut self, state: &mut AheuiState) -> Result<(), AheuiRuntimeError> {
+        if state.terminated {
+            return state.step();
+        }
+
+        let key = (state.position, state.direction);
+        if !self.blocks.contains_key(&key) {
+            let hits = self.hits.entry(key).or_insert(0);
+            *hits += 1;
+            if *hits >= HOT_THRESHOLD {
+                let block = compile_block(&state.program, key.0, key.1);
+                self.blocks.insert(key, block);
+            }
+        }
+
+        match self.blocks.get(&key) {
+            Some(Some(block)) => {
+                run_block(block, state);
+                // let the interpreter handle the instruction native code stopped at
+                state.step()
+            },
+            _ => state.step(),
+        }
+    }
+
+    // run until terminated, printing any errors encountered
+    pub fn run(&mut self, state: &mut AheuiState) {
+        while !state.terminated {
+            if let Err(err) = self.step(state) {
+                state.flush_output();
+                eprintln!("{err}");
+            }
+        }
+    }
+}
+
+// run a compiled block on the storages of state,
+// leaving state at the position native code exited from
+fn run_block(block: &CompiledBlock, state: &mut AheuiState) {
+    let mut context = NativeContext {
+        stacks: std::array::from_fn(|_| NativeStack {
+            ptr: ptr::null_mut(),
+            len: 0,
+            cap: 0,
+        }),
+        storage_index: state.storage_index,
+        fuel: LOOP_FUEL,
+    };
+
+    for (storage, native) in state.storages.iter_mut().zip(context.stacks.iter_mut()) {
+        if let Some(stack) = storage.stack_mut() {
+            stack.reserve(MIN_SPARE_CAPACITY);
+            native.ptr = stack.as_mut_ptr();
+            native.len = stack.len();
+            native.cap = stack.capacity();
+        }
+    }
+
+    let exit = block.code.call(&mut context);
+
+    for (storage, native) in state.storages.iter_mut().zip(context.stacks.iter()) {
+        if let Some(stack) = storage.stack_mut() {
+            // native code only writes within capacity and
+            // every value below the new length has been initialized
+            unsafe {
+                stack.set_len(native.len);
+            }
+        }
+    }
+
+    // every state before the exit ran once, plus a whole loop for each unit of fuel used
+    state.steps += exit + (LOOP_FUEL - context.fuel) * block.loop_length;
+    let (position, direction) = block.states[exit];
+    state.storage_index = context.storage_index;
+    state.position = position;
+    state.direction = direction;
+}
@@ -0,0 +1,642 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::hangul::CODA_JAMO;
+use crate::component::AheuiCoordinates;
+use crate::asm::{AheuiAsmBuilder, AheuiAsmError};
+
+// reasons a program in the structured language is rejected, each with its line number
+#[derive(Debug, PartialEq, Eq)]
+pub enum AheuiLangError {
+    InvalidCharacter(usize, char),
+    InvalidNumber(usize, String),
+    UnexpectedToken(usize, String),
+    UnexpectedEnd,
+    UndefinedVariable(usize, String),
+    UndefinedFunction(usize, String),
+    DuplicateFunction(usize, String),
+    WrongArgumentCount(usize, String),
+    RecursiveCall(usize, String),
+    TooManyVariables(usize, String),
+    InvalidAssembly(AheuiAsmError), // assembly generated for the program is rejected, which is a bug
+}
+
+impl fmt::Display for AheuiLangError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AheuiLangError::InvalidCharacter(line, c) => write!(f, "line {}: invalid character {:?}", line, c),
+            AheuiLangError::InvalidNumber(line, number) => write!(f, "line {}: invalid number {}", line, number),
+            AheuiLangError::UnexpectedToken(line, token) => write!(f, "line {}: unexpected {}", line, token),
+            AheuiLangError::UnexpectedEnd => write!(f, "unexpected end of file"),
+            AheuiLangError::UndefinedVariable(line, name) => write!(f, "line {}: {} is read before it is assigned", line, name),
+            AheuiLangError::UndefinedFunction(line, name) => write!(f, "line {}: undefined function {}", line, name),
+            AheuiLangError::DuplicateFunction(line, name) => write!(f, "line {}: duplicate function {}", line, name),
+            AheuiLangError::WrongArgumentCount(line, name) => write!(f, "line {}: wrong number of arguments to {}", line, name),
+            AheuiLangError::RecursiveCall(line, name) => write!(f, "line {}: {} calls itself, so it can not be inlined", line, name),
+            AheuiLangError::TooManyVariables(line, name) => write!(f, "line {}: no storage left for {}", line, name),
+            AheuiLangError::InvalidAssembly(error) => write!(f, "generated assembly is invalid: {}", error),
+        }
+    }
+}
+
+// the grid cells compiled from each line of a program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AheuiSourceMap {
+    pub cells: Vec<(AheuiCoordinates, usize)>,
+}
+
+impl AheuiSourceMap {
+    // line a cell was compiled from, if it was compiled from one
+    // rather than routing between others
+    pub fn line(&self, position: AheuiCoordinates) -> Option<usize> {
+        self.cells.iter().find(|(cell, _)| *cell == position).map(|(_, line)| *line)
+    }
+
+    // {"cells": [{"x": 1, "y": 1, "line": 3}]}, with zero-based coordinates
+    pub fn to_json(&self) -> String {
+        if self.cells.is_empty() {
+            return "{\"cells\": []}\n".to_string();
+        }
+        let cells: Vec<String> = self.cells
+            .iter()
+            .map(|(position, line)| format!("{{\"x\": {}, \"y\": {}, \"line\": {}}}", position.x, position.y, line))
+            .collect();
+        format!("{{\"cells\": [\n  {}\n]}}\n", cells.join(",\n  "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Number(isize),
+    String(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::String(s) => write!(f, "{:?}", s),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+// longest symbols first, so they are not split up
+const SYMBOLS: [&str; 19] = [
+    "==", "!=", "<=", ">=", "<", ">", "=", "+", "-", "*", "/", "%", "!",
+    "(", ")", "{", "}", ",", ";",
+];
+
+// split source into tokens, each with its line
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, AheuiLangError> {
+    let mut tokens = Vec::new();
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut chars = text.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+            if text[start..].starts_with("//") {
+                break;
+            }
+            if c.is_ascii_digit() {
+                let mut end = start + 1;
+                while let Some((i, c)) = chars.next_if(|(_, c)| c.is_ascii_alphanumeric()) {
+                    end = i + c.len_utf8();
+                }
+                let number = &text[start..end];
+                let n = number.parse().map_err(|_| AheuiLangError::InvalidNumber(line, number.to_string()))?;
+                tokens.push((line, Token::Number(n)));
+            } else if c.is_alphabetic() || c == '_' {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_') {
+                    end = i + c.len_utf8();
+                }
+                tokens.push((line, Token::Name(text[start..end].to_string())));
+            } else if c == '"' || c == '\'' {
+                // strings and characters, with escapes
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, end)) if end == c => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => s.push('\n'),
+                            Some((_, 't')) => s.push('\t'),
+                            Some((_, escaped @ ('\\' | '"' | '\''))) => s.push(escaped),
+                            Some((_, escaped)) => return Err(AheuiLangError::InvalidCharacter(line, escaped)),
+                            None => return Err(AheuiLangError::UnexpectedEnd),
+                        },
+                        Some((_, c)) => s.push(c),
+                        None => return Err(AheuiLangError::UnexpectedEnd),
+                    };
+                }
+                let token = match (c, s.chars().next()) {
+                    ('"', _) => Token::String(s),
+                    (_, Some(character)) if s.chars().count() == 1 => Token::Number(character as isize),
+                    _ => return Err(AheuiLangError::UnexpectedToken(line, format!("'{}'", s))),
+                };
+                tokens.push((line, token));
+            } else {
+                let symbol = SYMBOLS
+                    .iter()
+                    .find(|symbol| text[start..].starts_with(**symbol))
+                    .ok_or(AheuiLangError::InvalidCharacter(line, c))?;
+                for _ in 1..symbol.len() {
+                    chars.next();
+                }
+                tokens.push((line, Token::Symbol(symbol)));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+#[derive(Debug, Clone)]
+enum Expression {
+    Number(isize),
+    Variable(usize, String),
+    Input,
+    InputChar,
+    Negate(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone)]
+enum Statement {
+    Assign(usize, String, Expression),
+    Print(usize, Expression),
+    PrintChar(usize, Expression),
+    PrintString(usize, String),
+    If(usize, Expression, Vec<Statement>, Vec<Statement>),
+    While(usize, Expression, Vec<Statement>),
+    Call(usize, String, Vec<Expression>),
+    Halt(usize),
+}
+
+struct Function {
+    parameters: Vec<String>,
+    body: Vec<Statement>,
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(_, token)| token)
+    }
+
+    // line of the next token, or of the last one at the end
+    fn line(&self) -> usize {
+        self.tokens.get(self.index).or(self.tokens.last()).map_or(1, |(line, _)| *line)
+    }
+
+    fn next(&mut self) -> Result<Token, AheuiLangError> {
+        let token = self.peek().cloned().ok_or(AheuiLangError::UnexpectedEnd)?;
+        self.index += 1;
+        Ok(token)
+    }
+
+    fn unexpected<T>(&self, token: Token) -> Result<T, AheuiLangError> {
+        Err(AheuiLangError::UnexpectedToken(self.tokens[self.index - 1].0, token.to_string()))
+    }
+
+    fn accept(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if found {
+            self.index += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), AheuiLangError> {
+        match self.next()? {
+            Token::Symbol(s) if s == symbol => Ok(()),
+            token => self.unexpected(token),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, AheuiLangError> {
+        match self.next()? {
+            Token::Name(name) => Ok(name),
+            token => self.unexpected(token),
+        }
+    }
+
+    fn program(&mut self) -> Result<(HashMap<String, Function>, Vec<Statement>), AheuiLangError> {
+        let mut functions = HashMap::new();
+        let mut statements = Vec::new();
+        while self.peek().is_some() {
+            if self.peek() == Some(&Token::Name("fn".to_string())) {
+                self.index += 1;
+                let line = self.line();
+                let name = self.name()?;
+                self.expect("(")?;
+                let mut parameters = Vec::new();
+                while !self.accept(")") {
+                    if !parameters.is_empty() {
+                        self.expect(",")?;
+                    }
+                    parameters.push(self.name()?);
+                }
+                let body = self.block()?;
+                if functions.insert(name.clone(), Function { parameters, body }).is_some() {
+                    return Err(AheuiLangError::DuplicateFunction(line, name));
+                }
+            } else {
+                statements.push(self.statement()?);
+            }
+        }
+        Ok((functions, statements))
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, AheuiLangError> {
+        self.expect("{")?;
+        let mut statements = Vec::new();
+        while !self.accept("}") {
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement, AheuiLangError> {
+        let line = self.line();
+        let name = self.name()?;
+        let statement = match name.as_str() {
+            "if" => {
+                let condition = self.expression()?;
+                let then = self.block()?;
+                let otherwise = match self.peek() {
+                    Some(Token::Name(name)) if name == "else" => {
+                        self.index += 1;
+                        match self.peek() {
+                            Some(Token::Name(name)) if name == "if" => vec![self.statement()?],
+                            _ => self.block()?,
+                        }
+                    },
+                    _ => Vec::new(),
+                };
+                return Ok(Statement::If(line, condition, then, otherwise));
+            },
+            "while" => {
+                let condition = self.expression()?;
+                return Ok(Statement::While(line, condition, self.block()?));
+            },
+            "print" => match self.peek() {
+                Some(Token::String(s)) => {
+                    let s = s.clone();
+                    self.index += 1;
+                    Statement::PrintString(line, s)
+                },
+                _ => Statement::Print(line, self.expression()?),
+            },
+            "printc" => Statement::PrintChar(line, self.expression()?),
+            "halt" => Statement::Halt(line),
+            _ if self.accept("=") => Statement::Assign(line, name, self.expression()?),
+            _ if self.accept("(") => {
+                let mut arguments = Vec::new();
+                while !self.accept(")") {
+                    if !arguments.is_empty() {
+                        self.expect(",")?;
+                    }
+                    arguments.push(self.expression()?);
+                }
+                Statement::Call(line, name, arguments)
+            },
+            _ => return self.unexpected(Token::Name(name)),
+        };
+        self.expect(";")?;
+        Ok(statement)
+    }
+
+    // comparisons bind loosest and do not chain,
+    // then addition and subtraction, then multiplication, division and modulo
+    fn expression(&mut self) -> Result<Expression, AheuiLangError> {
+        let left = self.sum()?;
+        let operator = match self.peek() {
+            Some(Token::Symbol("==")) => Operator::Equal,
+            Some(Token::Symbol("!=")) => Operator::NotEqual,
+            Some(Token::Symbol("<")) => Operator::Less,
+            Some(Token::Symbol("<=")) => Operator::LessEqual,
+            Some(Token::Symbol(">")) => Operator::Greater,
+            Some(Token::Symbol(">=")) => Operator::GreaterEqual,
+            _ => return Ok(left),
+        };
+        self.index += 1;
+        Ok(Expression::Binary(operator, Box::new(left), Box::new(self.sum()?)))
+    }
+
+    fn sum(&mut self) -> Result<Expression, AheuiLangError> {
+        let mut left = self.product()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Symbol("+")) => Operator::Add,
+                Some(Token::Symbol("-")) => Operator::Subtract,
+                _ => return Ok(left),
+            };
+            self.index += 1;
+            left = Expression::Binary(operator, Box::new(left), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Expression, AheuiLangError> {
+        let mut left = self.unary()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Symbol("*")) => Operator::Multiply,
+                Some(Token::Symbol("/")) => Operator::Divide,
+                Some(Token::Symbol("%")) => Operator::Modulo,
+                _ => return Ok(left),
+            };
+            self.index += 1;
+            left = Expression::Binary(operator, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expression, AheuiLangError> {
+        let line = self.line();
+        match self.next()? {
+            Token::Symbol("-") => Ok(Expression::Negate(Box::new(self.unary()?))),
+            Token::Symbol("!") => Ok(Expression::Not(Box::new(self.unary()?))),
+            Token::Symbol("(") => {
+                let expression = self.expression()?;
+                self.expect(")")?;
+                Ok(expression)
+            },
+            Token::Number(n) => Ok(Expression::Number(n)),
+            Token::Name(name) if name == "input" => Ok(Expression::Input),
+            Token::Name(name) if name == "inputc" => Ok(Expression::InputChar),
+            Token::Name(name) => Ok(Expression::Variable(line, name)),
+            token => self.unexpected(token),
+        }
+    }
+}
+
+// storages variables can be kept in, leaving out the one expressions are
+// worked out in and the queues
+fn variable_storages() -> impl Iterator<Item = usize> {
+    (1..CODA_JAMO.len()).filter(|storage| *storage != 21 && *storage != 27)
+}
+
+// lowering of statements to linear assembly, each line with the line it comes from
+//
+// expressions are worked out in the storage without a final consonant, which is
+// always selected between statements. each variable has a storage of its own which
+// holds exactly its value, starting at 0. functions are inlined where they are
+// called, with their parameters and variables in storages of their own
+struct Lowering<'a> {
+    functions: &'a HashMap<String, Function>,
+    // storage and first line of each variable, by the function it is in
+    variables: Vec<(Option<String>, String, usize, usize)>,
+    assembly: AheuiAsmBuilder,
+    calls: Vec<String>,
+}
+
+impl Lowering<'_> {
+    fn storage(&self, name: &str) -> Option<usize> {
+        self.variables
+            .iter()
+            .find(|(function, variable, _, _)| function.as_ref() == self.calls.last() && variable == name)
+            .map(|(_, _, storage, _)| *storage)
+    }
+
+    // storage of a variable being assigned, giving it one if it has none
+    fn assign_storage(&mut self, line: usize, name: &str) -> Result<usize, AheuiLangError> {
+        if let Some(storage) = self.storage(name) {
+            return Ok(storage);
+        }
+        let storage = variable_storages()
+            .nth(self.variables.len())
+            .ok_or_else(|| AheuiLangError::TooManyVariables(line, name.to_string()))?;
+        self.variables.push((self.calls.last().cloned(), name.to_string(), storage, line));
+        Ok(storage)
+    }
+
+    // replace a value on top of the working storage with whether it is zero
+    fn is_zero(&mut self, line: usize) {
+        for text in ["dup", "push 0", "cmp", "swap", "push 0", "swap", "cmp", "mul"] {
+            self.assembly.emit(line, text);
+        }
+    }
+
+    // push the value of an expression onto the working storage
+    fn expression(&mut self, line: usize, expression: &Expression) -> Result<(), AheuiLangError> {
+        match expression {
+            Expression::Number(n) => self.assembly.constant(line, *n),
+            Expression::Variable(line, name) => {
+                let storage = self.storage(name).ok_or_else(|| AheuiLangError::UndefinedVariable(*line, name.clone()))?;
+                self.assembly.emit(*line, format!("sel {}", CODA_JAMO[storage]));
+                self.assembly.emit(*line, "dup");
+                self.assembly.emit(*line, "mov");
+                self.assembly.emit(*line, "sel");
+            },
+            Expression::Input => self.assembly.emit(line, "push int"),
+            Expression::InputChar => self.assembly.emit(line, "push char"),
+            Expression::Negate(operand) => {
+                self.expression(line, operand)?;
+                self.assembly.emit(line, "push 0");
+                self.assembly.emit(line, "swap");
+                self.assembly.emit(line, "sub");
+            },
+            Expression::Not(operand) => {
+                self.expression(line, operand)?;
+                self.is_zero(line);
+            },
+            Expression::Binary(operator, left, right) => {
+                self.expression(line, left)?;
+                self.expression(line, right)?;
+                // ㅈ pushes whether the value below is at least the one on top
+                match operator {
+                    Operator::Add => self.assembly.emit(line, "add"),
+                    Operator::Subtract => self.assembly.emit(line, "sub"),
+                    Operator::Multiply => self.assembly.emit(line, "mul"),
+                    Operator::Divide => self.assembly.emit(line, "div"),
+                    Operator::Modulo => self.assembly.emit(line, "mod"),
+                    Operator::Equal | Operator::NotEqual => {
+                        self.assembly.emit(line, "sub");
+                        self.is_zero(line);
+                        if *operator == Operator::NotEqual {
+                            self.assembly.emit(line, "push 0");
+                            self.assembly.emit(line, "swap");
+                            self.assembly.emit(line, "cmp");
+                        }
+                    },
+                    Operator::GreaterEqual => self.assembly.emit(line, "cmp"),
+                    Operator::LessEqual => {
+                        self.assembly.emit(line, "swap");
+                        self.assembly.emit(line, "cmp");
+                    },
+                    Operator::Greater => {
+                        self.assembly.constant(line, 1);
+                        self.assembly.emit(line, "add");
+                        self.assembly.emit(line, "cmp");
+                    },
+                    Operator::Less => {
+                        self.assembly.emit(line, "swap");
+                        self.assembly.constant(line, 1);
+                        self.assembly.emit(line, "add");
+                        self.assembly.emit(line, "cmp");
+                    },
+                };
+            },
+        };
+        Ok(())
+    }
+
+    // replace the value of a variable with the one on top of the working storage,
+    // getting rid of the old one by adding it times zero to the new one
+    fn store(&mut self, line: usize, storage: usize) {
+        let jamo = CODA_JAMO[storage];
+        for text in [format!("mov {}", jamo), format!("sel {}", jamo)] {
+            self.assembly.emit(line, text);
+        }
+        for text in ["swap", "push 0", "mul", "add", "sel"] {
+            self.assembly.emit(line, text);
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) -> Result<(), AheuiLangError> {
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), AheuiLangError> {
+        match statement {
+            Statement::Assign(line, name, value) => {
+                self.expression(*line, value)?;
+                let storage = self.assign_storage(*line, name)?;
+                self.store(*line, storage);
+            },
+            Statement::Print(line, value) => {
+                self.expression(*line, value)?;
+                self.assembly.emit(*line, "pop int");
+            },
+            Statement::PrintChar(line, value) => {
+                self.expression(*line, value)?;
+                self.assembly.emit(*line, "pop char");
+            },
+            Statement::PrintString(line, s) => {
+                for c in s.chars() {
+                    self.assembly.constant(*line, c as isize);
+                    self.assembly.emit(*line, "pop char");
+                }
+            },
+            Statement::If(line, condition, then, otherwise) => {
+                // ㅊ reflects to the else branch when the condition is zero
+                let (other, end) = (self.assembly.label(), self.assembly.label());
+                self.expression(*line, condition)?;
+                self.assembly.emit(*line, format!("brz {}", other));
+                self.statements(then)?;
+                self.assembly.emit(*line, format!("jmp {}", end));
+                self.assembly.emit(*line, format!("{}:", other));
+                self.statements(otherwise)?;
+                self.assembly.emit(*line, format!("{}:", end));
+            },
+            Statement::While(line, condition, body) => {
+                let (start, end) = (self.assembly.label(), self.assembly.label());
+                self.assembly.emit(*line, format!("{}:", start));
+                self.expression(*line, condition)?;
+                self.assembly.emit(*line, format!("brz {}", end));
+                self.statements(body)?;
+                self.assembly.emit(*line, format!("jmp {}", start));
+                self.assembly.emit(*line, format!("{}:", end));
+            },
+            Statement::Call(line, name, arguments) => {
+                let function = self.functions.get(name).ok_or_else(|| AheuiLangError::UndefinedFunction(*line, name.clone()))?;
+                if function.parameters.len() != arguments.len() {
+                    return Err(AheuiLangError::WrongArgumentCount(*line, name.clone()));
+                }
+                if self.calls.contains(name) {
+                    return Err(AheuiLangError::RecursiveCall(*line, name.clone()));
+                }
+
+                // arguments are all worked out in the caller before being
+                // moved to the parameters, last first
+                for argument in arguments {
+                    self.expression(*line, argument)?;
+                }
+                self.calls.push(name.clone());
+                for parameter in function.parameters.iter().rev() {
+                    let storage = self.assign_storage(*line, parameter)?;
+                    self.store(*line, storage);
+                }
+                self.statements(&function.body)?;
+                self.calls.pop();
+            },
+            Statement::Halt(line) => self.assembly.emit(*line, "halt"),
+        };
+        Ok(())
+    }
+}
+
+// compile a program in the structured language to linear assembly,
+// with the line each line of assembly comes from
+fn lower(source: &str) -> Result<AheuiAsmBuilder, AheuiLangError> {
+    let mut parser = Parser { tokens: tokenize(source)?, index: 0 };
+    let (functions, statements) = parser.program()?;
+
+    let mut lowering = Lowering {
+        functions: &functions,
+        variables: Vec::new(),
+        assembly: AheuiAsmBuilder::new(),
+        calls: Vec::new(),
+    };
+    // running off the end of the assembly halts
+    lowering.statements(&statements)?;
+
+    // every variable starts at zero
+    let mut start = Vec::new();
+    for (_, _, storage, line) in lowering.variables.iter() {
+        start.push((format!("sel {}", CODA_JAMO[*storage]), *line));
+        start.push(("push 0".to_string(), *line));
+    }
+    if let Some((_, _, _, line)) = lowering.variables.first() {
+        start.push(("sel".to_string(), *line));
+    }
+    let mut assembly = lowering.assembly;
+    assembly.lines.splice(0..0, start);
+    Ok(assembly)
+}
+
+// compile a program in the structured language to linear assembly,
+// commenting each instruction with the line it comes from
+pub fn compile_to_assembly(source: &str) -> Result<String, AheuiLangError> {
+    let assembly = lower(source)?;
+    Ok(assembly.lines
+        .iter()
+        .map(|(text, line)| match text.ends_with(':') {
+            true => format!("{}\n", text),
+            false => format!("    {:<20} ; line {}\n", text, line),
+        })
+        .collect())
+}
+
+// compile a program in the structured language to an aheui grid,
+// with the line each of its cells comes from
+pub fn compile(source: &str) -> Result<(String, AheuiSourceMap), AheuiLangError> {
+    let (grid, cells) = lower(source)?.assemble().map_err(AheuiLangError::InvalidAssembly)?;
+    Ok((grid, AheuiSourceMap { cells }))
+}
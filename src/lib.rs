@@ -1,14 +1,45 @@
 pub mod hangul;
 pub mod instruction;
 pub mod component;
+pub mod flow;
+pub mod codegen;
+pub mod asm;
+pub mod analysis;
+pub mod lint;
+pub mod cycle;
+pub mod record;
+pub mod encoding;
+pub mod generate;
+pub mod lang;
+pub mod bf;
+pub mod transform;
+pub mod compose;
+pub mod format;
+pub mod obfuscate;
+pub mod golf;
+pub mod reduce;
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+pub mod jit;
 
-use std::io;
+use std::{fmt, io};
+use std::cell::RefCell;
 use std::io::{BufRead, BufWriter, Write};
+use std::rc::Rc;
 use crate::instruction::*;
 use crate::component::*;
+use crate::cycle::AheuiLoopDetector;
+use crate::record::*;
+use crate::encoding::AheuiEncoding;
 
 
-#[derive(Debug)]
+// what reading input does once there is none left
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AheuiEof {
+    #[default]
+    Stop, // stop with an EndOfInput error
+    Value(isize), // read this value instead, such as -1
+}
+
 pub struct AheuiState {
     pub program: AheuiProgram,
     pub terminated: bool,
@@ -16,14 +47,53 @@ pub struct AheuiState {
     pub storage_index: usize,
     pub position: AheuiCoordinates,
     pub direction: AheuiDirection,
-    input: io::Stdin,
-    output: BufWriter<io::Stdout>,
+    pub steps: usize,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    prompt: bool,
+    loop_detector: Option<AheuiLoopDetector>,
+    strict: bool,
+    recording: Option<AheuiRecording>,
+    encoding: AheuiEncoding,
+    eof: AheuiEof,
+}
+
+impl fmt::Debug for AheuiState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AheuiState")
+            .field("program", &self.program)
+            .field("terminated", &self.terminated)
+            .field("storages", &self.storages)
+            .field("storage_index", &self.storage_index)
+            .field("position", &self.position)
+            .field("direction", &self.direction)
+            .field("steps", &self.steps)
+            .finish()
+    }
 }
 
 impl AheuiState {
     pub fn init(prog: &str) -> Self {
+        // read from stdin and write buffered output to stdout,
+        // prompting the user whenever input is needed
+        let input = Box::new(io::BufReader::new(io::stdin()));
+        let output = Box::new(BufWriter::new(io::stdout()));
+        let mut state = Self::init_with_io(prog, input, output);
+        state.prompt = true;
+        state
+    }
+
+    // initialize a program that reads from input and writes to output
+    // without prompting for input
+    pub fn init_with_io(prog: &str, input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
         // convert input string into AheuiProgram
         let program = AheuiProgram::from_str(prog);
+        Self::from_program(program, input, output)
+    }
+
+    // initialize an already decoded program that reads from input
+    // and writes to output without prompting for input
+    pub fn from_program(program: AheuiProgram, input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
         let terminated = false;
 
         // initialize the 28 storages
@@ -43,10 +113,6 @@ impl AheuiState {
         // initialize direction to AheuiDirection::Down(false) (0,1)
         let direction = AheuiDirection::Down(false);
 
-        // initialize buffered output to stdout
-        let stdout = io::stdout();
-        let output = BufWriter::new(stdout);
-
         Self {
             program,
             terminated,
@@ -54,86 +120,121 @@ impl AheuiState {
             storage_index,
             position,
             direction,
-            input: io::stdin(),
+            steps: 0,
+            input,
             output,
+            prompt: false,
+            loop_detector: None,
+            strict: false,
+            recording: None,
+            encoding: AheuiEncoding::Utf8,
+            eof: AheuiEof::Stop,
         }
     }
 
     // Update current position based on current direction and new direction
     pub fn step_coordinate(&mut self, new_direction: AheuiDirection) {
-        let mut final_direction = self.direction;
-
-        // either reflect the program's current direction
-        // or update it to the current instruction's direction
-        // or do nothing (in case of null instruction)
-        match new_direction {
-            AheuiDirection::Null => {},
-            AheuiDirection::ReflectX => final_direction.reflect_x(),
-            AheuiDirection::ReflectY => final_direction.reflect_y(),
-            AheuiDirection::ReflectXY => final_direction.reflect_xy(),
-            _ => final_direction = new_direction,
-        };
+        let (position, direction) = self.program.next_position(self.position, self.direction, new_direction);
+        self.position = position;
+        self.direction = direction;
+    }
 
-        // update position based on final_direction
-        match final_direction {
-            AheuiDirection::Left(b) => {
-                let movement = 1 + b as usize;
-                // in case movement goes beyond left boundary
-                if self.position.x < movement {
-                    // wrap around to right side of program
-                    self.position.x = self.program.size.x - (movement - self.position.x);
-                } else {
-                    // otherwise just subtract movement
-                    self.position.x -= movement;
-                }
-            },
-            AheuiDirection::Right(b) => {
-                let movement = 1 + b as usize;
-                // in case movement goes beyond right boundary
-                if self.position.x >= self.program.size.x - movement {
-                    // wrap around to right side of program
-                    self.position.x = movement - (self.program.size.x - self.position.x);
-                } else {
-                    // otherwise just add movement
-                    self.position.x += movement;
-                }
-            },
-            AheuiDirection::Up(b) => {
-                let movement = 1 + b as usize;
-                // in case movement goes beyond upper boundary
-                if self.position.y < movement {
-                    // wrap around to bottom side of program
-                    self.position.y = self.program.size.y - (movement - self.position.y);
-                } else {
-                    // otherwise just subtract movement
-                    self.position.y -= movement;
-                }
+    // stop with an InfiniteLoopError once the program returns to
+    // a state it has been in before without reading input since
+    pub fn detect_loops(&mut self) {
+        self.loop_detector = Some(AheuiLoopDetector::new());
+    }
+
+    // stop with a StorageUnderflow error when an instruction lacks values,
+    // instead of reflecting
+    pub fn strict_mode(&mut self) {
+        self.strict = true;
+    }
+
+    // write characters popped to output in encoding instead of UTF-8
+    pub fn output_encoding(&mut self, encoding: AheuiEncoding) {
+        self.encoding = encoding;
+    }
+
+    // read value instead of stopping once input has run out
+    pub fn end_of_input(&mut self, eof: AheuiEof) {
+        self.eof = eof;
+    }
+
+    // keep every value read and every chunk of output written from now on
+    pub fn record(&mut self) {
+        self.recording = Some(AheuiRecording::new());
+    }
+
+    // what was read and written since record was called
+    pub fn recording(&self) -> Option<&AheuiRecording> {
+        self.recording.as_ref()
+    }
+
+    // add an event of the current step to the recording, if there is one
+    fn record_event(&mut self, event: impl FnOnce(usize) -> AheuiEvent) {
+        let step = self.steps + 1;
+        if let Some(recording) = self.recording.as_mut() {
+            recording.events.push(event(step));
+        }
+    }
+
+    // fatal error for an instruction lacking values in the selected storage
+    fn storage_underflow(&mut self, operation: AheuiOperation) -> AheuiError {
+        self.terminated = true;
+        let depth = self.storages[self.storage_index].len();
+        AheuiError::StorageUnderflow(operation, self.storage_index, depth)
+    }
+
+    // value read once input has run out, or a fatal error
+    fn read_past_end(&mut self) -> Result<isize, AheuiError> {
+        match self.eof {
+            AheuiEof::Stop => {
+                self.terminated = true;
+                Err(AheuiError::EndOfInput)
             },
-            AheuiDirection::Down(b) => {
-                let movement = 1 + b as usize;
-                // in case movement goes beyond bottom boundary
-                if self.position.y >= self.program.size.y - movement {
-                    // wrap around to upper side of program
-                    self.position.y = movement - (self.program.size.y - self.position.y);
-                } else {
-                    // otherwise just add movement
-                    self.position.y += movement;
-                }
-            }
-            _ => {},
-        };
+            AheuiEof::Value(value) => Ok(value),
+        }
+    }
 
-        self.direction = final_direction;
+    // run one instruction, adding where the program was to any error
+    pub fn step(&mut self) -> Result<(), AheuiRuntimeError> {
+        let position = self.position;
+        let direction = self.direction;
+        let storage_index = self.storage_index;
+        match self.execute() {
+            Ok(()) => {
+                self.steps += 1;
+                Ok(())
+            },
+            Err(error) => Err(AheuiRuntimeError {
+                error,
+                position,
+                character: self.program.get_instruction(&position).map(|instruction| instruction.character),
+                direction,
+                step: self.steps + 1,
+                storage_index,
+            }),
+        }
     }
 
-    pub fn step(&mut self) -> Result<(), AheuiError> {
+    fn execute(&mut self) -> Result<(), AheuiError> {
         // return an error if program has terminated
         if self.terminated {
             return Err(AheuiError::TerminatedError);
         }
+
+        if let Some(mut detector) = self.loop_detector.take() {
+            let repeat = detector.check(self);
+            self.loop_detector = Some(detector);
+            if let Some((period, cells)) = repeat {
+                self.terminated = true;
+                return Err(AheuiError::InfiniteLoopError(period, cells));
+            }
+        }
         // get current instruction, or terminate if failed
         let instruction = match self.program.get_instruction(&self.position) {
-            Some(op) => op,
+            Some(op) => *op,
             None => {
                 self.terminated = true;
                 //self.output.flush().unwrap();
@@ -148,10 +249,7 @@ impl AheuiState {
             AheuiOperation::Terminate => {
                 // terminate program, and flush output
                 self.terminated = true;
-                match write!(self.output, "\n") {
-                    Ok(()) => {},
-                    Err(_) => {},
-                };
+                let _ = writeln!(self.output);
                 //self.output.flush().unwrap();
                 return Ok(());
             },
@@ -175,6 +273,8 @@ impl AheuiState {
                         })?;
                     current_storage.push(a_b);
                     success = true;
+                } else if self.strict {
+                    return Err(self.storage_underflow(instruction.operation));
                 }
             },
             AheuiOperation::Pop => {
@@ -185,30 +285,33 @@ impl AheuiState {
                         AheuiArgument::AsInt => {
                             // convert number to string representation then output
                             let num_string = num.to_string();
-                            match self.output.write_all(&num_string.into_bytes()) {
+                            match self.output.write_all(num_string.as_bytes()) {
                                 Ok(()) => success = true,
                                 Err(e) => return Err(AheuiError::OutputError(e)),
                             };
+                            self.record_event(|step| AheuiEvent::Output(step, num_string));
                         },
                         AheuiArgument::AsChar => {
-                            // attempt to convert number to char
-                            let num_char = char::from_u32(num as u32)
+                            // attempt to encode the number as a character
+                            let encoding = self.encoding;
+                            let (num_char, bytes) = encoding.character(num)
+                                .zip(encoding.encode(num))
                                 .ok_or_else(|| {
                                     self.terminated = true;
-                                    //self.output.flush().unwrap();
-                                    AheuiError::InvalidCharError(num as u32)
+                                    AheuiError::InvalidCharError(num, encoding)
                                 })?;
 
-                            // then convert char to byte array then output
-                            let mut char_array = [0; 4];
-                            num_char.encode_utf8(&mut char_array);
-                            match self.output.write_all(&char_array) {
+                            // then output only the bytes of its encoding
+                            match self.output.write_all(&bytes) {
                                 Ok(()) => success = true,
                                 Err(e) => return Err(AheuiError::OutputError(e)),
                             };
+                            self.record_event(|step| AheuiEvent::Output(step, num_char.to_string()));
                         },
                         _ => {},
                     };
+                } else if self.strict {
+                    return Err(self.storage_underflow(instruction.operation));
                 }
             },
             AheuiOperation::Push => {
@@ -223,13 +326,19 @@ impl AheuiState {
                     AheuiArgument::AsInt => {
                         // read a number from stdin
                         let mut buffer = String::new();
-                        let mut stdin_handle = self.input.lock();
 
                         // prompt user for input...
-                        print!("\ninput number: ");
-                        self.output.flush().expect("could not flush stdout");
+                        if self.prompt {
+                            write!(self.output, "\ninput number: ").map_err(AheuiError::OutputError)?;
+                        }
+                        self.output.flush().map_err(AheuiError::OutputError)?;
 
-                        match stdin_handle.read_line(&mut buffer) {
+                        match self.input.read_line(&mut buffer) {
+                            Ok(0) => {
+                                let num = self.read_past_end()?;
+                                self.storages[self.storage_index].push(num);
+                                success = true;
+                            },
                             Ok(_) => {
                                 // ... then attempt conversion into an isize and push
                                 let num: isize = buffer
@@ -239,6 +348,7 @@ impl AheuiState {
                                 let current_storage = self.storages.get_mut(self.storage_index).unwrap();
                                 current_storage.push(num);
                                 success = true;
+                                self.record_event(|step| AheuiEvent::InputNumber(step, num));
                             },
                             Err(e) => return Err(AheuiError::InputError(e)),
                         };
@@ -246,24 +356,31 @@ impl AheuiState {
                     AheuiArgument::AsChar => {
                         // read string from stdin
                         let mut buffer = String::new();
-                        let mut stdin_handle = self.input.lock();
 
                         // prompt user for input
-                        print!("\ninput character: ");
-                        self.output.flush().expect("could not flush stdout");
+                        if self.prompt {
+                            write!(self.output, "\ninput character: ").map_err(AheuiError::OutputError)?;
+                        }
+                        self.output.flush().map_err(AheuiError::OutputError)?;
 
-                        match stdin_handle.read_line(&mut buffer) {
+                        match self.input.read_line(&mut buffer) {
+                            Ok(0) => {
+                                let num = self.read_past_end()?;
+                                self.storages[self.storage_index].push(num);
+                                success = true;
+                            },
                             Ok(_) => {
                                 // then get first char in input
                                 let first_char = buffer
                                     .trim()
                                     .chars()
-                                    .nth(0)
-                                    .ok_or_else(|| AheuiError::EmptyInputError)?;
+                                    .next()
+                                    .ok_or(AheuiError::EmptyInputError)?;
                                 let num = first_char as isize;
                                 let current_storage = self.storages.get_mut(self.storage_index).unwrap();
                                 current_storage.push(num);
                                 success = true;
+                                self.record_event(|step| AheuiEvent::InputChar(step, first_char));
                             },
                             Err(e) => return Err(AheuiError::InputError(e)),
                         }
@@ -274,15 +391,23 @@ impl AheuiState {
             AheuiOperation::Duplicate => {
                 // duplicate the first element in storage
                 let current_storage = self.storages.get_mut(self.storage_index).unwrap();
-                if let Ok(_) = current_storage.duplicate() {
-                    success = true;
+                match current_storage.duplicate() {
+                    Ok(()) => success = true,
+                    Err(AheuiError::StorageSizeError) if self.strict => {
+                        return Err(self.storage_underflow(instruction.operation));
+                    },
+                    Err(_) => {},
                 };
             },
             AheuiOperation::Swap => {
                 // swap the top two values in storage
                 let current_storage = self.storages.get_mut(self.storage_index).unwrap();
-                if let Ok(_) = current_storage.swap() {
-                    success = true;
+                match current_storage.swap() {
+                    Ok(()) => success = true,
+                    Err(AheuiError::StorageSizeError) if self.strict => {
+                        return Err(self.storage_underflow(instruction.operation));
+                    },
+                    Err(_) => {},
                 };
             },
             AheuiOperation::StoreSelect => {
@@ -306,6 +431,8 @@ impl AheuiState {
                         let dest_storage = self.storages.get_mut(dest).unwrap();
                         dest_storage.push(current_num);
                         success = true;
+                    } else if self.strict {
+                        return Err(self.storage_underflow(instruction.operation));
                     };
                 };
             },
@@ -317,6 +444,9 @@ impl AheuiState {
                     let value1 = current_storage.pop().unwrap();
                     let value2 = current_storage.pop().unwrap();
                     current_storage.push((value1 <= value2) as isize);
+                    success = true;
+                } else if self.strict {
+                    return Err(self.storage_underflow(instruction.operation));
                 }
             },
 
@@ -326,6 +456,8 @@ impl AheuiState {
                 let current_storage = self.storages.get_mut(self.storage_index).unwrap();
                 if let Some(num) = current_storage.pop() {
                     success = num != 0;
+                } else if self.strict {
+                    return Err(self.storage_underflow(instruction.operation));
                 };
             },
         };
@@ -348,9 +480,103 @@ impl AheuiState {
         // run until terminated, printing any errors encountered
         while !self.terminated {
             if let Err(err) = self.step() {
-                self.output.flush().expect("Could not flush stdout");
+                self.flush_output();
                 eprintln!("{err}");
             }
         }
     }
+
+    pub fn flush_output(&mut self) {
+        self.output.flush().expect("Could not flush stdout");
+    }
+}
+
+// writer sharing its buffer, so output can be read back after running
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    // everything written so far
+    pub fn bytes(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// how a run limited to a number of steps ended
+#[derive(Debug)]
+pub enum AheuiEnding {
+    Terminated,
+    Failed(AheuiRuntimeError),
+    OutOfSteps,
+}
+
+// what a run wrote before it ended, however it did
+#[derive(Debug)]
+pub struct AheuiOutcome {
+    pub output: String,
+    pub steps: usize,
+    pub ending: AheuiEnding,
+}
+
+impl AheuiOutcome {
+    // whether two runs wrote the same and ended the same way,
+    // wherever in their programs they did
+    pub fn same_behavior(&self, other: &AheuiOutcome) -> bool {
+        self.output == other.output && match (&self.ending, &other.ending) {
+            (AheuiEnding::Terminated, AheuiEnding::Terminated) => true,
+            (AheuiEnding::Failed(a), AheuiEnding::Failed(b)) => a.error.to_string() == b.error.to_string(),
+            (AheuiEnding::OutOfSteps, AheuiEnding::OutOfSteps) => true,
+            _ => false,
+        }
+    }
+}
+
+// run a program on the given input for at most limit steps,
+// keeping what it wrote before stopping at the first error
+pub fn run_within(program: AheuiProgram, input: &str, limit: usize) -> AheuiOutcome {
+    let output = SharedBuffer::default();
+    let mut state = AheuiState::from_program(
+        program,
+        Box::new(io::Cursor::new(input.as_bytes().to_vec())),
+        Box::new(output.clone()),
+    );
+
+    let ending = loop {
+        if state.terminated {
+            break AheuiEnding::Terminated;
+        }
+        if state.steps >= limit {
+            break AheuiEnding::OutOfSteps;
+        }
+        if let Err(error) = state.step() {
+            break AheuiEnding::Failed(error);
+        }
+    };
+    state.flush_output();
+
+    AheuiOutcome {
+        output: String::from_utf8_lossy(&output.bytes()).into_owned(),
+        steps: state.steps,
+        ending,
+    }
+}
+
+// run a program to completion on the given input, returning its output
+// stops at the first error, since input can not be asked for again
+pub fn run_to_string(program: AheuiProgram, input: &str) -> Result<String, AheuiRuntimeError> {
+    let outcome = run_within(program, input, usize::MAX);
+    match outcome.ending {
+        AheuiEnding::Failed(error) => Err(error),
+        _ => Ok(outcome.output),
+    }
 }
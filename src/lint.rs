@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+use crate::hangul::*;
+use crate::instruction::*;
+use crate::component::*;
+use crate::flow::*;
+use crate::analysis::*;
+
+// kinds of problems reported by the linter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AheuiLintKind {
+    Unreachable, // cell can never be reached from the start
+    NonHangul, // non-hangul character on the execution path
+    Jamo, // jamo on the execution path, where a syllable was likely intended
+    DiscardingPop, // ㅁ with a coda other than ㅇ or ㅎ, which always reflects
+    Underflow, // instruction always lacks values in its storage
+    DivisionByZero, // ㄴ or ㄹ dividing by a value known to be zero
+    ExtensionStorage, // selecting or moving to the ㅎ storage
+    NoTermination, // execution can never reach ㅎ from here
+}
+
+impl AheuiLintKind {
+    // name used in diagnostics
+    pub fn name(&self) -> &'static str {
+        match self {
+            AheuiLintKind::Unreachable => "unreachable",
+            AheuiLintKind::NonHangul => "non-hangul",
+            AheuiLintKind::Jamo => "jamo",
+            AheuiLintKind::DiscardingPop => "discarding-pop",
+            AheuiLintKind::Underflow => "underflow",
+            AheuiLintKind::DivisionByZero => "division-by-zero",
+            AheuiLintKind::ExtensionStorage => "extension-storage",
+            AheuiLintKind::NoTermination => "no-termination",
+        }
+    }
+}
+
+// a problem found at a cell of a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AheuiLint {
+    pub kind: AheuiLintKind,
+    pub position: AheuiCoordinates,
+    pub message: String,
+}
+
+impl AheuiProgram {
+    // find likely mistakes in a program, ordered by position
+    pub fn lint(&self) -> Vec<AheuiLint> {
+        let analysis = AheuiAnalysis::from_program(self);
+        let (graph, states) = (&analysis.graph, &analysis.states);
+        let mut lints = Vec::new();
+        let mut add = |kind, position, message| lints.push(AheuiLint { kind, position, message });
+
+        // only follow paths the storage depths allow, so reflections
+        // which can never happen do not lead anywhere
+        let reached: Vec<&AheuiFlowNode> = graph.nodes
+            .iter()
+            .zip(states.iter())
+            .filter(|(_, state)| state.is_some())
+            .map(|(node, _)| node)
+            .collect();
+
+        // cells never visited, ignoring whitespace
+        let positions: HashSet<AheuiCoordinates> = reached.iter().map(|node| node.position).collect();
+        for (y, row) in self.program.iter().enumerate() {
+            for (x, instruction) in row.iter().enumerate() {
+                let position = AheuiCoordinates { x, y };
+                if !instruction.character.is_whitespace() && !positions.contains(&position) {
+                    add(AheuiLintKind::Unreachable, position, format!("{} can never be reached", instruction.character));
+                }
+            }
+        }
+
+        // problems with the instructions themselves, once per cell
+        let mut seen = HashSet::new();
+        for node in reached.iter().filter(|node| seen.insert(node.position)) {
+            let character = node.instruction.character;
+            if is_jamo(character) {
+                add(AheuiLintKind::Jamo, node.position, format!("{} is a jamo rather than a syllable, and is executed as a null instruction", character));
+            } else if !character.is_whitespace() && HangulSyllable::from_char(character).is_none() {
+                add(AheuiLintKind::NonHangul, node.position, format!("non-hangul character {:?} is executed as a null instruction", character));
+            }
+            if node.instruction.operation == AheuiOperation::Pop && node.instruction.argument == AheuiArgument::Null {
+                add(AheuiLintKind::DiscardingPop, node.position, format!("{} has a final consonant other than ㅇ or ㅎ, and always reflects", character));
+            }
+            if matches!(node.instruction.argument, AheuiArgument::Storage(27)) {
+                add(AheuiLintKind::ExtensionStorage, node.position, format!("{} uses the ㅎ extension storage, which has no defined behavior", character));
+            }
+        }
+
+        // instructions which lack values whenever they are reached,
+        // except discarding pops which reflect either way
+        let mut seen = HashSet::new();
+        for (index, node) in graph.nodes.iter().enumerate() {
+            let discarding = node.instruction.operation == AheuiOperation::Pop && node.instruction.argument == AheuiArgument::Null;
+            if discarding || !analysis.always_underflows(index) || !seen.insert(node.position) {
+                continue;
+            }
+            let state = states[index].as_ref().unwrap();
+            let storage = state.selected().unwrap();
+            add(AheuiLintKind::Underflow, node.position, format!(
+                "{} needs {} values, but the storage {} holds at most {} when moving {:?}",
+                node.instruction.character, node.instruction.operation.required_depth(),
+                storage_name(state.storage_index.unwrap()), storage.depth.high.unwrap(), node.direction,
+            ));
+        }
+
+        // divisions by a constant zero
+        let mut seen = HashSet::new();
+        for (index, node) in graph.nodes.iter().enumerate() {
+            if analysis.divides_by_zero(index) && seen.insert(node.position) {
+                add(AheuiLintKind::DivisionByZero, node.position, format!(
+                    "{} divides by zero when moving {:?}", node.instruction.character, node.direction,
+                ));
+            }
+        }
+
+        // nodes which can never reach termination, where they are entered from one which can
+        let successors: Vec<Vec<usize>> = (0..graph.nodes.len())
+            .map(|index| analysis.successors(index))
+            .collect();
+        let mut terminates = vec![false; graph.nodes.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (index, node) in graph.nodes.iter().enumerate() {
+                let next = successors[index].iter().any(|next| terminates[*next]);
+                if !terminates[index] && states[index].is_some() && (node.instruction.operation == AheuiOperation::Terminate || next) {
+                    terminates[index] = true;
+                    changed = true;
+                }
+            }
+        }
+        let mut entries = HashSet::new();
+        if terminates.first() == Some(&false) {
+            entries.insert(0);
+        }
+        for (index, next) in successors.iter().enumerate() {
+            if terminates[index] {
+                entries.extend(next.iter().filter(|next| !terminates[**next]));
+            }
+        }
+        let mut entries: Vec<usize> = entries.into_iter().collect();
+        entries.sort();
+        for index in entries {
+            let node = &graph.nodes[index];
+            add(AheuiLintKind::NoTermination, node.position, format!(
+                "execution can never terminate after reaching {} moving {:?}",
+                node.instruction.character, node.direction,
+            ));
+        }
+
+        lints.sort_by_key(|lint| (lint.position.y, lint.position.x, lint.kind));
+        lints
+    }
+}
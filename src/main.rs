@@ -1,22 +1,621 @@
 use std::env;
+use std::io;
 use std::io::prelude::*;
 use std::fs::File;
-use libaheui::AheuiState;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+use libaheui::{AheuiState, AheuiEnding, AheuiEof, run_within};
+use libaheui::component::{AheuiProgram, AheuiRuntimeError};
+use libaheui::codegen;
+use libaheui::asm;
+use libaheui::generate;
+use libaheui::lang;
+use libaheui::bf;
+use libaheui::transform::{self, AheuiTransform};
+use libaheui::format;
+use libaheui::obfuscate::obfuscate;
+use libaheui::golf::golf;
+use libaheui::reduce::reduce;
+use libaheui::analysis::AheuiAnalysis;
+use libaheui::record::{AheuiRecording, json_string};
+use libaheui::encoding::{AheuiEncoding, decode_source};
+use libaheui::hangul::is_jamo;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    
-    if args.len() != 2 {
-        println!("error: supply a program file to run");
-        return;
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // dispatch subcommands, running a program file otherwise
+    let result = match args.first().map(|arg| arg.as_str()) {
+        Some("compile") => compile(&args[1..]),
+        Some("disasm") => disasm(&args[1..]),
+        Some("asm") => assemble(&args[1..]),
+        Some("lint") => lint(&args[1..]),
+        Some("analyze") => analyze(&args[1..]),
+        Some("gen-print") => gen_print(&args[1..]),
+        Some("gen-constant") => gen_constant(&args[1..]),
+        Some("lang") => lang(&args[1..]),
+        Some("from-bf") => from_bf(&args[1..]),
+        Some("rotate") => rotate(&args[1..]),
+        Some("mirror") => mirror(&args[1..]),
+        Some("transpose") => transform_file(AheuiTransform::Transpose, &args[1..]),
+        Some("fmt") => fmt(&args[1..]),
+        Some("obfuscate") => obfuscate_file(&args[1..]),
+        Some("golf") => golf_file(&args[1..]),
+        Some("reduce") => reduce_file(&args[1..]),
+        _ => run(&args),
+    };
+
+    // errors go to stderr with a failing status, so that output
+    // redirected to a file is not mistaken for a result
+    if let Err(message) = result {
+        eprintln!("error: {}", message);
+        std::process::exit(1);
+    }
+}
+
+// read a source file into a string, detecting its encoding unless one is given
+fn read_source(filename: &str, encoding: Option<AheuiEncoding>) -> Result<String, String> {
+    let mut f = File::open(filename).map_err(|e| format!("can not open {}: {}", filename, e))?;
+    let mut buffer = Vec::new();
+
+    f.read_to_end(&mut buffer).map_err(|e| format!("can not read {}: {}", filename, e))?;
+    decode_source(&buffer, encoding).map_err(|e| format!("can not read {}: {}", filename, e))
+}
+
+// encoding named by the argument of an option
+fn parse_encoding(name: Option<&String>) -> Result<AheuiEncoding, String> {
+    let name = name.ok_or("supply an encoding")?;
+    AheuiEncoding::from_name(name).ok_or_else(|| format!("unknown encoding {}", name))
+}
+
+// value read at the end of input, given as the argument of --eof
+fn parse_eof(value: Option<&String>) -> Result<AheuiEof, String> {
+    let value = value.ok_or("supply a value to read at the end of input")?;
+    value.parse().map(AheuiEof::Value).map_err(|_| format!("invalid value {}", value))
+}
+
+// write generated output to a file, or stdout if none was given
+fn write_output(filename: Option<&str>, contents: &str) -> Result<(), String> {
+    match filename {
+        Some(filename) => {
+            let mut f = File::create(filename).map_err(|e| format!("can not create {}: {}", filename, e))?;
+            f.write_all(contents.as_bytes()).map_err(|e| format!("can not write {}: {}", filename, e))
+        },
+        None => {
+            print!("{}", contents);
+            Ok(())
+        },
+    }
+}
+
+// rsaheui [--jit] [--detect-loops] [--strict] [--record LOG] [--replay LOG] [--encoding ENCODING] [--output-encoding ENCODING] [--eof VALUE] FILE
+fn run(args: &[String]) -> Result<(), String> {
+    let mut use_jit = false;
+    let mut detect_loops = false;
+    let mut strict = false;
+    let mut record = None;
+    let mut replay = None;
+    let mut source_encoding = None;
+    let mut encoding = AheuiEncoding::Utf8;
+    let mut eof = AheuiEof::Stop;
+    let mut file = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--jit" => use_jit = true,
+            "--detect-loops" => detect_loops = true,
+            "--strict" => strict = true,
+            "--record" => record = Some(args.next().ok_or("supply a file to record to")?.as_str()),
+            "--replay" => replay = Some(args.next().ok_or("supply a recording to replay")?.as_str()),
+            "--encoding" => source_encoding = Some(parse_encoding(args.next())?),
+            "--output-encoding" => encoding = parse_encoding(args.next())?,
+            "--eof" => eof = parse_eof(args.next())?,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if file.is_none() => file = Some(arg.as_str()),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        };
+    }
+
+    let file = file.ok_or("supply a program file to run")?;
+    let buffer = read_source(file, source_encoding)?;
+
+    // a replay reads the recorded values instead of asking for input
+    let expected = match replay {
+        Some(filename) => Some(AheuiRecording::from_json(&read_source(filename, Some(AheuiEncoding::Utf8))?).map_err(|e| format!("can not read {}: {}", filename, e))?),
+        None => None,
+    };
+    let mut program = match &expected {
+        Some(recording) => AheuiState::init_with_io(
+            &buffer,
+            Box::new(io::Cursor::new(recording.input().into_bytes())),
+            Box::new(io::BufWriter::new(io::stdout())),
+        ),
+        None => AheuiState::init(&buffer),
+    };
+    // jamo are most likely syllables which were not typed or saved properly
+    for (y, row) in program.program.program.iter().enumerate() {
+        for (x, instruction) in row.iter().enumerate() {
+            if is_jamo(instruction.character) {
+                eprintln!("warning: {}:{}:{}: {} is a jamo rather than a syllable, and does nothing", file, y + 1, x + 1, instruction.character);
+            }
+        }
+    }
+
+    if detect_loops {
+        program.detect_loops();
+    }
+    if strict {
+        program.strict_mode();
+    }
+    program.output_encoding(encoding);
+    program.end_of_input(eof);
+    if record.is_some() || replay.is_some() {
+        program.record();
+    }
+    if use_jit {
+        run_jit(&mut program, file, replay.is_some());
+    } else {
+        execute(&mut program, file, replay.is_some(), AheuiState::step);
+    }
+
+    // dropping the program flushes its output before the replay is reported on
+    let recording = program.recording().cloned().unwrap_or_default();
+    drop(program);
+    if let Some(filename) = record {
+        write_output(Some(filename), &recording.to_json())?;
+    }
+    if let Some(expected) = expected {
+        if let Some(divergence) = expected.divergence(&recording) {
+            eprintln!("replay diverged: {}", divergence);
+            std::process::exit(1);
+        }
+        eprintln!("replay matches the recording");
+    }
+
+    Ok(())
+}
+
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+fn run_jit(program: &mut AheuiState, filename: &str, replay: bool) {
+    let mut jit = libaheui::jit::AheuiJit::new();
+    execute(program, filename, replay, |state| jit.step(state));
+}
+
+#[cfg(not(all(feature = "jit", target_arch = "x86_64", target_os = "linux")))]
+fn run_jit(program: &mut AheuiState, filename: &str, replay: bool) {
+    eprintln!("warning: rsaheui was built without the jit feature, interpreting instead");
+    execute(program, filename, replay, AheuiState::step);
+}
+
+// run a program until terminated, reporting each error
+// with the part of the program it happened in
+// a replay stops at the first error, since recorded input can not be asked for again
+fn execute(program: &mut AheuiState, filename: &str, replay: bool, mut step: impl FnMut(&mut AheuiState) -> Result<(), AheuiRuntimeError>) {
+    while !program.terminated {
+        if let Err(err) = step(program) {
+            program.flush_output();
+            eprintln!("{}", err);
+            eprintln!(" --> {}:{}:{}", filename, err.position.y + 1, err.position.x + 1);
+            eprintln!("{}", err.excerpt(&program.program));
+            if let Some(source) = err.source() {
+                eprintln!("caused by: {}", source);
+            }
+            if replay {
+                break;
+            }
+        }
+    }
+}
+
+// rsaheui compile --target TARGET [--strict] [--output-encoding ENCODING] [--eof VALUE] [--encoding ENCODING] [-o OUTPUT] FILE
+// the options of the interpreter only apply to c
+fn compile(args: &[String]) -> Result<(), String> {
+    let mut target = None;
+    let mut options = codegen::c::CompileOptions::default();
+    let (file, output, encoding) = input_output(args, "supply a program file to compile", |arg, args| {
+        match arg {
+            "--target" => target = args.next().map(|s| s.as_str()),
+            "--strict" => options.strict = true,
+            "--output-encoding" => options.encoding = parse_encoding(args.next())?,
+            "--eof" => options.eof = parse_eof(args.next())?,
+            _ => return Ok(false),
+        };
+        Ok(true)
+    })?;
+    let program = AheuiProgram::from_str(&read_source(file, encoding)?);
+
+    let source = match target {
+        Some("c") => codegen::c::compile(&program, &options),
+        Some("wat") => codegen::wat::compile(&program),
+        Some(target) => return Err(format!("unknown target {}", target)),
+        None => return Err("supply a target with --target".to_string()),
+    };
+
+    write_output(output, &source)
+}
+
+// arguments left to a subcommand after one of its flags
+type Args<'a> = std::slice::Iter<'a, String>;
+
+// get the input file, -o output file and --encoding of a subcommand
+// its other flags go to flag, which takes any values they have from
+// the arguments left and returns false for flags it does not know
+fn input_output<'a>(
+    args: &'a [String],
+    usage: &str,
+    mut flag: impl FnMut(&'a str, &mut Args<'a>) -> Result<bool, String>,
+) -> Result<(&'a str, Option<&'a str>, Option<AheuiEncoding>), String> {
+    let mut output = None;
+    let mut encoding = None;
+    let mut file = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = args.next().map(|s| s.as_str()),
+            "--encoding" => encoding = Some(parse_encoding(args.next())?),
+            _ if file.is_none() && !arg.starts_with('-') => file = Some(arg.as_str()),
+            _ if flag(arg, &mut args)? => {},
+            _ => return Err(format!("unexpected argument {}", arg)),
+        };
+    }
+
+    let file = file.ok_or(usage)?;
+    Ok((file, output, encoding))
+}
+
+// rsaheui disasm [--encoding ENCODING] [-o OUTPUT] FILE
+fn disasm(args: &[String]) -> Result<(), String> {
+    let (file, output, encoding) = input_output(args, "supply a program file to disassemble", |_, _| Ok(false))?;
+    let program = AheuiProgram::from_str(&read_source(file, encoding)?);
+    write_output(output, &asm::disassemble(&program))
+}
+
+// rsaheui asm [--encoding ENCODING] [-o OUTPUT] FILE
+fn assemble(args: &[String]) -> Result<(), String> {
+    let (file, output, encoding) = input_output(args, "supply an assembly file to assemble", |_, _| Ok(false))?;
+    let source = asm::assemble(&read_source(file, encoding)?).map_err(|e| e.to_string())?;
+    write_output(output, &source)
+}
+
+// rsaheui lint [--json] [--encoding ENCODING] [-o OUTPUT] FILE
+// exits with status 1 if any problems were found
+fn lint(args: &[String]) -> Result<(), String> {
+    let mut json = false;
+    let (file, output, encoding) = input_output(args, "supply a program file to lint", |arg, _| {
+        match arg {
+            "--json" => json = true,
+            _ => return Ok(false),
+        };
+        Ok(true)
+    })?;
+    let lints = AheuiProgram::from_str(&read_source(file, encoding)?).lint();
+
+    let mut report = String::new();
+    if json {
+        // one object per problem, with zero-based coordinates
+        let objects: Vec<String> = lints
+            .iter()
+            .map(|lint| format!(
+                "{{\"kind\": {}, \"x\": {}, \"y\": {}, \"message\": {}}}",
+                json_string(lint.kind.name()), lint.position.x, lint.position.y, json_string(&lint.message),
+            ))
+            .collect();
+        report.push_str(&format!("[{}]\n", objects.join(",\n ")));
+    } else {
+        // file:line:column, counting from one
+        for lint in lints.iter() {
+            report.push_str(&format!("{}:{}:{}: warning[{}]: {}\n", file, lint.position.y + 1, lint.position.x + 1, lint.kind.name(), lint.message));
+        }
     }
+    write_output(output, &report)?;
+
+    if !lints.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
 
-    let filename = &args[1];
-    let mut f = File::open(filename).expect("error opening file!");
-    let mut buffer = String::new();
+// rsaheui analyze [--encoding ENCODING] [-o OUTPUT] FILE
+// print the stack effect of every reachable instruction
+fn analyze(args: &[String]) -> Result<(), String> {
+    let (file, output, encoding) = input_output(args, "supply a program file to analyze", |_, _| Ok(false))?;
+    let analysis = AheuiAnalysis::from_program(&AheuiProgram::from_str(&read_source(file, encoding)?));
 
-    f.read_to_string(&mut buffer).unwrap();
+    // in order of position, leaving out instructions which only move the cursor
+    let mut report = String::new();
+    let mut nodes: Vec<_> = analysis.graph.nodes.iter().enumerate().collect();
+    nodes.sort_by_key(|(_, node)| (node.position.y, node.position.x));
+    for (index, node) in nodes {
+        if node.instruction.operation == libaheui::instruction::AheuiOperation::Null {
+            continue;
+        }
+        if let Some(effect) = analysis.stack_effect(index) {
+            report.push_str(&format!("{}:{}:{}: {} moving {:?}: {}\n", file, node.position.y + 1, node.position.x + 1, node.instruction.character, node.direction, effect));
+        }
+    }
+
+    if analysis.never_underflows() {
+        report.push_str("no instruction can reflect because of storage underflow\n");
+    }
+    write_output(output, &report)
+}
+
+// rsaheui gen-print [-o OUTPUT] TEXT
+// write a program which prints the text
+fn gen_print(args: &[String]) -> Result<(), String> {
+    let mut output = None;
+    let mut text = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = args.next().map(|s| s.as_str()),
+            _ if text.is_none() => text = Some(arg.as_str()),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        };
+    }
+
+    let text = text.ok_or("supply the text to print")?;
+    write_output(output, &generate::print_program(text))
+}
+
+// rsaheui gen-constant N
+// print short instructions found which push a number, and their syllables
+// they are only known to be the shortest up to 8 instructions
+fn gen_constant(args: &[String]) -> Result<(), String> {
+    let n = match args {
+        [n] => n.parse().map_err(|_| format!("invalid number {}", n))?,
+        [] => return Err("supply a number".to_string()),
+        [_, arg, ..] => return Err(format!("unexpected argument {}", arg)),
+    };
+
+    let constant = generate::find_constant(n);
+    let mnemonics: Vec<String> = constant.instructions.iter().map(asm::mnemonic).collect();
+    println!("{}", constant.syllables);
+    println!("{} instructions: {}", constant.instructions.len(), mnemonics.join(", "));
+    Ok(())
+}
+
+// rsaheui lang [--asm] [--map MAP] [--encoding ENCODING] [-o OUTPUT] FILE
+// compile a program in the structured language to aheui, or to assembly with --asm
+fn lang(args: &[String]) -> Result<(), String> {
+    let mut assembly = false;
+    let mut map = None;
+    let (file, output, encoding) = input_output(args, "supply a program file to compile", |arg, args| {
+        match arg {
+            "--asm" => assembly = true,
+            "--map" => map = Some(args.next().ok_or("supply a file to write the source map to")?.as_str()),
+            _ => return Ok(false),
+        };
+        Ok(true)
+    })?;
+    let source = read_source(file, encoding)?;
+    if assembly {
+        return write_output(output, &lang::compile_to_assembly(&source).map_err(|e| e.to_string())?);
+    }
+
+    let (grid, source_map) = lang::compile(&source).map_err(|e| e.to_string())?;
+    if let Some(map) = map {
+        write_output(Some(map), &source_map.to_json())?;
+    }
+    write_output(output, &grid)
+}
+
+// rsaheui from-bf [--asm] [--encoding ENCODING] [-o OUTPUT] FILE
+// translate a brainfuck program to aheui, or to assembly with --asm
+// the program is read as utf-8 unless an encoding is given
+fn from_bf(args: &[String]) -> Result<(), String> {
+    let mut assembly = false;
+    let (file, output, encoding) = input_output(args, "supply a program file to translate", |arg, _| {
+        match arg {
+            "--asm" => assembly = true,
+            _ => return Ok(false),
+        };
+        Ok(true)
+    })?;
+    let source = read_source(file, encoding.or(Some(AheuiEncoding::Utf8)))?;
+    let translated = if assembly { bf::to_assembly(&source) } else { bf::translate(&source) };
+    write_output(output, &translated.map_err(|e| e.to_string())?)
+}
 
-    let mut program = AheuiState::init(&buffer);
-    program.run();
+// rsaheui rotate DEGREES [--encoding ENCODING] [-o OUTPUT] FILE
+// rotate a program clockwise by 90, 180 or 270 degrees
+fn rotate(args: &[String]) -> Result<(), String> {
+    let degrees = args.first().ok_or("supply 90, 180 or 270 degrees to rotate by")?;
+    let transform = degrees.parse().ok()
+        .and_then(AheuiTransform::rotation)
+        .ok_or_else(|| format!("can not rotate by {} degrees", degrees))?;
+    transform_file(transform, &args[1..])
+}
+
+// rsaheui mirror horizontal|vertical [--encoding ENCODING] [-o OUTPUT] FILE
+// mirror a program, swapping left and right or top and bottom
+fn mirror(args: &[String]) -> Result<(), String> {
+    let transform = match args.first().map(|arg| arg.as_str()) {
+        Some("horizontal") => AheuiTransform::MirrorHorizontal,
+        Some("vertical") => AheuiTransform::MirrorVertical,
+        Some(arg) => return Err(format!("can not mirror {}", arg)),
+        None => return Err("supply horizontal or vertical to mirror".to_string()),
+    };
+    transform_file(transform, &args[1..])
+}
+
+// write a program rotated or reflected, warning when it may run differently
+fn transform_file(transform: AheuiTransform, args: &[String]) -> Result<(), String> {
+    let (file, output, encoding) = input_output(args, "supply a program file to transform", |_, _| Ok(false))?;
+    let program = AheuiProgram::from_str(&read_source(file, encoding)?);
+    if !transform.preserves(&program) {
+        eprintln!("warning: the first cell sets no direction, so the program starts moving in another direction");
+    }
+    write_output(output, &transform::transform(&program, transform).to_source())
+}
+
+// rsaheui fmt [--check] [--align] [--encoding ENCODING] [-o OUTPUT] FILE
+// rewrite a program in canonical form, or with --check,
+// list the lines which are not and fail
+fn fmt(args: &[String]) -> Result<(), String> {
+    let mut check = false;
+    let mut align = false;
+    let (file, output, encoding) = input_output(args, "supply a program file to format", |arg, _| {
+        match arg {
+            "--check" => check = true,
+            "--align" => align = true,
+            _ => return Ok(false),
+        };
+        Ok(true)
+    })?;
+    let source = read_source(file, encoding)?;
+    if !check {
+        return write_output(output, &format::format(&source, align));
+    }
+
+    let lines = format::check(&source, align);
+    for line in lines.iter() {
+        println!("{}:{}: not formatted", file, line);
+    }
+    if !lines.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// rsaheui obfuscate [--seed SEED] [--fill TEXT] [--encoding ENCODING] [-o OUTPUT] FILE
+// rewrite every syllable at random without changing what it does,
+// writing the text over cells which are never run with --fill
+fn obfuscate_file(args: &[String]) -> Result<(), String> {
+    let mut seed = None;
+    let mut fill = None;
+    let (file, output, encoding) = input_output(args, "supply a program file to obfuscate", |arg, args| {
+        match arg {
+            "--seed" => {
+                let value = args.next().ok_or("supply a seed")?;
+                seed = Some(value.parse().map_err(|_| format!("invalid seed {}", value))?);
+            },
+            "--fill" => fill = Some(args.next().ok_or("supply text to fill unreachable cells with")?.as_str()),
+            _ => return Ok(false),
+        };
+        Ok(true)
+    })?;
+    let program = AheuiProgram::from_str(&read_source(file, encoding)?);
+    // a different program each time unless a seed is given
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos() as u64)
+    });
+    write_output(output, &obfuscate(&program, seed, fill).to_source())
+}
+
+// rsaheui golf [--input INPUT]... [--encoding ENCODING] [-o OUTPUT] FILE
+// shrink a program while it behaves the same on every input file given
+fn golf_file(args: &[String]) -> Result<(), String> {
+    let mut inputs = Vec::new();
+    let (file, output, encoding) = input_output(args, "supply a program file to golf", |arg, args| {
+        match arg {
+            "--input" => {
+                let filename = args.next().ok_or("supply an input file")?;
+                inputs.push(std::fs::read_to_string(filename).map_err(|e| format!("can not read {}: {}", filename, e))?);
+            },
+            _ => return Ok(false),
+        };
+        Ok(true)
+    })?;
+    let program = AheuiProgram::from_str(&read_source(file, encoding)?);
+    let inputs: Vec<&str> = inputs.iter().map(|input| input.as_str()).collect();
+    let golfed = golf(&program, &inputs).map_err(|e| e.to_string())?;
+    eprintln!(
+        "{}x{} ({} cells) to {}x{} ({} cells)",
+        program.size.x, program.size.y, program.size.x * program.size.y,
+        golfed.size.x, golfed.size.y, golfed.size.x * golfed.size.y,
+    );
+    write_output(output, &golfed.to_source())
+}
+
+// what has to stay true of a program and its input while reducing them
+enum Predicate<'a> {
+    Error, // fails with the same kind of error
+    Differs(String), // ends with output other than this
+    Command(&'a str), // command given the program and input files succeeds
+}
+
+// rsaheui reduce (--error | --differs EXPECTED | --command COMMAND) [--input INPUT]
+//     [--limit STEPS] [--save-input FILE] [--encoding ENCODING] [-o OUTPUT] FILE
+// blank cells of a program and remove lines of its input while the predicate holds
+fn reduce_file(args: &[String]) -> Result<(), String> {
+    let read = |filename: &str| std::fs::read_to_string(filename).map_err(|e| format!("can not read {}: {}", filename, e));
+    let mut predicate = None;
+    let mut input = String::new();
+    let mut limit = None;
+    let mut save_input = None;
+    let (file, output, encoding) = input_output(args, "supply a program file to reduce", |arg, args| {
+        match arg {
+            "--error" => predicate = Some(Predicate::Error),
+            "--differs" => predicate = Some(Predicate::Differs(read(args.next().ok_or("supply a file of expected output")?)?)),
+            "--command" => predicate = Some(Predicate::Command(args.next().ok_or("supply a command to run")?.as_str())),
+            "--input" => input = read(args.next().ok_or("supply an input file")?)?,
+            "--limit" => {
+                let value = args.next().ok_or("supply a number of steps")?;
+                limit = Some(value.parse().map_err(|_| format!("invalid number of steps {}", value))?);
+            },
+            "--save-input" => save_input = Some(args.next().ok_or("supply a file to write the input to")?.as_str()),
+            _ => return Ok(false),
+        };
+        Ok(true)
+    })?;
+
+    let predicate = predicate.ok_or("supply --error, --differs or --command")?;
+    let program = AheuiProgram::from_str(&read_source(file, encoding)?);
+
+    // runs get ten times as many steps as the original took, as programs
+    // with cells blanked often loop forever
+    let original = run_within(program.clone(), &input, limit.unwrap_or(10_000_000));
+    let limit = limit.unwrap_or(10 * original.steps + 1000);
+    let error = match &original.ending {
+        AheuiEnding::Failed(error) => Some(std::mem::discriminant(&error.error)),
+        _ => None,
+    };
+    let (program_file, input_file) = (
+        env::temp_dir().join(format!("rsaheui-reduce-{}.ah", std::process::id())),
+        env::temp_dir().join(format!("rsaheui-reduce-{}.txt", std::process::id())),
+    );
+
+    let mut checks = 0;
+    let holds = |program: &AheuiProgram, input: &str| {
+        checks += 1;
+        match &predicate {
+            Predicate::Error => match run_within(program.clone(), input, limit).ending {
+                AheuiEnding::Failed(failure) => Some(std::mem::discriminant(&failure.error)) == error,
+                _ => false,
+            },
+            Predicate::Differs(expected) => {
+                let outcome = run_within(program.clone(), input, limit);
+                !matches!(outcome.ending, AheuiEnding::OutOfSteps) && outcome.output != *expected
+            },
+            Predicate::Command(command) => {
+                std::fs::write(&program_file, program.to_source()).is_ok() &&
+                std::fs::write(&input_file, input).is_ok() &&
+                std::process::Command::new(command)
+                    .arg(&program_file)
+                    .arg(&input_file)
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .status()
+                    .is_ok_and(|status| status.success())
+            },
+        }
+    };
+    let reduction = reduce(&program, &input, holds);
+    let _ = std::fs::remove_file(&program_file);
+    let _ = std::fs::remove_file(&input_file);
+    let reduction = reduction.ok_or("the predicate does not hold for the program and input given")?;
+
+    let count = |program: &AheuiProgram| program.program.iter().flatten().filter(|i| !i.character.is_whitespace()).count();
+    eprintln!(
+        "kept {} of {} cells and {} of {} lines of input, checking {} times",
+        count(&reduction.program), count(&program),
+        reduction.input.lines().count(), input.lines().count(), checks,
+    );
+    if let Some(save_input) = save_input {
+        write_output(Some(save_input), &reduction.input)?;
+    }
+    write_output(output, &reduction.program.to_source())
 }
@@ -0,0 +1,71 @@
+use std::collections::{HashMap, HashSet};
+use crate::hangul::{HangulSyllable, compose};
+use crate::instruction::{AheuiArgument, AheuiDirection, AheuiInstruction, AheuiOperation};
+use crate::component::{AheuiCoordinates, AheuiProgram};
+use crate::flow::AheuiFlowGraph;
+
+type Decoded = (AheuiOperation, AheuiDirection, AheuiArgument);
+
+// splitmix64, so that the same seed gives the same program everywhere
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    fn choose<T: Copy>(&mut self, items: &[T]) -> T {
+        items[(self.next() % items.len() as u64) as usize]
+    }
+}
+
+// rewrite every syllable as one picked at random from those decoding to the
+// same instruction, choosing among the onsets of null operations, the vowels
+// which keep the direction and the final consonants with the same meaning
+//
+// with fill, cells which can never be reached are written over with its
+// characters in reading order instead, repeating them as needed. every path
+// the flow graph has is kept, including reflections which may never happen,
+// so cells filled are never run
+pub fn obfuscate(program: &AheuiProgram, seed: u64, fill: Option<&str>) -> AheuiProgram {
+    let mut random = Random(seed);
+    let mut syllables: HashMap<Decoded, Vec<char>> = HashMap::new();
+
+    let reached: HashSet<AheuiCoordinates> = AheuiFlowGraph::from_program(program).nodes
+        .iter()
+        .map(|node| node.position)
+        .collect();
+    // line breaks in the text would not take up a cell
+    let fill: Option<Vec<char>> = fill
+        .map(|text| compose(text).chars().filter(|c| !c.is_control()).collect())
+        .filter(|text: &Vec<char>| !text.is_empty());
+    let mut filled = 0;
+
+    let rows = program.program
+        .iter()
+        .enumerate()
+        .map(|(y, row)| row
+            .iter()
+            .enumerate()
+            .map(|(x, instruction)| {
+                if let Some(text) = fill.as_ref().filter(|_| !reached.contains(&AheuiCoordinates { x, y })) {
+                    filled += 1;
+                    return AheuiInstruction::from_char(text[(filled - 1) % text.len()]);
+                }
+                if HangulSyllable::from_char(instruction.character).is_none() {
+                    return *instruction;
+                }
+                let decoded = (instruction.operation, instruction.direction, instruction.argument);
+                let choices = syllables
+                    .entry(decoded)
+                    .or_insert_with(|| AheuiInstruction::encode(decoded.0, decoded.1, decoded.2));
+                AheuiInstruction::from_char(random.choose(choices))
+            })
+            .collect())
+        .collect();
+    AheuiProgram::from_rows(rows)
+}
@@ -0,0 +1,343 @@
+use std::{fmt, error};
+
+// a value read or a chunk of output written by a program,
+// with the number of the step it happened on, counting from 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AheuiEvent {
+    InputNumber(usize, isize),
+    InputChar(usize, char),
+    Output(usize, String),
+}
+
+impl AheuiEvent {
+    pub fn step(&self) -> usize {
+        match self {
+            AheuiEvent::InputNumber(step, _) |
+            AheuiEvent::InputChar(step, _) |
+            AheuiEvent::Output(step, _) => *step,
+        }
+    }
+}
+
+// error for a recording which can not be read back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AheuiRecordingError {
+    InvalidJson(usize), // byte offset the JSON stops making sense at
+    InvalidEvent(usize), // index of an event which is not an input or output
+}
+
+impl fmt::Display for AheuiRecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AheuiRecordingError::InvalidJson(offset) => write!(f, "invalid JSON at byte {}", offset),
+            AheuiRecordingError::InvalidEvent(index) => write!(f, "event {} is not a number, char or output", index),
+        }
+    }
+}
+
+impl error::Error for AheuiRecordingError {}
+
+// first output of a replay which differs from the recording
+// None for output that is missing on either side
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AheuiDivergence {
+    pub step: usize,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl fmt::Display for AheuiDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "output differs at step {}: ", self.step)?;
+        match (&self.expected, &self.actual) {
+            (Some(expected), Some(actual)) => write!(f, "expected {}, got {}", json_string(expected), json_string(actual)),
+            (Some(expected), None) => write!(f, "expected {}, got no more output", json_string(expected)),
+            (None, Some(actual)) => write!(f, "expected no more output, got {}", json_string(actual)),
+            (None, None) => write!(f, "outputs match"),
+        }
+    }
+}
+
+// everything a program read and wrote during a run, in order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AheuiRecording {
+    pub events: Vec<AheuiEvent>,
+}
+
+impl AheuiRecording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // input text which makes a program read the recorded values again,
+    // one per line
+    pub fn input(&self) -> String {
+        self.events
+            .iter()
+            .filter_map(|event| match event {
+                AheuiEvent::InputNumber(_, number) => Some(format!("{}\n", number)),
+                AheuiEvent::InputChar(_, character) => Some(format!("{}\n", character)),
+                AheuiEvent::Output(..) => None,
+            })
+            .collect()
+    }
+
+    // compare the output of a replay against this recording,
+    // returning the first chunk which was written differently
+    pub fn divergence(&self, replay: &AheuiRecording) -> Option<AheuiDivergence> {
+        let outputs = |recording: &AheuiRecording| -> Vec<(usize, String)> {
+            recording.events
+                .iter()
+                .filter_map(|event| match event {
+                    AheuiEvent::Output(step, text) => Some((*step, text.clone())),
+                    _ => None,
+                })
+                .collect()
+        };
+        let mut expected = outputs(self).into_iter();
+        let mut actual = outputs(replay).into_iter();
+
+        loop {
+            match (expected.next(), actual.next()) {
+                (None, None) => return None,
+                (Some(a), Some(b)) if a == b => continue,
+                (a, b) => {
+                    let step = a.iter().chain(b.iter()).map(|(step, _)| *step).min().unwrap();
+                    return Some(AheuiDivergence {
+                        step,
+                        expected: a.map(|(_, text)| text),
+                        actual: b.map(|(_, text)| text),
+                    });
+                },
+            };
+        }
+    }
+
+    // {"events": [{"step": 1, "number": 3}, {"step": 2, "char": "가"}, {"step": 4, "output": "3"}]}
+    pub fn to_json(&self) -> String {
+        if self.events.is_empty() {
+            return "{\"events\": []}\n".to_string();
+        }
+        let events: Vec<String> = self.events
+            .iter()
+            .map(|event| match event {
+                AheuiEvent::InputNumber(step, number) => format!("{{\"step\": {}, \"number\": {}}}", step, number),
+                AheuiEvent::InputChar(step, character) => format!("{{\"step\": {}, \"char\": {}}}", step, json_string(&character.to_string())),
+                AheuiEvent::Output(step, text) => format!("{{\"step\": {}, \"output\": {}}}", step, json_string(text)),
+            })
+            .collect();
+        format!("{{\"events\": [\n  {}\n]}}\n", events.join(",\n  "))
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, AheuiRecordingError> {
+        let mut parser = JsonParser { source: s, offset: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.offset != s.len() {
+            return Err(AheuiRecordingError::InvalidJson(parser.offset));
+        }
+
+        let events = match value.get("events") {
+            Some(Json::Array(events)) => events,
+            _ => return Err(AheuiRecordingError::InvalidJson(0)),
+        };
+
+        let events = events
+            .iter()
+            .enumerate()
+            .map(|(index, event)| {
+                let step = match event.get("step") {
+                    Some(Json::Number(step)) if *step > 0 => *step as usize,
+                    _ => return Err(AheuiRecordingError::InvalidEvent(index)),
+                };
+                match (event.get("number"), event.get("char"), event.get("output")) {
+                    (Some(Json::Number(number)), None, None) => Ok(AheuiEvent::InputNumber(step, *number)),
+                    (None, Some(Json::String(character)), None) if character.chars().count() == 1 => {
+                        Ok(AheuiEvent::InputChar(step, character.chars().next().unwrap()))
+                    },
+                    (None, None, Some(Json::String(text))) => Ok(AheuiEvent::Output(step, text.clone())),
+                    _ => Err(AheuiRecordingError::InvalidEvent(index)),
+                }
+            })
+            .collect::<Result<Vec<AheuiEvent>, AheuiRecordingError>>()?;
+
+        Ok(Self { events })
+    }
+}
+
+// quote a string for JSON output
+pub fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        };
+    }
+    quoted.push('"');
+    quoted
+}
+
+// the subset of JSON recordings are written in
+enum Json {
+    Number(isize),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    source: &'a str,
+    offset: usize,
+}
+
+impl JsonParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.offset..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.offset += 1;
+        }
+    }
+
+    fn error<T>(&self) -> Result<T, AheuiRecordingError> {
+        Err(AheuiRecordingError::InvalidJson(self.offset))
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), AheuiRecordingError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.offset += 1;
+                Ok(())
+            },
+            _ => self.error(),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, AheuiRecordingError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => {
+                self.offset += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.offset += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.next() {
+                        Some(',') => continue,
+                        Some('}') => return Ok(Json::Object(fields)),
+                        _ => return self.error(),
+                    };
+                }
+            },
+            Some('[') => {
+                self.offset += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.offset += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.next() {
+                        Some(',') => continue,
+                        Some(']') => return Ok(Json::Array(items)),
+                        _ => return self.error(),
+                    };
+                }
+            },
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.offset;
+                self.offset += 1;
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.offset += 1;
+                }
+                match self.source[start..self.offset].parse() {
+                    Ok(number) => Ok(Json::Number(number)),
+                    Err(_) => Err(AheuiRecordingError::InvalidJson(start)),
+                }
+            },
+            _ => self.error(),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, AheuiRecordingError> {
+        if self.next() != Some('"') {
+            return self.error();
+        }
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.next() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('u') => {
+                        let mut code = self.hex()?;
+                        // characters outside the basic plane come as surrogate pairs
+                        if (0xD800..0xDC00).contains(&code) && self.source[self.offset..].starts_with("\\u") {
+                            self.offset += 2;
+                            let low = self.hex()?;
+                            if !(0xDC00..0xE000).contains(&low) {
+                                return self.error();
+                            }
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                        }
+                        match char::from_u32(code) {
+                            Some(c) => s.push(c),
+                            None => return self.error(),
+                        };
+                    },
+                    _ => return self.error(),
+                },
+                Some(c) => s.push(c),
+                None => return self.error(),
+            };
+        }
+    }
+
+    fn hex(&mut self) -> Result<u32, AheuiRecordingError> {
+        let digits = self.source.get(self.offset..self.offset + 4).ok_or(AheuiRecordingError::InvalidJson(self.offset))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| AheuiRecordingError::InvalidJson(self.offset))?;
+        self.offset += 4;
+        Ok(code)
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+use crate::instruction::AheuiInstruction;
+use crate::component::{AheuiCoordinates, AheuiProgram};
+
+// smallest program and input found for which a predicate still holds
+pub struct AheuiReduction {
+    pub program: AheuiProgram,
+    pub input: String,
+}
+
+// remove as many items as possible while test holds for those kept, by
+// delta debugging: trying without each of n chunks, and splitting them
+// further when none can go. the items kept are 1-minimal, so that test
+// no longer holds without any one of them
+pub fn ddmin<T: Clone>(items: &[T], mut test: impl FnMut(&[T]) -> bool) -> Vec<T> {
+    let mut items = items.to_vec();
+    if test(&[]) {
+        return Vec::new();
+    }
+
+    let mut n = 2;
+    while items.len() >= 2 {
+        let size = items.len().div_ceil(n);
+        let mut reduced = false;
+        for start in (0..items.len()).step_by(size) {
+            let complement: Vec<T> = items[..start].iter().chain(items[(start + size).min(items.len())..].iter()).cloned().collect();
+            if test(&complement) {
+                items = complement;
+                n = (n - 1).max(2);
+                reduced = true;
+                break;
+            }
+        }
+        if !reduced {
+            if n >= items.len() {
+                break;
+            }
+            n = (2 * n).min(items.len());
+        }
+    }
+    items
+}
+
+// the program with every cell which is not blank and not kept made blank
+fn blank(program: &AheuiProgram, kept: &HashSet<AheuiCoordinates>) -> AheuiProgram {
+    let rows = program.program
+        .iter()
+        .enumerate()
+        .map(|(y, row)| row
+            .iter()
+            .enumerate()
+            .map(|(x, instruction)| match kept.contains(&AheuiCoordinates { x, y }) || instruction.character.is_whitespace() {
+                true => *instruction,
+                false => AheuiInstruction::null(),
+            })
+            .collect())
+        .collect();
+    AheuiProgram::from_rows(rows)
+}
+
+// shrink a program and its input for as long as holds is true of them, first
+// removing lines of input, then blanking cells of the program, and then removing
+// lines of input again. the program keeps its size, so that it wraps around as before
+// None if holds is not true of the program and input to begin with
+pub fn reduce(program: &AheuiProgram, input: &str, mut holds: impl FnMut(&AheuiProgram, &str) -> bool) -> Option<AheuiReduction> {
+    if !holds(program, input) {
+        return None;
+    }
+
+    let cells: Vec<AheuiCoordinates> = program.program
+        .iter()
+        .enumerate()
+        .flat_map(|(y, row)| row
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| !instruction.character.is_whitespace())
+            .map(move |(x, _)| AheuiCoordinates { x, y }))
+        .collect();
+    let mut lines: Vec<&str> = input.split_inclusive('\n').collect();
+
+    lines = ddmin(&lines, |lines| holds(program, &lines.concat()));
+    let input = lines.concat();
+    let kept: HashSet<AheuiCoordinates> = ddmin(&cells, |cells| holds(&blank(program, &cells.iter().copied().collect()), &input))
+        .into_iter()
+        .collect();
+    let blanked = blank(program, &kept);
+    lines = ddmin(&lines, |lines| holds(&blanked, &lines.concat()));
+
+    Some(AheuiReduction {
+        program: blanked,
+        input: lines.concat(),
+    })
+}
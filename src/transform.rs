@@ -0,0 +1,138 @@
+use crate::hangul::{HangulSyllable, HangulVowel};
+use crate::instruction::{AheuiDirection, AheuiInstruction};
+use crate::component::{AheuiCoordinates, AheuiProgram};
+
+// a rotation or reflection of the whole grid
+// rotations are clockwise, and mirroring horizontally swaps left and right
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AheuiTransform {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    MirrorHorizontal,
+    MirrorVertical,
+    Transpose,
+}
+
+impl AheuiTransform {
+    // clockwise rotation by a number of degrees
+    pub fn rotation(degrees: usize) -> Option<Self> {
+        match degrees {
+            90 => Some(AheuiTransform::Rotate90),
+            180 => Some(AheuiTransform::Rotate180),
+            270 => Some(AheuiTransform::Rotate270),
+            _ => None,
+        }
+    }
+
+    // whether rows become columns
+    fn swaps_axes(&self) -> bool {
+        matches!(self, AheuiTransform::Rotate90 | AheuiTransform::Rotate270 | AheuiTransform::Transpose)
+    }
+
+    // where a step of (dx, dy) goes, with y increasing downwards
+    fn vector(&self, dx: isize, dy: isize) -> (isize, isize) {
+        match self {
+            AheuiTransform::Rotate90 => (-dy, dx),
+            AheuiTransform::Rotate180 => (-dx, -dy),
+            AheuiTransform::Rotate270 => (dy, -dx),
+            AheuiTransform::MirrorHorizontal => (-dx, dy),
+            AheuiTransform::MirrorVertical => (dx, -dy),
+            AheuiTransform::Transpose => (dy, dx),
+        }
+    }
+
+    // size of the grid once transformed
+    fn size(&self, size: AheuiCoordinates) -> AheuiCoordinates {
+        if self.swaps_axes() {
+            AheuiCoordinates { x: size.y, y: size.x }
+        } else {
+            size
+        }
+    }
+
+    // where a cell of a grid of the given size ends up
+    fn position(&self, position: AheuiCoordinates, size: AheuiCoordinates) -> AheuiCoordinates {
+        let AheuiCoordinates { x, y } = position;
+        let (x, y) = match self {
+            AheuiTransform::Rotate90 => (size.y - 1 - y, x),
+            AheuiTransform::Rotate180 => (size.x - 1 - x, size.y - 1 - y),
+            AheuiTransform::Rotate270 => (y, size.x - 1 - x),
+            AheuiTransform::MirrorHorizontal => (size.x - 1 - x, y),
+            AheuiTransform::MirrorVertical => (x, size.y - 1 - y),
+            AheuiTransform::Transpose => (y, x),
+        };
+        AheuiCoordinates { x, y }
+    }
+
+    // the vowel which moves the way a vowel does once transformed
+    // vowels which do not set a direction are kept
+    pub fn vowel(&self, vowel: HangulVowel) -> HangulVowel {
+        let (dx, dy, fast) = match vowel {
+            HangulVowel::A => (1, 0, false),
+            HangulVowel::Ya => (1, 0, true),
+            HangulVowel::Eo => (-1, 0, false),
+            HangulVowel::Yeo => (-1, 0, true),
+            HangulVowel::O => (0, -1, false),
+            HangulVowel::Yo => (0, -1, true),
+            HangulVowel::U => (0, 1, false),
+            HangulVowel::Yu => (0, 1, true),
+            // reflecting across an axis becomes reflecting across the other one
+            HangulVowel::Eu if self.swaps_axes() => return HangulVowel::I,
+            HangulVowel::I if self.swaps_axes() => return HangulVowel::Eu,
+            _ => return vowel,
+        };
+        match (self.vector(dx, dy), fast) {
+            ((1, 0), false) => HangulVowel::A,
+            ((1, 0), true) => HangulVowel::Ya,
+            ((-1, 0), false) => HangulVowel::Eo,
+            ((-1, 0), true) => HangulVowel::Yeo,
+            ((0, -1), false) => HangulVowel::O,
+            ((0, -1), true) => HangulVowel::Yo,
+            ((0, 1), false) => HangulVowel::U,
+            _ => HangulVowel::Yu,
+        }
+    }
+
+    // whether the transformed program behaves as the original one does
+    //
+    // the grid is shifted so that the first cell is run first again, but
+    // execution always starts moving down, so if the first cell does not
+    // set a direction, the transform has to keep moving down as it is
+    pub fn preserves(&self, program: &AheuiProgram) -> bool {
+        let keeps_down = self.vector(0, 1) == (0, 1);
+        let sets_direction = program.get_instruction(&AheuiCoordinates::zero()).is_some_and(|instruction| matches!(instruction.direction,
+            AheuiDirection::Up(_) | AheuiDirection::Down(_) | AheuiDirection::Left(_) | AheuiDirection::Right(_)));
+        keeps_down || sets_direction || program.size.x == 0
+    }
+}
+
+// rotate or reflect a program, rewriting the vowel of every syllable to match
+//
+// the program wraps around at its edges, so shifting it along either axis does
+// not change how it runs. the transformed grid is shifted so that the first cell
+// of the original program is at the top left
+pub fn transform(program: &AheuiProgram, transform: AheuiTransform) -> AheuiProgram {
+    let size = transform.size(program.size);
+    if size.x == 0 || size.y == 0 {
+        return AheuiProgram::from_rows(Vec::new());
+    }
+
+    let origin = transform.position(AheuiCoordinates::zero(), program.size);
+    let mut rows = vec![vec![AheuiInstruction::null(); size.x]; size.y];
+    for (y, row) in program.program.iter().enumerate() {
+        for (x, instruction) in row.iter().enumerate() {
+            let position = transform.position(AheuiCoordinates { x, y }, program.size);
+            let x = (position.x + size.x - origin.x) % size.x;
+            let y = (position.y + size.y - origin.y) % size.y;
+            rows[y][x] = match HangulSyllable::from_char(instruction.character) {
+                Some(syllable) => {
+                    let vowel = transform.vowel(syllable.vowel);
+                    AheuiInstruction::from_char(HangulSyllable::new(syllable.onset, vowel, syllable.coda).to_char())
+                },
+                None => *instruction,
+            };
+        }
+    }
+    AheuiProgram::from_rows(rows)
+}
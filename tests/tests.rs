@@ -1,11 +1,78 @@
 use libaheui::hangul::*;
 use libaheui::instruction::*;
 use libaheui::component::*;
-use libaheui::AheuiState;
+use libaheui::{AheuiState, SharedBuffer};
 use std::io;
 use std::io::prelude::*;
 use std::fs::File;
 
+// create a program reading from input, along with a handle to its output
+fn init_captured(prog: &str, input: &str) -> (AheuiState, SharedBuffer) {
+    let output = SharedBuffer::default();
+    let program = AheuiState::init_with_io(
+        prog,
+        Box::new(io::Cursor::new(input.as_bytes().to_vec())),
+        Box::new(output.clone()),
+    );
+    (program, output)
+}
+
+// generator of reproducible random 6x6 programs which read no input
+struct RandomPrograms {
+    seed: u64,
+}
+
+impl RandomPrograms {
+    fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    // small linear congruential generator
+    fn next(&mut self, n: u32) -> u32 {
+        self.seed = self.seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((self.seed >> 33) % n as u64) as u32
+    }
+
+    fn next_program(&mut self) -> String {
+        let mut buffer = String::new();
+        for _ in 0..6 {
+            for _ in 0..6 {
+                let onset = self.next(19);
+                let vowel = self.next(21);
+                let mut coda = self.next(28);
+                // avoid reading input
+                if onset == 7 && (coda == 21 || coda == 27) {
+                    coda = 0;
+                }
+                buffer.push(char::from_u32(0xAC00 + (onset * 21 + vowel) * 28 + coda).unwrap());
+            }
+            buffer.push('\n');
+        }
+        buffer
+    }
+}
+
+// run a program until it terminates or reaches the step limit,
+// returning its output if it terminated
+fn run_limited(buffer: &str, limit: usize) -> Option<Vec<u8>> {
+    let (mut program, output) = init_captured(buffer, "");
+    for _ in 0..limit {
+        if program.terminated {
+            program.flush_output();
+            return Some(output.bytes());
+        }
+        let _ = program.step();
+    }
+    None
+}
+
+fn read_program(filename: &str) -> String {
+    let mut f = File::open(filename).unwrap();
+    let mut buffer = String::new();
+    f.read_to_string(&mut buffer).unwrap();
+    buffer
+}
+
 #[test]
 fn test_hangul_creation() {
     let han = '한';
@@ -35,9 +102,9 @@ fn test_basic_operator() {
     let deol = '덜';
     let beugs = '씋';
 
-    let mah_operator = AheuiInstruction::from_char(mah);
-    let deol_operator = AheuiInstruction::from_char(deol);
-    let beugs_operator = AheuiInstruction::from_char(beugs);
+    let _mah_operator = AheuiInstruction::from_char(mah);
+    let _deol_operator = AheuiInstruction::from_char(deol);
+    let _beugs_operator = AheuiInstruction::from_char(beugs);
     // println!("{:?}", mah_operator);
     // println!("{:?}", deol_operator);
     // println!("{:?}", beugs_operator);
@@ -48,7 +115,7 @@ fn test_construct_program() {
     let mut f = File::open("tests/hello.ah").unwrap();
     let mut buffer = String::new();
 
-    f.read_to_string(&mut buffer);
+    f.read_to_string(&mut buffer).unwrap();
 
     let program = AheuiProgram::from_str(&buffer);
     println!("{:#?}", program);
@@ -58,7 +125,7 @@ fn test_construct_program() {
 fn test_single_op_program() {
     let basic_program = "발희";
 
-    let mut program = AheuiState::init(&basic_program);
+    let mut program = AheuiState::init(basic_program);
     println!("Initial state of program:\nRunning: {:?}\nPosition: {:?}\nDirection: {:?}\nStorage: {:#?}", !program.terminated, program.position, program.direction, program.storages[0]);
 
     program.step().unwrap();
@@ -72,7 +139,7 @@ fn test_single_op_program() {
 fn test_pa() {
     let pa = "발반파희";
 
-    let mut program = AheuiState::init(&pa);
+    let program = AheuiState::init(pa);
     println!("Program is {:#?}", program.program);
     println!("Current position is {:?}\nCharacter: {}\nStorage: {:?}", program.position, program.program.get_instruction(&program.position).unwrap().character, program.storages[program.storage_index]);
 }
@@ -82,11 +149,11 @@ fn test_hello_world() {
     let mut f = File::open("tests/hello.ah").unwrap();
     let mut buffer = String::new();
 
-    f.read_to_string(&mut buffer);
+    f.read_to_string(&mut buffer).unwrap();
 
     //let buffer = "밯밦밝다다맣희";
 
-    let mut stdin = io::stdin();
+    let _stdin = io::stdin();
     let mut program = AheuiState::init(&buffer);
     //println!("Program is {:#?}", program.program);
     while !program.terminated {
@@ -95,3 +162,1243 @@ fn test_hello_world() {
         program.step().unwrap();
     }
 }
+
+#[test]
+fn test_compare_continues() {
+    // ㅈ moves on in its direction once it has compared two values,
+    // here pushing 0 as 4 is less than 8, rather than reflecting
+    let mut program = AheuiState::init("밤밣자희");
+    for _ in 0..3 {
+        program.step().unwrap();
+    }
+    assert_eq!((program.position.x, program.position.y), (3, 0));
+    assert_eq!(program.storages[0].peek(), Some(&0));
+}
+
+#[test]
+fn test_captured_output() {
+    let (mut program, output) = init_captured("방맣희", "65\n");
+    while !program.terminated {
+        program.step().unwrap();
+    }
+    program.flush_output();
+
+    assert_eq!(output.bytes()[0], b'A');
+}
+
+#[test]
+fn test_compare() {
+    // 3 >= 2 pushes 1, which is then printed
+    let (mut program, output) = init_captured("밟받자망희", "");
+    while !program.terminated {
+        program.step().unwrap();
+    }
+    program.flush_output();
+
+    assert_eq!(output.bytes().as_slice(), b"1\n");
+}
+
+#[cfg(feature = "jit")]
+#[test]
+fn test_jit_matches_interpreter() {
+    use libaheui::jit::AheuiJit;
+
+    let cases = [
+        ("tests/hello.ah", ""),
+        ("tests/fibbo.ah", ""),
+        ("tests/poem.ah", ""),
+        ("tests/invalidchar.ah", ""),
+        ("tests/inputchar.ah", "44032\n"),
+    ];
+
+    for (filename, input) in cases {
+        let buffer = read_program(filename);
+
+        let (mut interpreted, interpreted_output) = init_captured(&buffer, input);
+        while !interpreted.terminated {
+            let _ = interpreted.step();
+        }
+        interpreted.flush_output();
+
+        let (mut compiled, compiled_output) = init_captured(&buffer, input);
+        let mut jit = AheuiJit::new();
+        while !compiled.terminated {
+            let _ = jit.step(&mut compiled);
+        }
+        compiled.flush_output();
+
+        assert_eq!(interpreted_output.bytes().as_slice(), compiled_output.bytes().as_slice(), "{}", filename);
+        for (a, b) in interpreted.storages.iter().zip(compiled.storages.iter()) {
+            assert_eq!(a.values(), b.values(), "{}", filename);
+        }
+        assert_eq!(interpreted.steps, compiled.steps, "{}", filename);
+    }
+}
+
+#[cfg(feature = "jit")]
+#[test]
+fn test_jit_compiles_hot_loop() {
+    use libaheui::jit::AheuiJit;
+
+    let buffer = read_program("tests/fibbo.ah");
+    let (mut program, _output) = init_captured(&buffer, "");
+    let mut jit = AheuiJit::new();
+    while !program.terminated {
+        let _ = jit.step(&mut program);
+    }
+
+    assert!(jit.compiled_blocks() > 0);
+}
+
+#[cfg(feature = "jit")]
+#[test]
+fn test_jit_random_programs() {
+    use libaheui::jit::AheuiJit;
+
+    let mut programs = RandomPrograms::new(0x5eed);
+    for _ in 0..200 {
+        let buffer = programs.next_program();
+
+        let (mut interpreted, interpreted_output) = init_captured(&buffer, "");
+        for _ in 0..2000 {
+            if interpreted.terminated {
+                break;
+            }
+            let _ = interpreted.step();
+        }
+
+        // only compare programs known to terminate,
+        // every jit step runs at least one instruction
+        if interpreted.terminated {
+            let (mut compiled, compiled_output) = init_captured(&buffer, "");
+            let mut jit = AheuiJit::new();
+            for _ in 0..2000 {
+                if compiled.terminated {
+                    break;
+                }
+                let _ = jit.step(&mut compiled);
+            }
+
+            interpreted.flush_output();
+            compiled.flush_output();
+            assert!(compiled.terminated, "{}", buffer);
+            assert_eq!(interpreted_output.bytes().as_slice(), compiled_output.bytes().as_slice(), "{}", buffer);
+            for (a, b) in interpreted.storages.iter().zip(compiled.storages.iter()) {
+                assert_eq!(a.values(), b.values(), "{}", buffer);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_compile_c_matches_interpreter() {
+    use libaheui::codegen;
+    use std::process::{Command, Stdio};
+
+    let dir = std::env::temp_dir().join(format!("rsaheui-c-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    for (name, input) in [("hello", ""), ("fibbo", ""), ("poem", ""), ("invalidchar", ""), ("inputchar", "44032\n")] {
+        let buffer = read_program(&format!("tests/{}.ah", name));
+        let source = codegen::c::compile(&AheuiProgram::from_str(&buffer), &Default::default());
+        let source_path = dir.join(format!("{}.c", name));
+        let binary_path = dir.join(name);
+        std::fs::write(&source_path, source).unwrap();
+
+        // skip when no C compiler is available
+        let status = match Command::new("cc").arg("-O1").arg("-o").arg(&binary_path).arg(&source_path).status() {
+            Ok(status) => status,
+            Err(_) => return,
+        };
+        assert!(status.success(), "{}", name);
+
+        let mut child = Command::new(&binary_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        let compiled_output = child.wait_with_output().unwrap().stdout;
+
+        let (mut program, output) = init_captured(&buffer, input);
+        while !program.terminated {
+            let _ = program.step();
+        }
+        program.flush_output();
+
+        let interpreted_output = output.bytes();
+        // the interpreter only prompts when reading from stdin
+        let compiled_output = String::from_utf8(compiled_output).unwrap().replace("\ninput number: ", "");
+        assert_eq!(interpreted_output, compiled_output.into_bytes(), "{}", name);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// run a module generated by the wat backend, returning its output and exit code
+fn run_wat(source: &str, input: &[i64]) -> (String, i32) {
+    use wasmi::{Caller, Engine, Linker, Module, Store};
+
+    struct Host {
+        input: Vec<i64>,
+        output: String,
+    }
+
+    let engine = Engine::default();
+    let wasm = wat::parse_str(source).unwrap();
+    let module = Module::new(&engine, &wasm[..]).unwrap();
+    let mut input = input.to_vec();
+    input.reverse();
+    let mut store = Store::new(&engine, Host { input, output: String::new() });
+
+    let mut linker = <Linker<Host>>::new(&engine);
+    linker.func_wrap("aheui", "input_number", |mut caller: Caller<'_, Host>| {
+        caller.data_mut().input.pop().unwrap()
+    }).unwrap();
+    linker.func_wrap("aheui", "input_char", |mut caller: Caller<'_, Host>| {
+        caller.data_mut().input.pop().unwrap()
+    }).unwrap();
+    linker.func_wrap("aheui", "output_number", |mut caller: Caller<'_, Host>, n: i64| {
+        caller.data_mut().output.push_str(&n.to_string());
+    }).unwrap();
+    linker.func_wrap("aheui", "output_char", |mut caller: Caller<'_, Host>, c: i32| {
+        caller.data_mut().output.push(char::from_u32(c as u32).unwrap());
+    }).unwrap();
+    linker.func_wrap("aheui", "error", |_: Caller<'_, Host>, _kind: i32, _a: i64, _b: i64| {}).unwrap();
+
+    let instance = linker.instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+    let run = instance.get_typed_func::<(), i32>(&store, "run").unwrap();
+    let code = run.call(&mut store, ()).unwrap();
+    (store.data().output.clone(), code)
+}
+
+#[test]
+fn test_compile_wat_matches_interpreter() {
+    use libaheui::codegen;
+
+    for (name, input) in [("hello", ""), ("fibbo", ""), ("poem", ""), ("invalidchar", ""), ("inputchar", "44032\n")] {
+        let buffer = read_program(&format!("tests/{}.ah", name));
+        let source = codegen::wat::compile(&AheuiProgram::from_str(&buffer));
+        let numbers: Vec<i64> = input.split_whitespace().map(|n| n.parse().unwrap()).collect();
+        let (compiled_output, _) = run_wat(&source, &numbers);
+
+        let (mut program, output) = init_captured(&buffer, input);
+        while !program.terminated {
+            let _ = program.step();
+        }
+        program.flush_output();
+
+        let interpreted_output = output.bytes();
+        assert_eq!(interpreted_output, compiled_output.into_bytes(), "{}", name);
+    }
+}
+
+#[test]
+fn test_compile_wat_exit_code() {
+    use libaheui::codegen;
+
+    // push 3 and 2, multiply, then end with 6 as the exit code
+    let source = codegen::wat::compile(&AheuiProgram::from_str("받반따히"));
+    assert_eq!(run_wat(&source, &[]), ("\n".to_string(), 6));
+}
+
+#[test]
+fn test_compile_wat_random_programs() {
+    use libaheui::codegen;
+
+    let mut programs = RandomPrograms::new(0xa4e1);
+    for _ in 0..200 {
+        let buffer = programs.next_program();
+        if let Some(output) = run_limited(&buffer, 2000) {
+            let source = codegen::wat::compile(&AheuiProgram::from_str(&buffer));
+            let (compiled_output, _) = run_wat(&source, &[]);
+            assert_eq!(output, compiled_output.into_bytes(), "{}", buffer);
+        }
+    }
+}
+
+#[test]
+fn test_validate_program() {
+    use libaheui::flow::AheuiValidationError;
+
+    assert_eq!(AheuiProgram::from_str(&read_program("tests/hello.ah")).validate(), Ok(()));
+    assert_eq!(AheuiProgram::from_str("").validate(), Err(AheuiValidationError::EmptyProgram));
+    assert_eq!(AheuiProgram::from_str("받반따망").validate(), Err(AheuiValidationError::NoTermination));
+}
+
+#[test]
+fn test_disassemble_assemble_round_trip() {
+    use libaheui::asm;
+
+    for (name, input) in [("hello", ""), ("fibbo", ""), ("poem", ""), ("invalidchar", ""), ("inputchar", "44032\n")] {
+        let buffer = read_program(&format!("tests/{}.ah", name));
+        let assembled = asm::assemble(&asm::disassemble(&AheuiProgram::from_str(&buffer))).unwrap();
+        let run = |buffer: &str| {
+            let (mut program, output) = init_captured(buffer, input);
+            while !program.terminated {
+                let _ = program.step();
+            }
+            program.flush_output();
+            output.bytes()
+        };
+        assert_eq!(run(&assembled), run(&buffer), "{}", name);
+    }
+
+    let mut programs = RandomPrograms::new(0x0a53);
+    for _ in 0..200 {
+        let buffer = programs.next_program();
+        if let Some(output) = run_limited(&buffer, 2000) {
+            let assembled = asm::assemble(&asm::disassemble(&AheuiProgram::from_str(&buffer))).unwrap();
+            assert_eq!(run_limited(&assembled, 100000), Some(output), "{}", buffer);
+        }
+    }
+}
+
+#[test]
+fn test_assemble() {
+    use libaheui::asm::{self, AheuiAsmError};
+
+    // count down from 3, then print 5 from the ㄱ stack
+    let source = "
+        push 3
+    loop:
+        dup
+        pop int
+        push 3
+        push 2
+        sub          ; push 1
+        sub
+        dup
+        brz end
+        jmp loop
+    end:
+        push 5
+        mov ㄱ
+        sel ㄱ
+        pop int
+        halt
+    ";
+    let program = AheuiProgram::from_str(&asm::assemble(source).unwrap());
+    assert_eq!(libaheui::run_to_string(program, "").unwrap(), "3215\n");
+
+    assert_eq!(asm::assemble("jmp nowhere"), Err(AheuiAsmError::UndefinedLabel(1, "nowhere".to_string())));
+    assert_eq!(asm::assemble("a:\na: halt"), Err(AheuiAsmError::DuplicateLabel(2, "a".to_string())));
+    assert_eq!(asm::assemble("push 1"), Err(AheuiAsmError::InvalidOperand(1, "1".to_string())));
+    assert_eq!(asm::assemble("push 2 else a\na: halt"), Err(AheuiAsmError::InvalidOperand(1, "else a".to_string())));
+    assert_eq!(asm::assemble("jump a"), Err(AheuiAsmError::UnknownMnemonic(1, "jump".to_string())));
+}
+
+#[test]
+fn test_lint() {
+    use libaheui::lint::AheuiLintKind;
+
+    let lints = |source: &str| -> Vec<(AheuiLintKind, usize, usize)> {
+        AheuiProgram::from_str(source)
+            .lint()
+            .into_iter()
+            .map(|lint| (lint.kind, lint.position.x, lint.position.y))
+            .collect()
+    };
+
+    assert_eq!(lints(&read_program("tests/hello.ah")), vec![]);
+    assert_eq!(lints("희\n밤"), vec![(AheuiLintKind::Unreachable, 0, 1)]);
+    assert_eq!(lints("a\n희"), vec![(AheuiLintKind::NonHangul, 0, 0)]);
+    assert_eq!(lints("ㅂ\n희"), vec![(AheuiLintKind::Jamo, 0, 0)]);
+    assert_eq!(lints("마희"), vec![(AheuiLintKind::DiscardingPop, 0, 0)]);
+    assert_eq!(lints("다희"), vec![(AheuiLintKind::Underflow, 0, 0)]);
+    assert_eq!(lints("샇희"), vec![(AheuiLintKind::ExtensionStorage, 0, 0)]);
+    assert_eq!(lints("바"), vec![(AheuiLintKind::NoTermination, 0, 0)]);
+
+    // the multiplication always has two values, so it never reflects into 다
+    assert_eq!(lints("밤밣따희다"), vec![(AheuiLintKind::Unreachable, 4, 0)]);
+}
+
+#[test]
+fn test_analysis() {
+    use libaheui::analysis::AheuiAnalysis;
+    use libaheui::lint::AheuiLintKind;
+
+    let analysis = AheuiAnalysis::from_program(&AheuiProgram::from_str(&read_program("tests/hello.ah")));
+    assert!(analysis.never_underflows());
+
+    // push 4 and 8 then multiply, leaving exactly one value of 32
+    let analysis = AheuiAnalysis::from_program(&AheuiProgram::from_str("밤밣따희"));
+    let state = analysis.state(AheuiCoordinates { x: 3, y: 0 }, AheuiDirection::Right(false)).unwrap();
+    let storage = state.selected().unwrap();
+    assert_eq!((storage.depth.low, storage.depth.high), (1, Some(1)));
+    assert_eq!(storage.values, vec![Some(32)]);
+    let index = analysis.graph.node_index(AheuiCoordinates { x: 2, y: 0 }, AheuiDirection::Right(false)).unwrap();
+    assert_eq!(analysis.stack_effect(index).unwrap(), "∅ depth 2 [8, 4] -> ∅ depth 1 [32]");
+
+    // the ㄱ storage is empty when 따 is reached by moving right, so it always reflects
+    let analysis = AheuiAnalysis::from_program(&AheuiProgram::from_str("박삭따희"));
+    assert!(!analysis.never_underflows());
+    let index = analysis.graph.node_index(AheuiCoordinates { x: 2, y: 0 }, AheuiDirection::Right(false)).unwrap();
+    assert!(analysis.always_underflows(index));
+
+    // every state reached when running a program lies within its bounds
+    let mut programs = RandomPrograms::new(0x3a1e);
+    for _ in 0..200 {
+        let buffer = programs.next_program();
+        let analysis = AheuiAnalysis::from_program(&AheuiProgram::from_str(&buffer));
+        let (mut program, _) = init_captured(&buffer, "");
+        for _ in 0..2000 {
+            if program.terminated {
+                break;
+            }
+            let state = analysis.state(program.position, program.direction).expect(&buffer);
+            assert!(state.storage_index.is_none_or(|index| index == program.storage_index), "{}", buffer);
+            for (storage, bounds) in program.storages.iter().zip(state.storages.iter()) {
+                assert!(storage.len() >= bounds.depth.low, "{}", buffer);
+                assert!(bounds.depth.high.is_none_or(|high| storage.len() <= high), "{}", buffer);
+                for (value, known) in storage.values().iter().zip(bounds.values.iter()) {
+                    assert!(known.is_none_or(|known| known == *value), "{}", buffer);
+                }
+            }
+            let _ = program.step();
+        }
+    }
+
+    // push 2 and 0 then divide
+    let lints: Vec<_> = AheuiProgram::from_str("반바나희").lint().into_iter().map(|lint| lint.kind).collect();
+    assert_eq!(lints, vec![AheuiLintKind::DivisionByZero]);
+}
+
+#[test]
+fn test_detect_loops() {
+    // push 0, duplicate and print it, then come back and print the copy
+    let (mut program, output) = init_captured("바빠망어", "");
+    program.detect_loops();
+    let mut result = Ok(());
+    for _ in 0..100 {
+        result = program.step();
+        if program.terminated {
+            break;
+        }
+    }
+    match result.map_err(|err| err.error) {
+        Err(AheuiError::InfiniteLoopError(period, cells)) => {
+            assert_eq!(period, 8);
+            let mut cells: Vec<(usize, usize)> = cells.iter().map(|cell| (cell.x, cell.y)).collect();
+            cells.sort();
+            assert_eq!(cells, vec![(0, 0), (1, 0), (2, 0), (3, 0)]);
+        },
+        _ => panic!("loop was not detected"),
+    };
+    assert!(program.terminated);
+    program.flush_output();
+    assert!(!output.bytes().is_empty());
+
+    // reading input may change what happens next, even in the same state
+    let (mut program, _) = init_captured("방망어", &"1\n".repeat(100));
+    program.detect_loops();
+    for _ in 0..200 {
+        assert!(!matches!(program.step().map_err(|err| err.error), Err(AheuiError::InfiniteLoopError(..))));
+    }
+
+    // terminating programs are unaffected
+    let (mut program, output) = init_captured(&read_program("tests/hello.ah"), "");
+    program.detect_loops();
+    while !program.terminated {
+        program.step().unwrap();
+    }
+    program.flush_output();
+    assert_eq!(output.bytes().as_slice(), b"Hello, world!\n\n");
+
+    // storages are compared in place, in the order values would be popped
+    let (mut stack, mut queue) = (AheuiStorage::new(false), AheuiStorage::new(true));
+    for value in 1..=3 {
+        stack.push(value);
+        queue.push(value);
+    }
+    assert_eq!(stack.iter().copied().collect::<Vec<isize>>(), vec![3, 2, 1]);
+    assert_eq!(queue.iter().copied().collect::<Vec<isize>>(), vec![1, 2, 3]);
+    assert!(stack.iter().eq(stack.values().iter()) && queue.iter().eq(queue.values().iter()));
+}
+
+#[test]
+fn test_strict_mode() {
+    let strict_error = |source: &str| {
+        let (mut program, _) = init_captured(source, "");
+        program.strict_mode();
+        while !program.terminated {
+            if let Err(err) = program.step() {
+                return Some((err.position, err.error));
+            }
+        }
+        None
+    };
+
+    // adding with an empty storage
+    match strict_error("다희") {
+        Some((position, AheuiError::StorageUnderflow(operation, index, depth))) => {
+            assert_eq!((position.x, position.y), (0, 0));
+            assert_eq!((operation, index, depth), (AheuiOperation::Add, 0, 0));
+        },
+        err => panic!("expected an underflow, got {:?}", err),
+    };
+
+    // swapping with one value in the ㄱ storage
+    match strict_error("삭반파희") {
+        Some((position, AheuiError::StorageUnderflow(operation, index, depth))) => {
+            assert_eq!((position.x, position.y), (2, 0));
+            assert_eq!((operation, index, depth), (AheuiOperation::Swap, 1, 1));
+        },
+        err => panic!("expected an underflow, got {:?}", err),
+    };
+
+    assert!(strict_error(&read_program("tests/hello.ah")).is_none());
+
+    // without strict mode the instruction reflects, here onto 희
+    let (mut program, _) = init_captured("다희", "");
+    program.step().unwrap();
+    program.step().unwrap();
+    assert!(program.terminated);
+}
+
+// writer which always fails
+struct FailingWriter;
+
+impl Write for FailingWriter {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("device is full"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(io::Error::other("device is full"))
+    }
+}
+
+#[test]
+fn test_runtime_error_context() {
+    use std::error::Error;
+
+    // the error keeps where and when the failing instruction ran
+    let (mut program, _) = init_captured("분\n다희", "");
+    program.strict_mode();
+    program.step().unwrap();
+    let err = program.step().unwrap_err();
+    assert!(matches!(err.error, AheuiError::StorageUnderflow(..)));
+    assert_eq!((err.position.x, err.position.y), (0, 1));
+    assert_eq!(err.character, Some('다'));
+    assert_eq!(err.direction, AheuiDirection::Down(false));
+    assert_eq!((err.step, err.storage_index), (2, 0));
+    assert_eq!(err.to_string(), "fatal: Add needs 2 values, but storage ∅ holds 1 at (0, 1) 다 moving Down(false) on step 2, with storage ∅ selected");
+    assert_eq!(err.excerpt(&program.program), "  |\n1 | 분\n2 | 다희\n  | ^^");
+    assert!(err.source().is_none());
+
+    // stepping again after termination still reports a message
+    let err = program.step().unwrap_err();
+    assert!(matches!(err.error, AheuiError::TerminatedError));
+    assert!(!err.error.to_string().is_empty());
+
+    // carets line up with wide characters before the failing cell
+    let program = AheuiProgram::from_str("아a다희");
+    let err = AheuiRuntimeError {
+        error: AheuiError::StorageSizeError,
+        position: AheuiCoordinates { x: 2, y: 0 },
+        character: Some('다'),
+        direction: AheuiDirection::Right(false),
+        step: 3,
+        storage_index: 1,
+    };
+    assert_eq!(err.excerpt(&program), "  |\n1 | 아a다희\n  |    ^^");
+    assert!(err.to_string().contains("storage ㄱ selected"));
+
+    // i/o errors are kept as the source
+    let mut program = AheuiState::init_with_io("밯망희", Box::new(io::Cursor::new(b"a\n".to_vec())), Box::new(FailingWriter));
+    let err = program.step().unwrap_err();
+    assert!(matches!(err.error, AheuiError::OutputError(_)));
+    assert_eq!(err.character, Some('밯'));
+    assert_eq!(err.source().unwrap().to_string(), "device is full");
+}
+
+#[test]
+fn test_record_replay() {
+    use libaheui::record::*;
+
+    // add two numbers, then echo a character
+    let source = "방방다망밯맣희";
+    let (mut program, _) = init_captured(source, "3\n4\n가\n");
+    program.record();
+    while !program.terminated {
+        program.step().unwrap();
+    }
+    let recording = program.recording().unwrap().clone();
+    assert_eq!(recording.events, vec![
+        AheuiEvent::InputNumber(1, 3),
+        AheuiEvent::InputNumber(2, 4),
+        AheuiEvent::Output(4, "7".to_string()),
+        AheuiEvent::InputChar(5, '가'),
+        AheuiEvent::Output(6, "가".to_string()),
+    ]);
+    assert_eq!(recording.input(), "3\n4\n가\n");
+
+    let json = recording.to_json();
+    assert_eq!(AheuiRecording::from_json(&json), Ok(recording.clone()));
+    assert_eq!(AheuiRecording::from_json(&AheuiRecording::new().to_json()), Ok(AheuiRecording::new()));
+    let escaped = AheuiRecording { events: vec![AheuiEvent::Output(1, "\"\\\n\u{1}😀".to_string())] };
+    assert_eq!(AheuiRecording::from_json(&escaped.to_json()), Ok(escaped));
+    assert_eq!(
+        AheuiRecording::from_json(r#"{"events": [{"step": 1, "output": "😀A"}]}"#).unwrap().events,
+        vec![AheuiEvent::Output(1, "😀A".to_string())],
+    );
+    assert_eq!(AheuiRecording::from_json("{\"events\": [}"), Err(AheuiRecordingError::InvalidJson(12)));
+    assert_eq!(AheuiRecording::from_json("{\"events\": [{\"step\": 1}]}"), Err(AheuiRecordingError::InvalidEvent(0)));
+
+    // replaying the same program reproduces the output
+    let replay = |source: &str| {
+        let (mut program, _) = init_captured(source, &recording.input());
+        program.record();
+        while !program.terminated {
+            if program.step().is_err() {
+                break;
+            }
+        }
+        program.recording().unwrap().clone()
+    };
+    assert_eq!(recording.divergence(&replay(source)), None);
+
+    // subtracting instead diverges at the first output
+    let divergence = recording.divergence(&replay("방방타망밯맣희")).unwrap();
+    assert_eq!(divergence.step, 4);
+    assert_eq!(divergence.expected.as_deref(), Some("7"));
+    assert_eq!(divergence.actual.as_deref(), Some("-1"));
+
+    // running out of recorded input leaves output missing
+    let divergence = recording.divergence(&replay("방방방다망희")).unwrap();
+    assert_eq!(divergence.actual, None);
+    assert_eq!(divergence.to_string(), "output differs at step 4: expected \"7\", got no more output");
+}
+
+#[test]
+fn test_output_encoding() {
+    use libaheui::encoding::AheuiEncoding;
+
+    // run a program popping value as a character, returning its output
+    // before the newline written on termination
+    let output = |value: isize, encoding: AheuiEncoding| -> Result<Vec<u8>, AheuiError> {
+        let (mut program, output) = init_captured("방맣희", &format!("{}\n", value));
+        program.output_encoding(encoding);
+        while !program.terminated {
+            program.step().map_err(|err| err.error)?;
+        }
+        program.flush_output();
+        let mut bytes = output.bytes();
+        assert_eq!(bytes.pop(), Some(b'\n'));
+        Ok(bytes)
+    };
+
+    // only the bytes actually used are written
+    assert_eq!(output(65, AheuiEncoding::Utf8).unwrap(), b"A");
+    assert_eq!(output(0xAC00, AheuiEncoding::Utf8).unwrap(), "가".as_bytes());
+    assert_eq!(output(0x1F600, AheuiEncoding::Utf8).unwrap(), "😀".as_bytes());
+
+    // values which are not characters are fatal
+    for value in [-1, -65, 0xD800, 0x110000, isize::MAX] {
+        match output(value, AheuiEncoding::Utf8) {
+            Err(AheuiError::InvalidCharError(num, AheuiEncoding::Utf8)) => assert_eq!(num, value),
+            result => panic!("expected an invalid character for {}, got {:?}", value, result),
+        };
+    }
+
+    assert_eq!(output(0x141, AheuiEncoding::Byte).unwrap(), b"A");
+    assert_eq!(output(-1, AheuiEncoding::Byte).unwrap(), [0xFF]);
+
+    assert_eq!(output(0xAC00, AheuiEncoding::Utf16Le).unwrap(), [0x00, 0xAC]);
+    assert_eq!(output(0xAC00, AheuiEncoding::Utf16Be).unwrap(), [0xAC, 0x00]);
+    assert_eq!(output(0x1F600, AheuiEncoding::Utf16Be).unwrap(), [0xD8, 0x3D, 0xDE, 0x00]);
+
+    // KS X 1001 syllables, extension syllables and compatibility jamo
+    assert_eq!(output(65, AheuiEncoding::Cp949).unwrap(), b"A");
+    assert_eq!(output('가' as isize, AheuiEncoding::Cp949).unwrap(), [0xB0, 0xA1]);
+    assert_eq!(output('힣' as isize, AheuiEncoding::Cp949).unwrap(), [0xC6, 0x52]);
+    assert_eq!(output('갂' as isize, AheuiEncoding::Cp949).unwrap(), [0x81, 0x41]);
+    assert_eq!(output('똠' as isize, AheuiEncoding::Cp949).unwrap(), [0x8C, 0x63]);
+    assert_eq!(output('ㅎ' as isize, AheuiEncoding::Cp949).unwrap(), [0xA4, 0xBE]);
+    assert_eq!(output('가' as isize, AheuiEncoding::EucKr).unwrap(), [0xB0, 0xA1]);
+    assert!(matches!(output('똠' as isize, AheuiEncoding::EucKr), Err(AheuiError::InvalidCharError(_, AheuiEncoding::EucKr))));
+    assert!(matches!(output('😀' as isize, AheuiEncoding::Cp949), Err(AheuiError::InvalidCharError(_, AheuiEncoding::Cp949))));
+
+    assert_eq!(AheuiEncoding::from_name("UTF-16LE"), Some(AheuiEncoding::Utf16Le));
+    assert_eq!(AheuiEncoding::from_name("euc-kr"), Some(AheuiEncoding::EucKr));
+    assert_eq!(AheuiEncoding::from_name("latin-1"), None);
+}
+
+#[test]
+fn test_source_encoding() {
+    use libaheui::encoding::*;
+
+    // encode a source with the output encodings, which cover every hangul syllable
+    let encode = |source: &str, encoding: AheuiEncoding| -> Vec<u8> {
+        source.chars().flat_map(|c| encoding.encode(c as isize).unwrap()).collect()
+    };
+
+    for filename in ["tests/hello.ah", "tests/fibbo.ah", "tests/inputchar.ah"] {
+        let source = read_program(filename);
+        let expected = AheuiProgram::from_str(&source);
+        let characters = |program: &AheuiProgram| -> Vec<Vec<char>> {
+            program.program.iter().map(|row| row.iter().map(|instruction| instruction.character).collect()).collect()
+        };
+
+        let mut encoded = vec![
+            (AheuiEncoding::Utf8, [vec![0xEF, 0xBB, 0xBF], source.as_bytes().to_vec()].concat()),
+            (AheuiEncoding::Utf16Le, [vec![0xFF, 0xFE], encode(&source, AheuiEncoding::Utf16Le)].concat()),
+            (AheuiEncoding::Utf16Be, [vec![0xFE, 0xFF], encode(&source, AheuiEncoding::Utf16Be)].concat()),
+            (AheuiEncoding::Utf16Le, encode(&source, AheuiEncoding::Utf16Le)),
+            (AheuiEncoding::Utf16Be, encode(&source, AheuiEncoding::Utf16Be)),
+            (AheuiEncoding::Cp949, encode(&source, AheuiEncoding::Cp949)),
+        ];
+        if let Some(euc_kr) = source.chars().map(|c| AheuiEncoding::EucKr.encode(c as isize)).collect::<Option<Vec<Vec<u8>>>>() {
+            encoded.push((AheuiEncoding::Cp949, euc_kr.concat()));
+        }
+
+        for (encoding, bytes) in encoded {
+            assert_eq!(AheuiEncoding::detect(&bytes), encoding, "{}", filename);
+            let program = AheuiProgram::from_bytes(&bytes, None).unwrap();
+            assert_eq!(characters(&program), characters(&expected), "{} in {}", filename, encoding);
+            let program = AheuiProgram::from_bytes(&bytes, Some(encoding)).unwrap();
+            assert_eq!(characters(&program), characters(&expected), "{} in {}", filename, encoding);
+        }
+    }
+
+    // CP949 syllables from KS X 1001 and the extension, jamo and other characters
+    assert_eq!(AheuiEncoding::Cp949.decode(&[0xB0, 0xA1, 0x8C, 0x63, 0xC6, 0x52, b'\n', 0xA4, 0xBE, 0xA1, 0xDA]).unwrap(), "가똠힣\nㅎ\u{FFFD}");
+    assert_eq!(AheuiEncoding::EucKr.decode(&[0xB0, 0xA1, 0x8C, 0x63]), Err(AheuiDecodeError { encoding: AheuiEncoding::EucKr, offset: 2 }));
+    assert_eq!(AheuiEncoding::Cp949.decode(&[0xB0, 0xA1, 0xB0]), Err(AheuiDecodeError { encoding: AheuiEncoding::Cp949, offset: 2 }));
+    assert_eq!(AheuiEncoding::Cp949.decode(&[0xC7, 0x41]), Err(AheuiDecodeError { encoding: AheuiEncoding::Cp949, offset: 0 }));
+
+    assert_eq!(AheuiEncoding::Utf16Le.decode(&[0x00, 0xAC, 0x00, 0xD8]), Err(AheuiDecodeError { encoding: AheuiEncoding::Utf16Le, offset: 2 }));
+    assert_eq!(AheuiEncoding::Utf16Be.decode(&[0xAC, 0x00, 0xD8]), Err(AheuiDecodeError { encoding: AheuiEncoding::Utf16Be, offset: 2 }));
+    assert_eq!(AheuiEncoding::Utf8.decode(&[0xEF, 0xBB, 0xBF, b'a', 0xFF]).unwrap_err().to_string(), "invalid UTF-8 at byte 4");
+}
+
+#[test]
+fn test_print_program() {
+    use libaheui::generate::print_program;
+    use libaheui::run_to_string;
+
+    // terminating adds a newline of its own
+    for text in ["안녕하세요", "Hello, world!\n", "", "aaaa", "ㅎㅎ 가나다 😀", "\u{10FFFF}\0"] {
+        let program = AheuiProgram::from_str(&print_program(text));
+        assert_eq!(run_to_string(program, "").unwrap(), format!("{}\n", text), "{:?}", text);
+    }
+
+    // a square grid of syllables, leaving nothing unreachable
+    let source = print_program("Hello, world!");
+    let rows: Vec<usize> = source.lines().map(|line| line.chars().count()).collect();
+    assert!(rows.iter().all(|row| *row <= rows.len()), "{}", source);
+    assert!(source.lines().flat_map(|line| line.chars()).all(|c| HangulSyllable::from_char(c).is_some()));
+    assert!(AheuiProgram::from_str(&source).lint().is_empty(), "{}", source);
+    assert_eq!(print_program(""), "해\n");
+}
+
+#[test]
+fn test_find_constant() {
+    use libaheui::generate::{AheuiConstantSearch, find_constant};
+    use libaheui::run_to_string;
+
+    // the syllables push the value, which is then popped as a number
+    let mut search = AheuiConstantSearch::new();
+    for n in (-100..=1000).chain([44032, 55203, 0x10FFFF, 1 << 32, -(1 << 20)]) {
+        let constant = search.find(n);
+        assert_eq!(constant.value, n);
+        assert_eq!(constant.instructions.len(), constant.syllables.chars().count());
+        let program = AheuiProgram::from_str(&format!("{}망희", constant.syllables));
+        assert_eq!(run_to_string(program, "").unwrap(), format!("{}\n", n), "{}", constant.syllables);
+    }
+
+    // shortest lengths, checked by hand
+    let length = |n| find_constant(n).instructions.len();
+    assert_eq!(length(0), 1);
+    assert_eq!(length(9), 1);
+    assert_eq!(length(1), 3);
+    assert_eq!(length(-1), 3);
+    assert_eq!(length(81), 3);
+    assert_eq!(length(100), 5);
+    assert_eq!(length(1331), 7);
+    assert_eq!(length(1 << 32), 9);
+
+    let constant = find_constant(10);
+    assert_eq!(constant.syllables, "박발따");
+    let operations: Vec<AheuiOperation> = constant.instructions.iter().map(|instruction| instruction.operation).collect();
+    assert_eq!(operations, [AheuiOperation::Push, AheuiOperation::Push, AheuiOperation::Multiply]);
+}
+
+#[test]
+fn test_compile_lang() {
+    use libaheui::lang::{self, AheuiLangError};
+    use libaheui::run_to_string;
+
+    let source = "\
+// fibonacci numbers below a limit
+fn show(label, value) {
+    print label;
+    print \": \";
+    print value;
+    printc '\\n';
+}
+
+limit = input;
+a = 0;
+b = 1;
+i = 0;
+while a < limit {
+    show(i, a);
+    t = a + b;
+    a = b;
+    b = t;
+    i = i + 1;
+}
+if i == 0 { print \"none\"; } else if i > 5 { print \"many\"; } else { print \"few\"; }
+";
+    let (grid, source_map) = lang::compile(source).unwrap();
+    let run = |input: &str| run_to_string(AheuiProgram::from_str(&grid), input).unwrap();
+    assert_eq!(run("10"), "0: 0\n1: 1\n2: 1\n3: 2\n4: 3\n5: 5\n6: 8\nmany\n");
+    assert_eq!(run("2"), "0: 0\n1: 1\n2: 1\nfew\n");
+    assert_eq!(run("0"), "none\n");
+
+    // every statement has cells, and cells holding instructions are mapped
+    let lines: Vec<Vec<char>> = grid.lines().map(|line| line.chars().collect()).collect();
+    for line in [3, 4, 5, 6, 9, 13, 14, 15, 18, 20] {
+        assert!(source_map.cells.iter().any(|(_, l)| *l == line), "{}", line);
+    }
+    for (position, _) in source_map.cells.iter() {
+        assert_ne!(lines[position.y][position.x], ' ');
+    }
+    let (position, _) = source_map.cells.iter().find(|(_, line)| *line == 14).unwrap();
+    assert_eq!(source_map.line(*position), Some(14));
+
+    // operators, with division rounding towards zero as in aheui
+    let (grid, _) = lang::compile("x = -7; print x / 2; print x % 3; print !x; print !0;\
+        print 3 != 3; print 3 != 4; print 2 <= 2; print 3 <= 2; print 2 >= 3;\
+        print 2 > 1; print 1 > 1; print 1 < 2; print 2 < 2; print (1 + 2) * 3 - 4 == 5;").unwrap();
+    assert_eq!(run_to_string(AheuiProgram::from_str(&grid), "").unwrap(), "-3-1010110010101\n");
+
+    assert!(lang::compile_to_assembly("x = 2; print x;").unwrap().contains("pop int              ; line 1"));
+    assert_eq!(lang::compile("print y;"), Err(AheuiLangError::UndefinedVariable(1, "y".to_string())));
+    assert_eq!(lang::compile("fn f() { f(); }\nf();"), Err(AheuiLangError::RecursiveCall(1, "f".to_string())));
+    assert_eq!(lang::compile("fn f(a) { }\nf(1, 2);"), Err(AheuiLangError::WrongArgumentCount(2, "f".to_string())));
+    assert_eq!(lang::compile("g();"), Err(AheuiLangError::UndefinedFunction(1, "g".to_string())));
+    assert_eq!(lang::compile("x = 1\nprint x;"), Err(AheuiLangError::UnexpectedToken(2, "print".to_string())));
+    assert_eq!(lang::compile("x = (1;"), Err(AheuiLangError::UnexpectedToken(1, ";".to_string())));
+}
+
+#[test]
+fn test_translate_bf() {
+    use libaheui::bf::{self, AheuiBfError};
+    use libaheui::run_to_string;
+
+    let run = |source: &str, input: &str| {
+        run_to_string(AheuiProgram::from_str(&bf::translate(source).unwrap()), input).unwrap()
+    };
+    let hello = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+    assert_eq!(run(hello, ""), "Hello World!\n\n");
+
+    // cells wrap around at 256, and input replaces the current cell
+    assert_eq!(run("-.[-]+++++++++++++++++++++++++++++++++.", ""), "ÿ!\n");
+    assert_eq!(run(",+.>,.<.", "a\nb\n"), "bbb\n");
+
+    assert_eq!(bf::translate("+[\n[]"), Err(AheuiBfError::UnclosedLoop(1, 2)));
+    assert_eq!(bf::translate("+]"), Err(AheuiBfError::UnopenedLoop(1, 2)));
+}
+
+#[test]
+fn test_transform() {
+    use libaheui::transform::{transform, AheuiTransform};
+    use libaheui::run_to_string;
+
+    let transforms = [
+        AheuiTransform::Rotate90,
+        AheuiTransform::Rotate180,
+        AheuiTransform::Rotate270,
+        AheuiTransform::MirrorHorizontal,
+        AheuiTransform::MirrorVertical,
+        AheuiTransform::Transpose,
+    ];
+    let run = |program: AheuiProgram| run_to_string(program, "").map_err(|e| e.to_string());
+    for file in ["tests/hello.ah", "tests/fibbo.ah", "tests/poem.ah"] {
+        let program = AheuiProgram::from_str(&read_program(file));
+        let expected = run(AheuiProgram::from_str(&read_program(file))).map_err(|e| e.split(" at ").next().unwrap().to_string());
+        for t in transforms {
+            assert!(t.preserves(&program));
+            let source = transform(&program, t).to_source();
+            let actual = run(AheuiProgram::from_str(&source)).map_err(|e| e.split(" at ").next().unwrap().to_string());
+            assert_eq!(actual, expected, "{} {:?}", file, t);
+        }
+    }
+
+    assert_eq!(AheuiTransform::Rotate90.vowel(HangulVowel::A), HangulVowel::U);
+    assert_eq!(AheuiTransform::Rotate270.vowel(HangulVowel::Ya), HangulVowel::Yo);
+    assert_eq!(AheuiTransform::MirrorHorizontal.vowel(HangulVowel::Eo), HangulVowel::A);
+    assert_eq!(AheuiTransform::MirrorHorizontal.vowel(HangulVowel::Eu), HangulVowel::Eu);
+    assert_eq!(AheuiTransform::Transpose.vowel(HangulVowel::Eu), HangulVowel::I);
+    assert_eq!(AheuiTransform::Rotate180.vowel(HangulVowel::Ui), HangulVowel::Ui);
+
+    // the first cell is moved back to the top left, and blank cells
+    // before it become null syllables
+    let program = AheuiProgram::from_str("아희하\n밯");
+    assert_eq!(transform(&program, AheuiTransform::Rotate180).to_source(), "어허희\n벟\n");
+    let program = AheuiProgram::from_str("아\n\n하희");
+    assert_eq!(transform(&program, AheuiTransform::Transpose).to_source(), "우 후\n애애희\n");
+
+    // blank cells ending every row are kept as null syllables, as the
+    // program would otherwise wrap around sooner and loop forever
+    let program = AheuiProgram::from_str("여 희");
+    let source = transform(&program, AheuiTransform::MirrorHorizontal).to_source();
+    assert_eq!(source, "야희애\n");
+    let (mut mirrored, _) = init_captured(&source, "");
+    for _ in 0..100 {
+        if mirrored.terminated {
+            break;
+        }
+        mirrored.step().unwrap();
+    }
+    assert!(mirrored.terminated);
+
+    // starting downwards matters when the first cell sets no direction
+    let program = AheuiProgram::from_str("애희\n아우");
+    assert!(!AheuiTransform::Rotate90.preserves(&program));
+    assert!(AheuiTransform::MirrorHorizontal.preserves(&program));
+}
+
+#[test]
+fn test_compose() {
+    use libaheui::compose::{compose, AheuiFragment, AheuiComposeError};
+    use libaheui::run_to_string;
+
+    let at = |x, y| AheuiCoordinates { x, y };
+    // prints 5 and leaves to the right, then prints 81 and terminates
+    let mut sum = AheuiFragment::new(AheuiProgram::from_str("반받다망"), at(1, 1));
+    sum.exit = Some((at(3, 0), AheuiDirection::Right(false)));
+    let product = AheuiFragment::new(AheuiProgram::from_str("밟밟따망희"), at(2, 3));
+    let program = compose(&[sum, product]).unwrap();
+    assert_eq!(program.to_source(), "아우\n애반받다망우\n애애우어어어\n애애밟밟따망희\n");
+    assert_eq!(run_to_string(program, "").unwrap(), "581\n");
+
+    // an entry which sets no direction is arrived at in the direction asked for
+    let mut sum = AheuiFragment::new(AheuiProgram::from_str("반받다망"), at(0, 0));
+    sum.exit = Some((at(3, 0), AheuiDirection::Right(false)));
+    let mut product = AheuiFragment::new(AheuiProgram::from_str("애\n밟밟따망희"), at(0, 2));
+    product.entry = (at(0, 0), AheuiDirection::Down(false));
+    let program = compose(&[sum, product]).unwrap();
+    assert_eq!(run_to_string(program, "").unwrap(), "581\n");
+
+    // a fast entry is jumped to, skipping every other cell of the column,
+    // where arriving slowly would terminate on the first 행
+    let mut column = AheuiFragment::new(AheuiProgram::from_str("밴\n행\n밷\n행\n맹\n행\n희"), at(1, 2));
+    column.entry = (at(0, 0), AheuiDirection::Down(true));
+    let program = compose(&[column]).unwrap();
+    assert_eq!(program.get_instruction(&at(1, 0)).unwrap().direction, AheuiDirection::Down(true));
+    assert_eq!(run_to_string(program, "").unwrap(), "3\n");
+
+    let fragment = |x, y| {
+        let mut fragment = AheuiFragment::new(AheuiProgram::from_str("반받다망"), at(x, y));
+        fragment.exit = Some((at(0, 0), AheuiDirection::Left(false)));
+        fragment
+    };
+    assert_eq!(compose(&[fragment(0, 0), fragment(3, 0)]).err(), Some(AheuiComposeError::Overlap(0, 1)));
+    assert_eq!(compose(&[fragment(0, 0), fragment(0, 2)]).err(), Some(AheuiComposeError::NoRoute(1)));
+    let product = AheuiFragment::new(AheuiProgram::from_str("밟밟따망희"), at(0, 0));
+    assert_eq!(compose(&[product, fragment(0, 2)]).err(), Some(AheuiComposeError::NoExit(0)));
+}
+
+#[test]
+fn test_format() {
+    use libaheui::format;
+
+    // null operations, ignored final consonants and blank cells are normalized,
+    // and padding at the end of rows is left out
+    // 밙 pushes 5 like 발
+    let source = "가\u{BC19}\u{3000}마  \n각희\r\n";
+    assert_eq!(format::format(source, false), "아발\u{3000}마\n아희\n");
+    assert_eq!(format::format(source, true), "아발\u{3000}마\n아희\u{3000}\u{3000}\n");
+    assert_eq!(format::check(source, false), vec![1, 2]);
+    assert_eq!(format::check("아발\u{3000}마\n아희", false), vec![2]);
+
+    for file in ["tests/hello.ah", "tests/fibbo.ah", "tests/poem.ah"] {
+        let formatted = format::format(&read_program(file), false);
+        assert_eq!(format::check(&formatted, false), Vec::<usize>::new());
+        assert_eq!(format::format(&formatted, true), format::format(&read_program(file), true));
+        let run = |source: &str| libaheui::run_to_string(AheuiProgram::from_str(source), "").map_err(|e| e.to_string());
+        assert_eq!(run(&formatted), run(&read_program(file)));
+    }
+}
+
+#[test]
+fn test_obfuscate() {
+    use libaheui::obfuscate::obfuscate;
+    use libaheui::run_to_string;
+
+    let decoded = |program: &AheuiProgram| -> Vec<(AheuiOperation, AheuiDirection, AheuiArgument)> {
+        program.program.iter().flatten().map(|i| (i.operation, i.direction, i.argument)).collect()
+    };
+    let program = AheuiProgram::from_str(&read_program("tests/hello.ah"));
+    let obfuscated = obfuscate(&program, 42, None);
+    assert_eq!(decoded(&obfuscated), decoded(&program));
+    assert_ne!(obfuscated.to_source(), program.to_source());
+    assert_eq!(obfuscate(&program, 42, None).to_source(), obfuscated.to_source());
+    assert_ne!(obfuscate(&program, 43, None).to_source(), obfuscated.to_source());
+    assert_eq!(run_to_string(obfuscated, "").unwrap(), "Hello, world!\n\n");
+
+    // cells which are never run are written over with the text
+    let (grid, _) = libaheui::lang::compile("x = input; while x > 0 { print x; x = x - 1; }").unwrap();
+    let program = AheuiProgram::from_str(&grid);
+    let source = obfuscate(&program, 1, Some("아희는 난해한\n언어입니다")).to_source();
+    assert!(source.contains("아희는 난해한언어입니다아희는"));
+    assert_eq!(run_to_string(AheuiProgram::from_str(&source), "3").unwrap(), "321\n");
+}
+
+#[test]
+fn test_golf() {
+    use libaheui::golf::golf;
+    use libaheui::run_to_string;
+
+    // 8 * 8 + 8 * 8 is pushed in fewer instructions, and the rows
+    // and null instructions left over are removed
+    let program = AheuiProgram::from_str("밣밣따밣밣따다망희\n\n애애애\n");
+    let golfed = golf(&program, &[]).unwrap();
+    assert_eq!(golfed.to_source(), "박밣밣따따망희\n");
+    assert_eq!(run_to_string(golfed, "").unwrap(), "128\n");
+
+    // behaviour is kept on every input given
+    let (grid, _) = libaheui::lang::compile("x = input; while x > 0 { print x; x = x - 1; }").unwrap();
+    let program = AheuiProgram::from_str(&grid);
+    let golfed = golf(&program, &["3\n", "12\n"]).unwrap();
+    assert!(golfed.size.x * golfed.size.y < program.size.x * program.size.y);
+    let source = golfed.to_source();
+    assert_eq!(run_to_string(AheuiProgram::from_str(&source), "3\n").unwrap(), "321\n");
+    assert_eq!(run_to_string(AheuiProgram::from_str(&source), "12\n").unwrap(), "121110987654321\n");
+
+    // no row or column can go, but the path along the last row is made
+    // shorter by moving the cells at its end back over those doing nothing
+    let program = AheuiProgram::from_str("반맹밴맹밴우\n우맹밴맹밴어\n아아아아망희\n");
+    let golfed = golf(&program, &[]).unwrap();
+    assert_eq!(golfed.size, program.size);
+    assert_eq!(golfed.to_source(), "반맹밴맹밴우\n우맹밴맹밴어\n망희\n");
+    assert_eq!(run_to_string(golfed, "").unwrap(), "22222\n");
+}
+
+#[test]
+fn test_reduce() {
+    use libaheui::reduce::{ddmin, reduce};
+    use libaheui::{run_within, AheuiEnding};
+
+    assert_eq!(ddmin(&[1, 2, 3, 4, 5, 6, 7, 8], |items| items.contains(&3) && items.contains(&6)), vec![3, 6]);
+
+    // the same error is kept with fewer cells
+    let program = AheuiProgram::from_str(&read_program("tests/fibbo.ah"));
+    let overflows = |program: &AheuiProgram, input: &str| matches!(
+        run_within(program.clone(), input, 100000).ending,
+        AheuiEnding::Failed(AheuiRuntimeError { error: AheuiError::ArithmeticError(..), .. }),
+    );
+    let reduction = reduce(&program, "", overflows).unwrap();
+    let count = |program: &AheuiProgram| program.program.iter().flatten().filter(|i| !i.character.is_whitespace()).count();
+    assert!(count(&reduction.program) < count(&program));
+    assert!(overflows(&reduction.program, ""));
+    assert_eq!(reduction.program.size, program.size);
+
+    // lines of input and cells are removed while a 5 is still written
+    let program = AheuiProgram::from_str("방망방망방망희");
+    let writes_5 = |program: &AheuiProgram, input: &str| run_within(program.clone(), input, 1000).output.contains('5');
+    let reduction = reduce(&program, "3\n5\n7\n", writes_5).unwrap();
+    assert_eq!(reduction.input, "5\n");
+    assert_eq!(reduction.program.to_source(), "방망애애애애애\n");
+    assert!(reduce(&program, "3\n", writes_5).is_none());
+}
+
+#[test]
+fn test_compile_c_options() {
+    use libaheui::AheuiEof;
+    use libaheui::codegen::c::{compile, CompileOptions};
+    use libaheui::encoding::AheuiEncoding;
+    use std::process::{Command, Stdio};
+
+    let dir = std::env::temp_dir().join(format!("rsaheui-c-options-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let strict = CompileOptions { strict: true, ..Default::default() };
+    let encoded = |encoding| CompileOptions { encoding, ..Default::default() };
+    let eof = CompileOptions { eof: AheuiEof::Value(-1), ..Default::default() };
+    let cases = [
+        // push 3 twice, print one and add with one left
+        ("받받망다희", "", strict),
+        ("밯맣희", "한\n", encoded(AheuiEncoding::Utf16Le)),
+        ("밯맣희", "한\n", encoded(AheuiEncoding::Utf16Be)),
+        ("밯맣희", "똠\n", encoded(AheuiEncoding::Cp949)),
+        // 똠 is not one of the syllables of EUC-KR
+        ("밯맣희", "똠\n", encoded(AheuiEncoding::EucKr)),
+        ("밯맣희", "한\n", encoded(AheuiEncoding::Byte)),
+        ("방망방망희", "7\n", eof),
+        ("방망희", "", CompileOptions::default()),
+    ];
+
+    for (i, (buffer, input, options)) in cases.into_iter().enumerate() {
+        let source_path = dir.join(format!("{}.c", i));
+        let binary_path = dir.join(i.to_string());
+        std::fs::write(&source_path, compile(&AheuiProgram::from_str(buffer), &options)).unwrap();
+
+        // skip when no C compiler is available
+        let status = match Command::new("cc").arg("-o").arg(&binary_path).arg(&source_path).status() {
+            Ok(status) => status,
+            Err(_) => return,
+        };
+        assert!(status.success(), "{}", buffer);
+
+        let mut child = Command::new(&binary_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        let compiled = child.wait_with_output().unwrap();
+        // the interpreter only prompts when reading from stdin
+        let mut compiled_output = compiled.stdout;
+        for prompt in [&b"\ninput number: "[..], &b"\ninput character: "[..]] {
+            while let Some(start) = compiled_output.windows(prompt.len()).position(|window| window == prompt) {
+                compiled_output.drain(start..start + prompt.len());
+            }
+        }
+
+        let (mut program, output) = init_captured(buffer, input);
+        if options.strict {
+            program.strict_mode();
+        }
+        program.output_encoding(options.encoding);
+        program.end_of_input(options.eof);
+        let mut error = None;
+        while !program.terminated {
+            if let Err(e) = program.step() {
+                error = Some(e.error.to_string());
+            }
+        }
+        program.flush_output();
+
+        assert_eq!(output.bytes(), compiled_output, "{}", buffer);
+        assert_eq!(compiled.status.success(), error.is_none(), "{}", buffer);
+        if let Some(error) = error {
+            assert_eq!(String::from_utf8(compiled.stderr).unwrap().trim_end(), error, "{}", buffer);
+        }
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_encode_instruction() {
+    // every syllable is one of the encodings of its instruction,
+    // and every encoding decodes to the same instruction
+    for code in 0xAC00..=0xD7A3 {
+        let c = char::from_u32(code).unwrap();
+        let instruction = AheuiInstruction::from_char(c);
+        let encodings = AheuiInstruction::encode(instruction.operation, instruction.direction, instruction.argument);
+        assert!(encodings.contains(&c), "{}", c);
+        let canonical = AheuiInstruction::from_char(encodings[0]);
+        assert_eq!(
+            (canonical.operation, canonical.direction, canonical.argument),
+            (instruction.operation, instruction.direction, instruction.argument),
+        );
+    }
+
+    let canonical = |operation, direction, argument| AheuiInstruction::encode(operation, direction, argument).first().copied();
+    assert_eq!(canonical(AheuiOperation::Push, AheuiDirection::Right(false), AheuiArgument::Number(4)), Some('밤'));
+    assert_eq!(canonical(AheuiOperation::Push, AheuiDirection::Down(false), AheuiArgument::Number(9)), Some('붋'));
+    assert_eq!(canonical(AheuiOperation::Null, AheuiDirection::Null, AheuiArgument::Null), Some('애'));
+    assert_eq!(canonical(AheuiOperation::Pop, AheuiDirection::Up(true), AheuiArgument::AsChar), Some('묳'));
+    assert_eq!(canonical(AheuiOperation::Terminate, AheuiDirection::ReflectXY, AheuiArgument::Null), Some('희'));
+    assert_eq!(canonical(AheuiOperation::StoreTransfer, AheuiDirection::Left(false), AheuiArgument::Storage(21)), Some('썽'));
+
+    // the number of syllables for an instruction
+    assert_eq!(AheuiInstruction::encode(AheuiOperation::Push, AheuiDirection::Right(false), AheuiArgument::Number(2)).len(), 3);
+    assert_eq!(AheuiInstruction::encode(AheuiOperation::Add, AheuiDirection::Right(false), AheuiArgument::Null).len(), 28);
+    assert_eq!(AheuiInstruction::encode(AheuiOperation::Null, AheuiDirection::Null, AheuiArgument::Null).len(), 5 * 10 * 28);
+
+    // arguments which do not go with the operation
+    assert!(AheuiInstruction::encode(AheuiOperation::Push, AheuiDirection::Right(false), AheuiArgument::Number(1)).is_empty());
+    assert!(AheuiInstruction::encode(AheuiOperation::Add, AheuiDirection::Right(false), AheuiArgument::Storage(3)).is_empty());
+}
+
+#[test]
+fn test_compose_jamo() {
+    // decompose every syllable into conjoining jamo, like NFD
+    let decompose = |source: &str| -> String {
+        source.chars().flat_map(|c| {
+            let code = c as u32;
+            if !(0xAC00..=0xD7A3).contains(&code) {
+                return vec![c];
+            }
+            let offset = code - 0xAC00;
+            let mut jamo = vec![
+                char::from_u32(0x1100 + offset / 588).unwrap(),
+                char::from_u32(0x1161 + offset % 588 / 28).unwrap(),
+            ];
+            if !offset.is_multiple_of(28) {
+                jamo.push(char::from_u32(0x11A7 + offset % 28).unwrap());
+            }
+            jamo
+        }).collect()
+    };
+
+    assert_eq!(compose("\u{1112}\u{1161}\u{11AB}"), "한");
+    assert_eq!(compose("하\u{11AB}\u{1112}\u{1174}"), "한희");
+    // jamo which can not be composed are kept, taking a column each
+    assert_eq!(compose("\u{1112}한\u{11AB}ㅎ"), "\u{1112}한\u{11AB}ㅎ");
+    assert!(is_jamo('\u{1112}') && is_jamo('ㅎ') && is_jamo('ㅏ') && !is_jamo('희') && !is_jamo('a'));
+
+    // a decomposed program runs the same as a precomposed one
+    let source = read_program("tests/hello.ah");
+    let decomposed = decompose(&source);
+    assert_ne!(decomposed, source);
+    let program = AheuiProgram::from_str(&decomposed);
+    let expected = AheuiProgram::from_str(&source);
+    assert_eq!(program.size, expected.size);
+    assert_eq!(libaheui::run_to_string(program, "").unwrap(), libaheui::run_to_string(expected, "").unwrap());
+}
+
+#[test]
+fn test_hangul_composition() {
+    let han = '한';
+    let han_struct = HangulSyllable {
+        onset: HangulOnset::Hieut,
+        vowel: HangulVowel::A,
+        coda: HangulCoda::Nieun,
+    };
+
+    assert_eq!(han_struct.to_char(), han);
+    assert_eq!(HangulSyllable::new(HangulOnset::Hieut, HangulVowel::A, HangulCoda::Nieun), han_struct);
+    assert_eq!(HangulSyllable::from_parts(18, 0, 4), Some(han_struct));
+    assert_eq!(HangulSyllable::from_parts(19, 0, 0), None);
+
+    // every syllable composes back into itself
+    for code in 0xAC00..=0xD7A3 {
+        let c = char::from_u32(code).unwrap();
+        assert_eq!(HangulSyllable::from_char(c).unwrap().to_char(), c);
+    }
+}
+
+#[test]
+fn test_asm_builder() {
+    use libaheui::asm::AheuiAsmBuilder;
+
+    // print 7 unless it is zero, each statement given the line it comes from
+    let mut assembly = AheuiAsmBuilder::new();
+    assembly.constant(1, 7);
+    let end = assembly.label();
+    assembly.emit(2, "dup");
+    assembly.emit(2, format!("brz {}", end));
+    assembly.emit(3, "pop int");
+    assembly.emit(4, format!("{}:", end));
+    assembly.emit(4, "halt");
+
+    assert!(assembly.source().starts_with("    push"));
+    assert!(assembly.source().ends_with("L1:\n    halt\n"));
+
+    let (grid, cells) = assembly.assemble().unwrap();
+    let program = AheuiProgram::from_str(&grid);
+    assert_eq!(libaheui::run_to_string(program, "").unwrap(), "7\n");
+    let mut lines: Vec<usize> = cells.iter().map(|(_, line)| *line).collect();
+    lines.dedup();
+    assert_eq!(lines, vec![1, 2, 3, 4]);
+}